@@ -0,0 +1,22 @@
+use std::process::Command;
+
+//GIT_HASH of the commit the binary is built from, recorded in the manifests of batch runs
+//"-dirty" is appended when tracked files differ from that commit
+fn git(args: &[&str]) -> Option<String>{
+    let output = Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main(){
+    if let Some(hash) = git(&["rev-parse", "HEAD"]).filter(|h| !h.is_empty()){
+        let dirty = git(&["status", "--porcelain", "--untracked-files=no"]).map_or(false, |s| !s.is_empty());
+        println!("cargo:rustc-env=GIT_HASH={hash}{}",if dirty {"-dirty"} else {""});
+    }
+    //logs/HEAD changes with every commit and checkout
+    if let Some(dir) = git(&["rev-parse", "--absolute-git-dir"]){
+        println!("cargo:rerun-if-changed={dir}/HEAD");
+        println!("cargo:rerun-if-changed={dir}/logs/HEAD");
+        println!("cargo:rerun-if-changed={dir}/index");
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,528 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::expr::*;
+use crate::sweep::*;
+use crate::results::*;
+use crate::batch::*;
+use crate::metrics::OutputMetric;
+use crate::architecture::*;
+use crate::ordering::*;
+use crate::geometry::*;
+
+//approximate bayesian computation with sequential Monte Carlo (Beaumont et al. 2009), see sweeps/README.md
+//a particle is accepted when the summary statistics of its replicate population are within epsilon of the observed ones,
+//epsilon of every generation is a quantile of the distances of the previous generation
+//particles are perturbed in the internal space of their priors, the logarithm for log_uniform
+//unlike sensitivity and calibration, every simulation grows new replicates so the posterior includes the growth noise
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(tag = "distribution", rename_all = "snake_case")]
+pub enum Prior{
+    Uniform{ min: f32, max: f32 },
+    LogUniform{ min: f32, max: f32 },
+    Normal{ mean: f32, sd: f32 },
+}
+
+impl Prior{
+    fn value(&self, internal: f64) -> f32{
+        match self{
+            Prior::LogUniform{..} => internal.exp() as f32,
+            _ => internal as f32,
+        }
+    }
+    //density in the internal space
+    fn density(&self, internal: f64) -> f64{
+        let uniform = |min: f64, max: f64| if internal>=min && internal<=max {1./(max-min)} else {0.};
+        match *self{
+            Prior::Uniform{ min, max } => uniform(min as f64, max as f64),
+            Prior::LogUniform{ min, max } => uniform((min as f64).ln(), (max as f64).ln()),
+            Prior::Normal{ mean, sd } => {
+                let z = (internal-mean as f64)/sd as f64;
+                (-0.5*z*z).exp()/(sd as f64*(2.*std::f64::consts::PI).sqrt())
+            }
+        }
+    }
+    fn sample(&self, rng: &mut ChaCha8Rng) -> f64{
+        match *self{
+            Prior::Uniform{ min, max } => rng.gen_range(min as f64..=max as f64),
+            Prior::LogUniform{ min, max } => rng.gen_range((min as f64).ln()..=(max as f64).ln()),
+            Prior::Normal{ mean, sd } => mean as f64+sd as f64*normal(rng),
+        }
+    }
+    fn check(&self) -> Result<(),String>{
+        match *self{
+            Prior::Uniform{ min, max } if !(min<max) => Err("min is not below max".to_string()),
+            Prior::LogUniform{ min, max } if !(min>0. && min<max) => Err("log_uniform needs 0 < min < max".to_string()),
+            Prior::Normal{ sd, .. } if !(sd>0.) => Err("sd is not positive".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn normal(rng: &mut ChaCha8Rng) -> f64{
+    let u: f64 = rng.gen::<f64>().max(f64::MIN_POSITIVE);
+    (-2.*u.ln()).sqrt()*(2.*std::f64::consts::PI*rng.gen::<f64>()).cos()
+}
+
+//value of one tree
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(tag = "statistic", rename_all = "snake_case")]
+pub enum TreeStatistic{
+    //main stem metric, see sensitivity.rs
+    Metric{ metric: OutputMetric },
+    //branches of a Gravelius order
+    Branches{ order: i32 },
+    Height,
+    ActiveBuds,
+}
+
+impl TreeStatistic{
+    fn architecture(&self) -> bool{
+        !matches!(self, TreeStatistic::Metric{..})
+    }
+    fn value(&self, tree: &Tree, architecture: Option<&TreeArchitectureStats>) -> f32{
+        match (self,architecture){
+            (TreeStatistic::Metric{ metric },_) => metric.value(tree),
+            (TreeStatistic::Branches{ order },Some(stats)) => stats.orders.get(order).map_or(0., |o| o.branches as f32),
+            (TreeStatistic::Height,Some(stats)) => stats.height,
+            (TreeStatistic::ActiveBuds,Some(stats)) => stats.active_buds as f32,
+            _ => 0.,
+        }
+    }
+}
+
+//how the replicate population is summarised
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Summary{
+    #[default]
+    Mean,
+    Sd,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct SummaryStatistic{
+    #[serde(flatten)]
+    pub statistic: TreeStatistic,
+    #[serde(default)]
+    pub summary: Summary,
+    pub observed: f32,
+    //distances are divided by it, the median absolute deviation over the first generation when missing
+    #[serde(default)]
+    pub scale: Option<f32>,
+}
+
+impl SummaryStatistic{
+    //column name, e.g. peak_ratio, branches_1 or height_sd
+    pub fn name(&self) -> String{
+        let name = match self.statistic{
+            TreeStatistic::Metric{ metric } => serde_json::to_value(metric).ok().and_then(|v| v.as_str().map(|s| s.to_string())).unwrap_or_default(),
+            TreeStatistic::Branches{ order } => format!("branches_{order}"),
+            TreeStatistic::Height => "height".to_string(),
+            TreeStatistic::ActiveBuds => "active_buds".to_string(),
+        };
+        match self.summary{
+            Summary::Mean => name,
+            Summary::Sd => format!("{name}_sd"),
+        }
+    }
+}
+
+fn default_particles() -> usize{
+    200
+}
+fn default_generations() -> usize{
+    5
+}
+fn default_quantile() -> f64{
+    0.5
+}
+fn default_credible() -> f64{
+    0.95
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct AbcConfig{
+    #[serde(default)]
+    pub name: String,
+    pub scenario: String,
+    #[serde(default)]
+    pub replicates: Option<u64>,
+    #[serde(default)]
+    pub precision: Option<f32>,
+    #[serde(default = "default_particles")]
+    pub particles: usize,
+    #[serde(default = "default_generations")]
+    pub generations: usize,
+    //quantile of the distances of a generation used as the epsilon of the next one
+    #[serde(default = "default_quantile")]
+    pub quantile: f64,
+    //simulations and proposals outside the prior of a generation after which it stops with the particles accepted so far,
+    //20 * particles by default
+    #[serde(default)]
+    pub max_simulations: Option<usize>,
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_credible")]
+    pub credible: f64,
+    pub priors: BTreeMap<String,Prior>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    #[serde(default)]
+    pub derived: BTreeMap<String,Expr>,
+    pub statistics: Vec<SummaryStatistic>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Particle{
+    pub values: BTreeMap<String,f32>,
+    pub weight: f64,
+    pub distance: f64,
+    pub statistics: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Generation{
+    pub epsilon: f64,
+    pub simulations: usize,
+    //proposals outside the prior, not simulated
+    #[serde(default)]
+    pub rejected: usize,
+    pub accepted: usize,
+}
+
+//weighted mean, median and central credible interval of a parameter
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct PosteriorSummary{
+    pub parameter: String,
+    pub mean: f32,
+    pub median: f32,
+    pub low: f32,
+    pub high: f32,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct AbcResult{
+    pub config: AbcConfig,
+    pub scales: Vec<f32>,
+    pub generations: Vec<Generation>,
+    pub posterior: Vec<PosteriorSummary>,
+}
+
+impl AbcConfig{
+    pub fn load(path: &std::path::Path) -> Result<AbcConfig,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let config: Result<AbcConfig,String> = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        config.and_then(|c| c.check().map(|_| c)).map_err(|e| format!("{}: {e}",path.display()))
+    }
+    fn check(&self) -> Result<(),String>{
+        if self.priors.is_empty(){
+            return Err("no priors".to_string());
+        }
+        if self.statistics.is_empty(){
+            return Err("no statistics".to_string());
+        }
+        if self.particles<2 || self.generations==0{
+            return Err("abc needs at least 2 particles and 1 generation".to_string());
+        }
+        if !(self.quantile>0. && self.quantile<1.){
+            return Err("quantile is not between 0 and 1".to_string());
+        }
+        check_names(self.priors.keys().chain(self.parameters.keys()), &self.derived)?;
+        for (name,prior) in &self.priors{
+            if self.parameters.contains_key(name){
+                return Err(format!("{name} has a prior and a fixed value"));
+            }
+            prior.check().map_err(|e| format!("{name}: {e}"))?;
+        }
+        Ok(())
+    }
+
+    fn point(&self, base: &Settings, internal: &[f64]) -> Result<SweepPoint,String>{
+        let mut values = self.parameters.clone();
+        values.extend(self.priors.iter().zip(internal).map(|((name,prior),&z)| (name.clone(),prior.value(z))));
+        SweepPoint::new(base, &values, &self.derived)
+    }
+
+    fn prior_density(&self, internal: &[f64]) -> f64{
+        self.priors.values().zip(internal).map(|(prior,&z)| prior.density(z)).product()
+    }
+
+    //summary statistics of a new replicate population, the seed picks the replicate seeds
+    fn simulate(&self, base: &Settings, scenario: &Scenario, options: &RunOptions, internal: &[f64], seed: u64) -> Result<Vec<f32>,String>{
+        let point = self.point(base, internal)?;
+        let options = RunOptions { master_seed: seed, ..options.clone() };
+        let (trees,_) = simulate_replicates(&point.settings, scenario, &options)?;
+        let architecture: Vec<Option<TreeArchitectureStats>> = trees.iter().map(|tree|{
+            self.statistics.iter().any(|s| s.statistic.architecture())
+                .then(|| TreeArchitectureStats::new(tree, OrderingScheme::Gravelius, &GeometryParams::default()))
+        }).collect();
+        Ok(self.statistics.iter().map(|s|{
+            let values: Vec<f32> = trees.iter().zip(&architecture).map(|(tree,a)| s.statistic.value(tree, a.as_ref())).collect();
+            let mean = values.iter().sum::<f32>()/values.len().max(1) as f32;
+            match s.summary{
+                Summary::Mean => mean,
+                Summary::Sd => (values.iter().map(|v| (v-mean)*(v-mean)).sum::<f32>()/(values.len().max(2)-1) as f32).sqrt(),
+            }
+        }).collect())
+    }
+}
+
+fn distance(statistics: &[f32], config: &AbcConfig, scales: &[f32]) -> f64{
+    statistics.iter().zip(&config.statistics).zip(scales)
+        .map(|((s,o),scale)| ((s-o.observed) as f64/scale.max(f32::EPSILON) as f64).powi(2)).sum::<f64>().sqrt()
+}
+
+fn weighted_quantile(values: &[(f64,f64)], q: f64) -> f64{
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a,b| a.0.total_cmp(&b.0));
+    let total: f64 = sorted.iter().map(|v| v.1).sum();
+    let mut cumulative = 0.;
+    for (value,weight) in &sorted{
+        cumulative += weight;
+        if cumulative>=q*total{
+            return *value;
+        }
+    }
+    sorted.last().map_or(f64::NAN, |v| v.0)
+}
+
+//internal values, weight, distance and statistics of a particle
+type Weighted = (Vec<f64>,f64,f64,Vec<f32>);
+
+//variance of the gaussian perturbation kernel, twice the weighted variance of the particles
+fn kernel_variance(particles: &[Weighted], k: usize) -> Vec<f64>{
+    (0..k).map(|j|{
+        let mean: f64 = particles.iter().map(|p| p.1*p.0[j]).sum();
+        2.*particles.iter().map(|p| p.1*(p.0[j]-mean).powi(2)).sum::<f64>()
+    }).collect()
+}
+
+fn kernel(variance: &[f64], from: &[f64], to: &[f64]) -> f64{
+    variance.iter().enumerate().map(|(j,&v)| if v>0. {(-0.5*(to[j]-from[j]).powi(2)/v).exp()/v.sqrt()} else {1.}).product()
+}
+
+//importance weight of an accepted candidate, normalised later over the generation
+fn candidate_weight(config: &AbcConfig, particles: &[Weighted], variance: &[f64], candidate: &[f64]) -> f64{
+    let denominator: f64 = particles.iter().map(|p| p.1*kernel(variance, &p.0, candidate)).sum();
+    config.prior_density(candidate)/denominator.max(f64::MIN_POSITIVE)
+}
+
+//perturbed particles inside the prior, at most wanted of them and at most budget proposals,
+//returns the candidates and the number of proposals outside the prior
+fn propose(config: &AbcConfig, particles: &[Weighted], variance: &[f64], rng: &mut ChaCha8Rng, wanted: usize, budget: usize) -> (Vec<Vec<f64>>,usize){
+    let mut candidates = vec![];
+    let mut rejected = 0;
+    while candidates.len()<wanted && candidates.len()+rejected<budget{
+        let r = rng.gen::<f64>();
+        let index = particles.iter().scan(0., |s,p| {*s += p.1; Some(*s)}).position(|s| s>=r).unwrap_or(particles.len()-1);
+        let candidate: Vec<f64> = variance.iter().enumerate().map(|(j,v)| particles[index].0[j]+v.sqrt()*normal(rng)).collect();
+        if config.prior_density(&candidate)>0.{
+            candidates.push(candidate);
+        } else {
+            rejected += 1;
+        }
+    }
+    (candidates,rejected)
+}
+
+//posterior particles of the last generation, also written with the summaries to {output}/abc_{name}
+pub fn run_abc(config: &AbcConfig, registry: &ScenarioRegistry, base: &Settings, options: &RunOptions, threads: Option<usize>) -> Result<(AbcResult,Vec<Particle>),String>{
+    let scenario = registry.get(&config.scenario).ok_or(format!("unknown scenario {}",config.scenario))?;
+    let pool = thread_pool(threads)?;
+    let mut rng = ChaCha8Rng::seed_from_u64(config.seed);
+    let k = config.priors.len();
+    let priors: Vec<Prior> = config.priors.values().copied().collect();
+    let max_simulations = config.max_simulations.unwrap_or(20*config.particles);
+
+    //every candidate has its own replicate seeds so candidates are independent populations
+    let run = |candidates: &[Vec<f64>], rng: &mut ChaCha8Rng| -> Result<Vec<Vec<f32>>,String>{
+        let seeds: Vec<u64> = candidates.iter().map(|_| rng.gen()).collect();
+        pool.install(|| candidates.par_iter().zip(seeds).map(|(c,seed)| config.simulate(base, scenario, options, c, seed)).collect())
+    };
+
+    //generation 0 from the prior, which also gives the missing scales
+    let candidates: Vec<Vec<f64>> = (0..config.particles).map(|_| priors.iter().map(|p| p.sample(&mut rng)).collect()).collect();
+    let statistics = run(&candidates, &mut rng)?;
+    let scales: Vec<f32> = config.statistics.iter().enumerate().map(|(i,s)| s.scale.unwrap_or_else(||{
+        let values: Vec<(f64,f64)> = statistics.iter().map(|st| (st[i] as f64,1.)).collect();
+        let median = weighted_quantile(&values, 0.5);
+        let deviations: Vec<(f64,f64)> = values.iter().map(|v| ((v.0-median).abs(),1.)).collect();
+        let mad = weighted_quantile(&deviations, 0.5) as f32;
+        if mad>0. {mad} else {1.}
+    })).collect();
+    let mut particles: Vec<Weighted> = candidates.into_iter().zip(statistics).map(|(c,s)|{
+        let d = distance(&s, config, &scales);
+        (c,1./config.particles as f64,d,s)
+    }).collect();
+    let mut generations = vec![Generation { epsilon: f64::INFINITY, simulations: config.particles, rejected: 0, accepted: config.particles }];
+    println!("generation 0: {} particles from the prior",config.particles);
+
+    for generation in 1..config.generations{
+        let distances: Vec<(f64,f64)> = particles.iter().map(|p| (p.2,1.)).collect();
+        let epsilon = weighted_quantile(&distances, config.quantile);
+        let variance = kernel_variance(&particles, k);
+        let mut accepted = vec![];
+        let mut simulations = 0;
+        let mut rejected = 0;
+        while accepted.len()<config.particles && simulations+rejected<max_simulations{
+            let (candidates,outside) = propose(config, &particles, &variance, &mut rng, config.particles-accepted.len(), max_simulations-simulations-rejected);
+            rejected += outside;
+            if candidates.is_empty(){
+                break;
+            }
+            let statistics = run(&candidates, &mut rng)?;
+            simulations += candidates.len();
+            for (candidate,s) in candidates.into_iter().zip(statistics){
+                let d = distance(&s, config, &scales);
+                if d<=epsilon && accepted.len()<config.particles{
+                    let weight = candidate_weight(config, &particles, &variance, &candidate);
+                    accepted.push((candidate,weight,d,s));
+                }
+            }
+        }
+        println!("generation {generation}: epsilon {epsilon:.4}, {} of {simulations} accepted, {rejected} proposals outside the prior",accepted.len());
+        generations.push(Generation { epsilon, simulations, rejected, accepted: accepted.len() });
+        if accepted.len()<2{
+            println!("too few particles accepted, the previous generation is kept");
+            break;
+        }
+        let total: f64 = accepted.iter().map(|p| p.1).sum();
+        for particle in accepted.iter_mut(){
+            particle.1 /= total;
+        }
+        particles = accepted;
+    }
+
+    let posterior: Vec<PosteriorSummary> = config.priors.iter().enumerate().map(|(j,(name,prior))|{
+        let values: Vec<(f64,f64)> = particles.iter().map(|p| (prior.value(p.0[j]) as f64,p.1)).collect();
+        let alpha = (1.-config.credible)/2.;
+        PosteriorSummary {
+            parameter: name.clone(),
+            mean: values.iter().map(|(v,w)| v*w).sum::<f64>() as f32,
+            median: weighted_quantile(&values, 0.5) as f32,
+            low: weighted_quantile(&values, alpha) as f32,
+            high: weighted_quantile(&values, 1.-alpha) as f32,
+        }
+    }).collect();
+    let particles: Vec<Particle> = particles.into_iter().map(|(internal,weight,distance,statistics)|{
+        let values = config.priors.iter().zip(&internal).map(|((name,prior),&z)| (name.clone(),prior.value(z))).collect();
+        Particle { values, weight, distance, statistics }
+    }).collect();
+    let result = AbcResult { config: config.clone(), scales, generations, posterior };
+
+    let dir = options.output.join(format!("abc_{}",config.name));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+    write_particles(&dir.join("posterior.csv"), config, &particles)?;
+    let path = dir.join("abc.json");
+    std::fs::write(&path, serde_json::to_string_pretty(&result).map_err(|e| e.to_string())?).map_err(|e| format!("{}: {e}",path.display()))?;
+    Ok((result,particles))
+}
+
+fn write_particles(path: &PathBuf, config: &AbcConfig, particles: &[Particle]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    let statistics: Vec<String> = config.statistics.iter().map(|s| s.name()).collect();
+    writer.write_record(["weight","distance"].into_iter().chain(config.priors.keys().map(|n| n.as_str())).chain(statistics.iter().map(|s| s.as_str()))).map_err(error)?;
+    for particle in particles{
+        writer.write_record([particle.weight.to_string(),particle.distance.to_string()].into_iter()
+            .chain(particle.values.values().map(|v| v.to_string()))
+            .chain(particle.statistics.iter().map(|v| v.to_string()))).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn config(prior: Prior) -> AbcConfig{
+        let mut config: AbcConfig = toml::from_str("scenario = \"WT\"\n[priors]\n[[statistics]]\nstatistic = \"height\"\nobserved = 1.0\n").unwrap();
+        config.priors.insert("decay".to_string(), prior);
+        config.check().unwrap();
+        config
+    }
+
+    #[test]
+    fn weighted_quantiles(){
+        let values = [(3.,1.),(1.,1.),(2.,2.)];
+        assert_eq!(weighted_quantile(&values, 0.25),1.);
+        assert_eq!(weighted_quantile(&values, 0.5),2.);
+        assert_eq!(weighted_quantile(&values, 0.75),2.);
+        assert_eq!(weighted_quantile(&values, 1.),3.);
+        assert!(weighted_quantile(&[], 0.5).is_nan());
+    }
+
+    #[test]
+    fn prior_densities(){
+        let uniform = Prior::Uniform{ min: 0., max: 2. };
+        assert_eq!(uniform.density(1.),0.5);
+        assert_eq!(uniform.density(2.5),0.);
+        //uniform in the logarithm, the internal value of 3 is ln 3
+        let log_uniform = Prior::LogUniform{ min: 1., max: std::f32::consts::E.powi(2) };
+        assert!((log_uniform.density(1.)-0.5).abs()<1e-6);
+        assert_eq!(log_uniform.density(-0.5),0.);
+        assert!((log_uniform.value(3f64.ln())-3.).abs()<1e-6);
+        let normal = Prior::Normal{ mean: 1., sd: 2. };
+        assert!((normal.density(1.)-1./(2.*(2.*std::f64::consts::PI).sqrt())).abs()<1e-12);
+        assert!((normal.density(3.)/normal.density(1.)-(-0.5f64).exp()).abs()<1e-12);
+        assert!(Prior::LogUniform{ min: 0., max: 1. }.check().is_err());
+        assert!(Prior::Uniform{ min: 1., max: 1. }.check().is_err());
+        assert!(Prior::Normal{ mean: 0., sd: 0. }.check().is_err());
+    }
+
+    #[test]
+    fn prior_samples(){
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let n = 4000;
+        let log_uniform = Prior::LogUniform{ min: 1., max: 100. };
+        let samples: Vec<f64> = (0..n).map(|_| log_uniform.sample(&mut rng)).collect();
+        assert!(samples.iter().all(|&z| log_uniform.density(z)>0.));
+        //half of the samples below the geometric mean 10
+        let below = samples.iter().filter(|&&z| log_uniform.value(z)<10.).count() as f64/n as f64;
+        assert!((below-0.5).abs()<0.05,"{below}");
+
+        let normal = Prior::Normal{ mean: 1., sd: 2. };
+        let samples: Vec<f64> = (0..n).map(|_| normal.sample(&mut rng)).collect();
+        let mean = samples.iter().sum::<f64>()/n as f64;
+        let sd = (samples.iter().map(|z| (z-mean).powi(2)).sum::<f64>()/(n-1) as f64).sqrt();
+        assert!((mean-1.).abs()<0.1 && (sd-2.).abs()<0.1,"{mean} {sd}");
+    }
+
+    #[test]
+    fn kernel_and_weights(){
+        let config = config(Prior::Uniform{ min: -5., max: 5. });
+        let particles: Vec<Weighted> = vec![(vec![0.],0.5,0.,vec![]),(vec![2.],0.5,0.,vec![])];
+        //twice the weighted variance of 0 and 2
+        let variance = kernel_variance(&particles, 1);
+        assert_eq!(variance,vec![2.]);
+        assert!((kernel(&variance, &[0.], &[0.])-1./2f64.sqrt()).abs()<1e-12);
+        //prior density 0.1 over the kernel mixture at 1, where both particles are one unit away
+        let expected = 0.1/((-0.25f64).exp()/2f64.sqrt());
+        assert!((candidate_weight(&config, &particles, &variance, &[1.])-expected).abs()<1e-9);
+        assert_eq!(candidate_weight(&config, &particles, &variance, &[6.]),0.);
+    }
+
+    #[test]
+    fn proposals_are_bounded(){
+        let config = config(Prior::Uniform{ min: 0., max: 1. });
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let inside: Vec<Weighted> = vec![(vec![0.5],1.,0.,vec![])];
+        let (candidates,rejected) = propose(&config, &inside, &[1e-6], &mut rng, 10, 50);
+        assert_eq!((candidates.len(),rejected),(10,0));
+        //every perturbation of a particle far outside the prior is rejected, the budget ends the loop
+        let outside: Vec<Weighted> = vec![(vec![5.],1.,0.,vec![])];
+        let (candidates,rejected) = propose(&config, &outside, &[1e-6], &mut rng, 10, 50);
+        assert_eq!((candidates.len(),rejected),(0,50));
+    }
+}
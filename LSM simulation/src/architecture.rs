@@ -0,0 +1,203 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use three_d::*;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::ordering::*;
+use crate::geometry::*;
+
+//traits of all branches of one order, lengths are in geometry units and angles in degrees
+#[derive(Serialize, Deserialize,Clone,Debug,Default)]
+pub struct OrderStats{
+    pub branches: usize,
+    pub total_length: f32,
+    pub mean_length: f32,
+    pub internodes: usize,
+    pub mean_branching_angle: f32,
+}
+
+//architecture traits comparable with the field scanning sheets (#1branches, Ave1Len, Ave1Angle, ...)
+//a branch is a chain of grown nodes with the same order, so for Gravelius order it is a whole axis
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct TreeArchitectureStats{
+    pub seed: u64,
+    pub scheme: OrderingScheme,
+    //order 0 is the main stem
+    pub orders: BTreeMap<i32,OrderStats>,
+    //lateral branches, all orders above 0
+    pub branches: usize,
+    pub total_length: f32,
+    pub mean_length: f32,
+    pub height: f32,
+    pub active_buds: usize,
+    pub dormant_buds: usize,
+    pub decapitated_tips: usize,
+}
+
+//child continuing the branch of the node, main child is preferred
+fn continuation(tree: &Tree, orders: &NodeOrders, id: NodeId) -> Option<NodeId>{
+    tree[id].children()
+        .filter(|&c| tree[c].bud_state!=BudState::DormantBud)
+        .find(|&c| orders.order(c)==orders.order(id))
+}
+
+//direction of the last compartment with length below the node, measured trees have zero length junction compartments
+fn incoming_direction(tree: &Tree, geometry: &TreeGeometry, id: NodeId) -> Option<Vec3>{
+    tree.path_to_root(id).flat_map(|n| geometry.node(n).iter().rev()).find(|c| c.length()>0.).map(|c| c.direction())
+}
+fn outgoing_direction(tree: &Tree, geometry: &TreeGeometry, id: NodeId) -> Option<Vec3>{
+    tree.axis(id).flat_map(|n| geometry.node(n).iter()).find(|c| c.length()>0.).map(|c| c.direction())
+}
+
+fn angle_degrees(a: Vec3, b: Vec3) -> f32{
+    a.dot(b).clamp(-1., 1.).acos().to_degrees()
+}
+
+impl TreeArchitectureStats{
+    pub fn new(tree: &Tree, scheme: OrderingScheme, params: &GeometryParams) -> TreeArchitectureStats{
+        let orders = tree.orders(scheme);
+        let geometry = TreeGeometry::new(tree, params);
+        let node_length = |id: NodeId| geometry.node(id).iter().map(|c| c.length()).sum::<f32>();
+
+        let mut per_order: BTreeMap<i32,OrderStats> = BTreeMap::new();
+        let mut angles: BTreeMap<i32,Vec<f32>> = BTreeMap::new();
+        for id in tree.preorder(){
+            let node = &tree[id];
+            if node.bud_state==BudState::DormantBud{
+                continue;
+            }
+            let order = orders.order(id);
+            let stats = per_order.entry(order).or_default();
+            stats.internodes+=1;
+            stats.total_length+=node_length(id);
+            let is_base = match node.parent{
+                None => true,
+                Some(parent) => continuation(tree, &orders, parent)!=Some(id),
+            };
+            if is_base{
+                stats.branches+=1;
+                if let Some(parent) = node.parent{
+                    let parent_direction = incoming_direction(tree, &geometry, parent);
+                    let direction = outgoing_direction(tree, &geometry, id);
+                    if let (Some(parent_direction),Some(direction)) = (parent_direction,direction){
+                        angles.entry(order).or_default().push(angle_degrees(parent_direction, direction));
+                    }
+                }
+            }
+        }
+        for (order,stats) in per_order.iter_mut(){
+            if stats.branches>0{
+                stats.mean_length=stats.total_length/stats.branches as f32;
+            }
+            if let Some(angles) = angles.get(order){
+                stats.mean_branching_angle=angles.iter().sum::<f32>()/angles.len() as f32;
+            }
+        }
+
+        let laterals = per_order.iter().filter(|(order,_)| **order>0).map(|(_,stats)| stats);
+        let (branches,total_length) = laterals.fold((0,0.),|(b,l),stats| (b+stats.branches,l+stats.total_length));
+        let base = geometry.base(tree.root()).y;
+        let height = geometry.compartments().filter(|c| tree[c.compartment.node].bud_state!=BudState::DormantBud).map(|c| c.end.y.max(c.start.y)-base).fold(0.,f32::max);
+        let count = |state: BudState| tree.nodes.iter().filter(|n| n.bud_state==state).count();
+
+        TreeArchitectureStats {
+            seed: tree.seed,
+            scheme,
+            orders: per_order,
+            branches,
+            total_length,
+            mean_length: if branches>0 {total_length/branches as f32} else {0.},
+            height,
+            active_buds: count(BudState::ActiveBud),
+            dormant_buds: count(BudState::DormantBud),
+            decapitated_tips: count(BudState::DecapitatedSegment),
+        }
+    }
+
+    pub fn max_order(&self) -> i32{
+        self.orders.keys().copied().max().unwrap_or(0)
+    }
+    pub fn order(&self, order: i32) -> OrderStats{
+        self.orders.get(&order).cloned().unwrap_or_default()
+    }
+}
+
+//one row per tree with the column names of the field sheets, orders missing on a tree are written as 0
+pub fn write_architecture_table(path: &Path, rows: &[(String,TreeArchitectureStats)]) -> Result<(),String>{
+    let max_order = rows.iter().map(|(_,stats)| stats.max_order()).max().unwrap_or(0).max(1);
+    let mut header: Vec<String> = ["Genotype","Seed","Height","TotalBranches","TotalLen","AveLen","StemLen","StemInternodes","ActiveBuds","DormantBuds","DecapitatedTips"]
+        .iter().map(|s| s.to_string()).collect();
+    for order in 1..=max_order{
+        header.extend([format!("{order}branches"),format!("Ave{order}Len"),format!("Ave{order}Angle"),format!("{order}internodes")]);
+    }
+    let mut writer = csv::Writer::from_path(path).map_err(|e| e.to_string())?;
+    writer.write_record(&header).map_err(|e| e.to_string())?;
+    for (name,stats) in rows{
+        let stem = stats.order(0);
+        let mut record = vec![
+            name.clone(),
+            stats.seed.to_string(),
+            format!("{:.3}",stats.height),
+            stats.branches.to_string(),
+            format!("{:.3}",stats.total_length),
+            format!("{:.3}",stats.mean_length),
+            format!("{:.3}",stem.total_length),
+            stem.internodes.to_string(),
+            stats.active_buds.to_string(),
+            stats.dormant_buds.to_string(),
+            stats.decapitated_tips.to_string(),
+        ];
+        for order in 1..=max_order{
+            let order_stats = stats.order(order);
+            record.extend([
+                order_stats.branches.to_string(),
+                format!("{:.3}",order_stats.mean_length),
+                format!("{:.3}",order_stats.mean_branching_angle),
+                order_stats.internodes.to_string(),
+            ]);
+        }
+        writer.write_record(&record).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::topology::tests::small;
+
+    //node numbers as drawn in the topology tests
+    #[test]
+    fn branch_counts(){
+        let tree = small();
+        let stats = TreeArchitectureStats::new(&tree, OrderingScheme::Gravelius, &GeometryParams::default());
+        //the dormant bud 6 is not a branch of order 2
+        assert_eq!(stats.max_order(),1);
+        assert_eq!((stats.order(0).branches,stats.order(0).internodes),(1,3));
+        assert_eq!((stats.order(1).branches,stats.order(1).internodes),(2,3));
+        assert_eq!(stats.branches,2);
+        assert_eq!((stats.active_buds,stats.dormant_buds,stats.decapitated_tips),(3,1,0));
+        //4 and 5 are one branch, 3 and 4 are two under Strahler order 1
+        let strahler = TreeArchitectureStats::new(&tree, OrderingScheme::Strahler, &GeometryParams::default());
+        assert_eq!((strahler.order(2).branches,strahler.order(2).internodes),(1,2));
+        assert_eq!((strahler.order(1).branches,strahler.order(1).internodes),(3,4));
+    }
+
+    #[test]
+    fn field_sheet_columns(){
+        let stats = TreeArchitectureStats::new(&small(), OrderingScheme::Gravelius, &GeometryParams::default());
+        let path = std::env::temp_dir().join(format!("tree_d_architecture_{}.csv",std::process::id()));
+        write_architecture_table(&path, &[("WT".to_string(),stats)]).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split(',').collect()).collect();
+        assert_eq!(lines[0],["Genotype","Seed","Height","TotalBranches","TotalLen","AveLen","StemLen","StemInternodes","ActiveBuds","DormantBuds","DecapitatedTips",
+            "1branches","Ave1Len","Ave1Angle","1internodes"]);
+        assert_eq!(lines.len(),2);
+        let row: BTreeMap<&str,&str> = lines[0].iter().copied().zip(lines[1].iter().copied()).collect();
+        assert_eq!((row["Genotype"],row["TotalBranches"],row["StemInternodes"],row["1branches"],row["1internodes"]),("WT","2","3","2","3"));
+        assert_eq!((row["ActiveBuds"],row["DormantBuds"],row["DecapitatedTips"]),("3","1","0"));
+    }
+}
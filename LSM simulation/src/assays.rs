@@ -0,0 +1,460 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use calamine::{open_workbook_auto, Reader};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::expr::*;
+use crate::sweep::*;
+use crate::results::*;
+use crate::batch::*;
+use crate::metrics::*;
+
+//comparison of simulated main stem profiles with internode-sampled assays (stem PAT, IAA and sucrose profiling), see sweeps/README.md
+//rows are grouped by genotype and treatment as the facets of the R scripts, and every group gets the pearson correlation of
+//the value with the internode as stat_cor, for the measurements and for the replicates simulated at the measured internodes
+//internodes are counted from the tip as in Tree::main_stem_internodes and the "save result" CSV
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Assay{
+    //polar auxin transport, CPM of transported labelled auxin
+    Pat,
+    //IAA, pmol/g FW
+    Iaa,
+    //sucrose, umol/g FW
+    Sucrose,
+}
+
+impl Assay{
+    //value columns of the lab sheets, the first one present is used
+    fn columns(&self) -> &'static [&'static str]{
+        match self{
+            Assay::Pat => &["CPM","CPM1"],
+            Assay::Iaa => &["IAA"],
+            Assay::Sucrose => &["sucrose"],
+        }
+    }
+    //transport is compared with PIN, sucrose has no simulated counterpart and is compared with the auxin gradient
+    fn quantity(&self) -> Quantity{
+        match self{
+            Assay::Pat => Quantity::Pin,
+            Assay::Iaa | Assay::Sucrose => Quantity::Auxin,
+        }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct AssayRow{
+    pub genotype: String,
+    //empty without a treatment column
+    pub treatment: String,
+    pub internode: i32,
+    pub value: f32,
+}
+
+//"int3" or 3
+fn parse_internode(text: &str) -> Option<i32>{
+    let text = text.trim();
+    let digits = text.strip_prefix("int").or(text.strip_prefix("Int")).unwrap_or(text);
+    digits.parse().ok()
+}
+
+//cells of the CSV, sheets saved with a comma decimal separator use semicolons between fields
+fn read_csv(path: &Path) -> Result<Vec<Vec<String>>,String>{
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let header = text.lines().next().unwrap_or_default();
+    let delimiter = if header.contains(';') && !header.contains(',') {b';'} else {b','};
+    let mut reader = csv::ReaderBuilder::new().delimiter(delimiter).trim(csv::Trim::All).flexible(true).has_headers(false).from_reader(text.as_bytes());
+    reader.records().map(|record| record.map(|r| r.iter().map(|f| f.to_string()).collect()).map_err(|e| e.to_string())).collect()
+}
+
+//cells of the named sheet of an xlsx, xlsm, xls or ods workbook, the first sheet when None
+fn read_workbook(path: &Path, sheet: Option<&str>) -> Result<Vec<Vec<String>>,String>{
+    let mut workbook = open_workbook_auto(path).map_err(|e| e.to_string())?;
+    let name = match sheet{
+        Some(name) => name.to_string(),
+        None => workbook.sheet_names().first().cloned().ok_or("no sheets")?,
+    };
+    let range = workbook.worksheet_range(&name).map_err(|e| format!("sheet {name}: {e}"))?;
+    Ok(range.rows().map(|row| row.iter().map(|cell| cell.to_string().trim().to_string()).collect()).collect())
+}
+
+//rows of Genotype, internode, an optional treatment and the value column of a lab sheet, as CSV or workbook
+//rows without an internode number (e.g. negative controls) or a numeric value are skipped as in the R scripts, and counted
+pub fn load_assay(path: &Path, assay: Assay, value: Option<&str>, sheet: Option<&str>) -> Result<(Vec<AssayRow>,usize),String>{
+    let error = |e: String| format!("{}: {e}",path.display());
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    let cells = match extension.as_str(){
+        "xlsx" | "xlsm" | "xlsb" | "xls" | "ods" => read_workbook(path, sheet),
+        _ => read_csv(path),
+    }.map_err(error)?;
+    let mut cells = cells.into_iter().filter(|row| row.iter().any(|c| !c.is_empty()));
+    let headers = cells.next().ok_or(error("empty table".to_string()))?;
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let genotype = column("genotype").ok_or(error("no Genotype column".to_string()))?;
+    let internode = column("internode").ok_or(error("no internode column".to_string()))?;
+    let treatment = column("treatment");
+    let value = match value{
+        Some(name) => column(name).ok_or(error(format!("no {name} column")))?,
+        None => assay.columns().iter().find_map(|name| column(name)).ok_or(error(format!("no {} column",assay.columns().join(" or "))))?,
+    };
+    let (mut rows,mut skipped) = (vec![],0);
+    for record in cells{
+        let field = |i: usize| record.get(i).map_or("", |f| f.as_str());
+        let parsed = parse_internode(field(internode)).zip(field(value).replace(',', ".").parse::<f32>().ok().filter(|v| v.is_finite()));
+        match parsed{
+            Some((internode,value)) => rows.push(AssayRow {
+                genotype: field(genotype).to_string(),
+                treatment: treatment.map_or(String::new(), |i| field(i).to_string()),
+                internode,
+                value,
+            }),
+            None => skipped += 1,
+        }
+    }
+    if rows.is_empty(){
+        return Err(error("no measurements".to_string()));
+    }
+    Ok((rows,skipped))
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct AssayConfig{
+    #[serde(default)]
+    pub name: String,
+    //measurement table, relative to the configuration file
+    pub data: PathBuf,
+    pub assay: Assay,
+    //sheet of a workbook, the first one otherwise
+    #[serde(default)]
+    pub sheet: Option<String>,
+    //value column, the column of the assay otherwise
+    #[serde(default)]
+    pub value: Option<String>,
+    //simulated quantity compared with the values, pin for pat and auxin otherwise
+    #[serde(default)]
+    pub quantity: Option<Quantity>,
+    //scenario of every group, "genotype" or "genotype treatment", otherwise the scenario named as the genotype
+    //(or genotype_decapitated for decap treatments) ignoring case, groups without a scenario are compared without simulation
+    #[serde(default)]
+    pub scenarios: BTreeMap<String,String>,
+    #[serde(default)]
+    pub replicates: Option<u64>,
+    #[serde(default)]
+    pub master_seed: Option<u64>,
+    #[serde(default)]
+    pub precision: Option<f32>,
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    #[serde(default)]
+    pub derived: BTreeMap<String,Expr>,
+}
+
+impl AssayConfig{
+    pub fn load(path: &Path) -> Result<AssayConfig,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let config: Result<AssayConfig,String> = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        let mut config = config.and_then(|c| check_names(c.parameters.keys(), &c.derived).map(|_| c)).map_err(|e| format!("{}: {e}",path.display()))?;
+        if config.data.is_relative(){
+            config.data = path.parent().unwrap_or(Path::new(".")).join(&config.data);
+        }
+        Ok(config)
+    }
+
+    pub fn quantity(&self) -> Quantity{
+        self.quantity.unwrap_or(self.assay.quantity())
+    }
+
+    fn scenario(&self, genotype: &str, treatment: &str, registry: &ScenarioRegistry) -> Result<Option<Scenario>,String>{
+        let label = group_label(genotype, treatment);
+        if let Some(name) = self.scenarios.get(&label).or(self.scenarios.get(genotype)){
+            return registry.get(name).cloned().map(Some).ok_or(format!("unknown scenario {name} of {label}"));
+        }
+        let name = match treatment.to_lowercase(){
+            t if t.starts_with("decap") => format!("{genotype}_decapitated"),
+            t if t.is_empty() || t=="intact" => genotype.to_string(),
+            _ => return Ok(None),
+        };
+        Ok(registry.names().into_iter().find(|n| n.eq_ignore_ascii_case(&name)).and_then(|n| registry.get(&n).cloned()))
+    }
+}
+
+fn group_label(genotype: &str, treatment: &str) -> String{
+    if treatment.is_empty() {genotype.to_string()} else {format!("{genotype} {treatment}")}
+}
+
+//one facet of the assay, values are in measured units and scaled simulated values in the same units
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct GroupComparison{
+    pub genotype: String,
+    pub treatment: String,
+    pub scenario: Option<String>,
+    //measurements and measured internodes
+    pub n: usize,
+    pub internodes: usize,
+    //correlation with the internode (stat_cor) and least squares slope per internode
+    pub measured_r: Option<f32>,
+    pub measured_p: Option<f32>,
+    pub measured_slope: Option<f32>,
+    //the same over the simulated replicates at the measured internodes
+    pub simulated_r: Option<f32>,
+    pub simulated_p: Option<f32>,
+    pub simulated_slope: Option<f32>,
+    //least squares factor from simulated to measured internode means
+    pub scale: Option<f32>,
+    //scaled simulated slope divided by the measured slope
+    pub slope_ratio: Option<f32>,
+    pub slope_sign_agrees: Option<bool>,
+    //correlation of the measured and simulated internode means
+    pub profile_r: Option<f32>,
+    pub profile_p: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct InternodeResidual{
+    pub genotype: String,
+    pub treatment: String,
+    pub internode: i32,
+    pub n: usize,
+    //mean and standard deviation of the measurements
+    pub measured: f32,
+    pub sd: Option<f32>,
+    //mean over the replicates, missing when the simulated main stem has fewer internodes
+    pub simulated: Option<f32>,
+    pub scaled_simulated: Option<f32>,
+    pub residual: Option<f32>,
+    //residual divided by sd, by 1 without sd
+    pub scaled: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct AssayComparison{
+    pub config: AssayConfig,
+    pub skipped_rows: usize,
+    pub groups: Vec<GroupComparison>,
+    pub residuals: Vec<InternodeResidual>,
+}
+
+//correlation, its p-value and the slope of y against x
+fn correlation(x: &[f32], y: &[f32]) -> (Option<f32>,Option<f32>,Option<f32>){
+    let r = pearson(x, y);
+    (r,r.and_then(|r| correlation_p_value(r, x.len())),linear_fit(x, y).map(|(slope,_)| slope))
+}
+
+fn compare_group(genotype: &str, treatment: &str, rows: &[&AssayRow], scenario: Option<&str>, simulated: Option<&Vec<BTreeMap<i32,f32>>>) -> (GroupComparison,Vec<InternodeResidual>){
+    let x: Vec<f32> = rows.iter().map(|r| r.internode as f32).collect();
+    let y: Vec<f32> = rows.iter().map(|r| r.value).collect();
+    let (measured_r,measured_p,measured_slope) = correlation(&x, &y);
+    let mut by_internode: BTreeMap<i32,Vec<f32>> = BTreeMap::new();
+    for row in rows{
+        by_internode.entry(row.internode).or_default().push(row.value);
+    }
+    let empty = vec![];
+    let replicates = simulated.unwrap_or(&empty);
+    //(internode, value) of every replicate at the measured internodes
+    let (sx,sy): (Vec<f32>,Vec<f32>) = replicates.iter().flat_map(|profile| by_internode.keys().filter_map(|i| profile.get(i).map(|v| (*i as f32,*v)))).unzip();
+    let (simulated_r,simulated_p,simulated_slope) = correlation(&sx, &sy);
+    let means: Vec<(i32,f32,Vec<f32>,Option<f32>)> = by_internode.into_iter().map(|(internode,values)|{
+        let simulated: Vec<f32> = replicates.iter().filter_map(|p| p.get(&internode).copied()).collect();
+        (internode,mean(&values),values,(!simulated.is_empty()).then(|| mean(&simulated)))
+    }).collect();
+    let paired: Vec<(f32,f32)> = means.iter().filter_map(|(_,m,_,s)| s.map(|s| (*m,s))).collect();
+    let ss: f32 = paired.iter().map(|(_,s)| s*s).sum();
+    let scale = (ss>0.).then(|| paired.iter().map(|(m,s)| m*s).sum::<f32>()/ss);
+    let (measured_means,simulated_means): (Vec<f32>,Vec<f32>) = paired.iter().copied().unzip();
+    let profile_r = pearson(&measured_means, &simulated_means);
+    let scaled_slope = scale.zip(simulated_slope).map(|(k,s)| k*s);
+    let comparison = GroupComparison {
+        genotype: genotype.to_string(),
+        treatment: treatment.to_string(),
+        scenario: scenario.map(|s| s.to_string()),
+        n: rows.len(),
+        internodes: means.len(),
+        measured_r,
+        measured_p,
+        measured_slope,
+        simulated_r,
+        simulated_p,
+        simulated_slope,
+        scale,
+        slope_ratio: scaled_slope.zip(measured_slope).filter(|(_,m)| *m!=0.).map(|(s,m)| s/m),
+        slope_sign_agrees: scaled_slope.zip(measured_slope).map(|(s,m)| (s>0.)==(m>0.)),
+        profile_r,
+        profile_p: profile_r.and_then(|r| correlation_p_value(r, paired.len())),
+    };
+    let residuals = means.into_iter().map(|(internode,measured,values,simulated)|{
+        let sd = (values.len()>=2).then(|| (values.iter().map(|v| (v-measured)*(v-measured)).sum::<f32>()/(values.len()-1) as f32).sqrt()).filter(|sd| *sd>0.);
+        let scaled_simulated = simulated.zip(scale).map(|(s,k)| s*k);
+        let residual = scaled_simulated.map(|s| measured-s);
+        InternodeResidual {
+            genotype: genotype.to_string(),
+            treatment: treatment.to_string(),
+            internode,
+            n: values.len(),
+            measured,
+            sd,
+            simulated,
+            scaled_simulated,
+            residual,
+            scaled: residual.map(|r| r/sd.unwrap_or(1.)),
+        }
+    }).collect();
+    (comparison,residuals)
+}
+
+pub fn run_comparison(config: &AssayConfig, registry: &ScenarioRegistry, base: &Settings, options: &RunOptions, threads: Option<usize>) -> Result<AssayComparison,String>{
+    let (rows,skipped_rows) = load_assay(&config.data, config.assay, config.value.as_deref(), config.sheet.as_deref())?;
+    let mut groups: BTreeMap<(String,String),Vec<&AssayRow>> = BTreeMap::new();
+    for row in &rows{
+        groups.entry((row.genotype.clone(),row.treatment.clone())).or_default().push(row);
+    }
+    let mut scenarios: Vec<Scenario> = vec![];
+    let mut group_scenarios = vec![];
+    for (genotype,treatment) in groups.keys(){
+        let scenario = config.scenario(genotype, treatment, registry)?;
+        if let Some(scenario) = &scenario{
+            if !scenarios.iter().any(|s| s.name==scenario.name){
+                scenarios.push(scenario.clone());
+            }
+        }
+        group_scenarios.push(scenario.map(|s| s.name));
+    }
+    let point = SweepPoint::new(base, &config.parameters, &config.derived)?;
+    let quantity = config.quantity();
+    //values of every simulated internode of every replicate
+    let simulated = run_jobs(&scenarios, "scenarios", threads, |scenario|{
+        let (trees,_) = simulate_replicates(&point.settings, scenario, options)?;
+        Ok(trees.iter().map(|tree| Profile::new(tree).internode_values(quantity, Aggregate::Mean)).collect::<Vec<_>>())
+    })?;
+    let simulated: BTreeMap<&str,Vec<BTreeMap<i32,f32>>> = scenarios.iter().map(|s| s.name.as_str()).zip(simulated).collect();
+    let mut comparison = AssayComparison { config: config.clone(), skipped_rows, groups: vec![], residuals: vec![] };
+    for (((genotype,treatment),rows),scenario) in groups.iter().zip(&group_scenarios){
+        let (group,residuals) = compare_group(genotype, treatment, rows, scenario.as_deref(), scenario.as_deref().and_then(|s| simulated.get(s)));
+        comparison.groups.push(group);
+        comparison.residuals.extend(residuals);
+    }
+    let dir = options.output.join(format!("assay_{}",config.name));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+    write_rows(&dir.join("summary.csv"), &comparison.groups)?;
+    write_rows(&dir.join("residuals.csv"), &comparison.residuals)?;
+    let path = dir.join("comparison.json");
+    std::fs::write(&path, serde_json::to_string_pretty(&comparison).map_err(|e| e.to_string())?).map_err(|e| format!("{}: {e}",path.display()))?;
+    Ok(comparison)
+}
+
+fn write_rows<T: Serialize>(path: &PathBuf, rows: &[T]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    for row in rows{
+        writer.serialize(row).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn load(name: &str, text: &str, assay: Assay) -> Result<(Vec<AssayRow>,usize),String>{
+        let path = std::env::temp_dir().join(format!("tree_d_{}_{name}",std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let loaded = load_assay(&path, assay, None, None);
+        std::fs::remove_file(&path).ok();
+        loaded
+    }
+
+    #[test]
+    fn lab_sheet_rows(){
+        let (rows,skipped) = load("pat.csv", "Plant,Genotype,Internode,Date,CPM1\n1,WT,int3,2022,120.5\n2,WT,int5,2022,NA\n3,NegtiveControl,NC,2022,5\n4,RNAi60,11,2022,80\n", Assay::Pat).unwrap();
+        assert_eq!(skipped,2);
+        assert_eq!(rows.len(),2);
+        assert_eq!((rows[0].genotype.as_str(),rows[0].internode,rows[0].value),("WT",3,120.5));
+        assert_eq!((rows[1].genotype.as_str(),rows[1].internode),("RNAi60",11));
+    }
+
+    #[test]
+    fn decimal_commas(){
+        let (rows,_) = load("iaa.csv", "Genotype;treatment;internode;IAA\nWT;decap;int7;12,25\n", Assay::Iaa).unwrap();
+        assert_eq!((rows[0].treatment.as_str(),rows[0].internode,rows[0].value),("decap",7,12.25));
+    }
+
+    #[test]
+    fn missing_value_column(){
+        let error = load("suc.csv", "Genotype,internode,IAA\nWT,int4,1\n", Assay::Sucrose).unwrap_err();
+        assert!(error.contains("no sucrose column"));
+    }
+
+    #[test]
+    fn workbook_sheets(){
+        let path = std::env::temp_dir().join(format!("tree_d_{}_pat.xlsx",std::process::id()));
+        std::fs::write(&path, include_bytes!("../tests/fixtures/pat_assay.xlsx")).unwrap();
+        let loaded = load_assay(&path, Assay::Pat, None, Some("PAT"));
+        //the first sheet holds notes only
+        let first = load_assay(&path, Assay::Pat, None, None);
+        let missing = load_assay(&path, Assay::Pat, None, Some("IAA"));
+        std::fs::remove_file(&path).ok();
+        let (rows,skipped) = loaded.unwrap();
+        assert_eq!(skipped,2);
+        let rows: Vec<(&str,&str,i32,f32)> = rows.iter().map(|r| (r.genotype.as_str(),r.treatment.as_str(),r.internode,r.value)).collect();
+        //numeric internode cells are read as well as "int" labels
+        assert_eq!(rows,vec![("WT","intact",1,100.),("WT","intact",2,150.5),("WT","decap",1,80.),("RNAi60","intact",4,60.25)]);
+        assert!(first.unwrap_err().contains("no Genotype column"));
+        assert!(missing.unwrap_err().contains("sheet IAA"));
+    }
+
+    fn row(internode: i32, value: f32) -> AssayRow{
+        AssayRow { genotype: "WT".to_string(), treatment: String::new(), internode, value }
+    }
+
+    fn close(a: Option<f32>, b: f32){
+        assert!(a.is_some_and(|a| (a-b).abs()<1e-4),"{a:?} != {b}");
+    }
+
+    #[test]
+    fn group_comparison(){
+        let rows = [row(1, 10.),row(1, 12.),row(2, 20.),row(3, 28.),row(3, 32.)];
+        let rows: Vec<&AssayRow> = rows.iter().collect();
+        //internode 4 is not measured and is ignored
+        let simulated = vec![BTreeMap::from([(1,1.),(2,2.),(3,3.),(4,100.)]),BTreeMap::from([(1,2.),(2,3.),(3,4.)])];
+        let (group,residuals) = compare_group("WT", "", &rows, Some("WT"), Some(&simulated));
+        assert_eq!((group.n,group.internodes),(5,3));
+        //sxy 38 over sxx 4
+        close(group.measured_slope, 9.5);
+        close(group.simulated_slope, 1.);
+        //internode means 11, 20, 30 measured and 1.5, 2.5, 3.5 simulated
+        let scale = 171.5/20.75;
+        close(group.scale, scale);
+        close(group.slope_ratio, scale/9.5);
+        assert_eq!(group.slope_sign_agrees,Some(true));
+        close(group.profile_r, 19./(2f32*180.666_67).sqrt());
+
+        let expected = [(1,2,11.,Some(2f32.sqrt()),1.5),(2,1,20.,None,2.5),(3,2,30.,Some(8f32.sqrt()),3.5)];
+        assert_eq!(residuals.len(),3);
+        for (residual,(internode,n,measured,sd,simulated)) in residuals.iter().zip(expected){
+            assert_eq!((residual.internode,residual.n,residual.measured),(internode,n,measured));
+            assert_eq!(residual.sd.is_some(),sd.is_some());
+            if let Some(sd) = sd{
+                close(residual.sd, sd);
+            }
+            close(residual.simulated, simulated);
+            close(residual.scaled_simulated, simulated*scale);
+            close(residual.residual, measured-simulated*scale);
+            close(residual.scaled, (measured-simulated*scale)/sd.unwrap_or(1.));
+        }
+    }
+
+    #[test]
+    fn group_without_simulation(){
+        let rows = [row(1, 10.),row(2, 8.),row(3, 3.)];
+        let rows: Vec<&AssayRow> = rows.iter().collect();
+        let (group,residuals) = compare_group("WT", "", &rows, None, None);
+        close(group.measured_slope, -3.5);
+        assert!(group.scale.is_none() && group.slope_ratio.is_none() && group.slope_sign_agrees.is_none() && group.simulated_r.is_none());
+        assert!(residuals.iter().all(|r| r.simulated.is_none() && r.residual.is_none()));
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+use rayon::prelude::*;
+use linya::*;
+use serde_pickle::SerOptions;
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::growth::replicate_seed;
+use crate::results::*;
+use crate::table::*;
+use crate::architecture::*;
+use crate::ordering::*;
+use crate::geometry::*;
+use crate::metrics::*;
+use crate::sweep::SweepPoint;
+
+//batch runs: replicates of scenarios brought to the steady state and saved in the results layout, see results.rs
+
+//whole trees of the run as a pickle
+pub fn save_tree(trees:&Vec<Tree>,results:&ResultsDir,manifest:&mut RunManifest) -> Result<(),String>{
+    let path = results.file(manifest,"trees","trees.pickle");
+    let pickle = serde_pickle::to_vec(&trees,SerOptions::new().proto_v2()).map_err(|e| e.to_string())?;
+    std::fs::write(&path,pickle).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+//(auxin, pin) along the main stem of every replicate as a pickle, with the seeds of the replicates in the same order
+pub fn save_tree_main_stem(trees:&Vec<Tree>,results:&ResultsDir,manifest:&mut RunManifest) -> Result<(),String>{
+    let path = results.file(manifest,"main_stem","main_stem.pickle");
+    let data : Vec<Vec<(f32,f32)>> = trees.iter().map(|tree|{
+        tree.main_stem_values()
+    }).collect();
+    let pickle = serde_pickle::to_vec(&data,SerOptions::new().proto_v2()).map_err(|e| e.to_string())?;
+    std::fs::write(&path,pickle).map_err(|e| format!("{}: {e}",path.display()))?;
+
+    let path = results.file(manifest,"seeds","seeds.json");
+    let seeds : Vec<u64> = trees.iter().map(|tree| tree.seed).collect();
+    std::fs::write(&path,serde_json::to_string(&seeds).map_err(|e| e.to_string())?).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+//architecture traits of the replicates in the layout of the field scanning sheets
+pub fn save_tree_architecture(trees:&Vec<Tree>,results:&ResultsDir,manifest:&mut RunManifest) -> Result<(),String>{
+    let path = results.file(manifest,"architecture","architecture.csv");
+    let name = manifest.scenario.name.clone();
+    let rows: Vec<(String,TreeArchitectureStats)> = trees.iter().map(|tree|{
+        (name.clone(),TreeArchitectureStats::new(tree, OrderingScheme::Gravelius, &GeometryParams::default()))
+    }).collect();
+    write_architecture_table(&path, &rows)
+}
+
+//main stem metrics of every replicate, see metrics.rs, their means are kept in the manifest
+pub fn save_tree_metrics(trees:&Vec<Tree>,results:&ResultsDir,manifest:&mut RunManifest) -> Result<(),String>{
+    let path = results.file(manifest,"metrics","metrics.csv");
+    let rows: Vec<(String,&Tree)> = trees.iter().map(|tree| (manifest.scenario.name.clone(),tree)).collect();
+    write_metrics_table(&path, &rows)?;
+    manifest.metrics = mean_metrics(trees);
+    Ok(())
+}
+
+//every compartment of every replicate, see table.rs
+pub fn save_tree_compartments(trees:&Vec<Tree>,results:&ResultsDir,manifest:&mut RunManifest) -> Result<(),String>{
+    let path = results.file(manifest,"compartments","compartments.parquet");
+    let mut table = CompartmentTable::new();
+    for (replicate,tree) in trees.iter().enumerate(){
+        table.add_tree(&manifest.scenario.name, replicate as i32, tree, &GeometryParams::default());
+    }
+    table.write_parquet(&path)
+}
+
+//replicates of one scenario brought to the steady state, kept in memory
+pub fn simulate_replicates(settings: &Settings,scenario: &Scenario,options: &RunOptions) -> Result<(Vec<Tree>,Vec<ReplicateRecord>),String>{
+    let mut trees = vec![];
+    let mut records = vec![];
+    for replicate in 0..options.replicates{
+        let now = Instant::now();
+        let seed = replicate_seed(options.master_seed,&scenario.name,replicate);
+        let tree = scenario.build(settings,seed)?;
+        let (tree,convergence) = Tree::steady_state(&tree,options.precision,options.max_iterations);
+        trees.push(tree);
+        records.push(ReplicateRecord { replicate, seed, seconds: now.elapsed().as_secs_f32(), convergence });
+    }
+    Ok((trees,records))
+}
+
+//replicates of one scenario brought to the steady state, saved in the results directory with its manifest
+pub fn generate_trees(point: &SweepPoint,scenario: &Scenario,options: &RunOptions) -> Result<RunManifest,String>{
+    let results = ResultsDir::new(&options.output);
+    let started = Instant::now();
+    let mut manifest = RunManifest::new(&point.settings,scenario,options);
+    manifest.parameters = point.values.clone();
+    manifest.design = point.design.clone();
+    results.create_run_dir(&manifest)?;
+    results.remove_manifest(&manifest)?;
+    let (trees,records) = simulate_replicates(&point.settings,scenario,options)?;
+    for record in records{
+        manifest.add_replicate(record);
+    }
+    save_tree_main_stem(&trees,&results,&mut manifest)?;
+    save_tree_architecture(&trees,&results,&mut manifest)?;
+    save_tree_metrics(&trees,&results,&mut manifest)?;
+    save_tree_compartments(&trees,&results,&mut manifest)?;
+    if options.save_trees{
+        save_tree(&trees,&results,&mut manifest)?;
+    }
+    manifest.seconds = started.elapsed().as_secs_f32();
+    results.write_manifest(&manifest)?;
+    Ok(manifest)
+}
+
+//rayon pool with the given number of threads, all cores when None
+pub fn thread_pool(threads: Option<usize>) -> Result<rayon::ThreadPool,String>{
+    rayon::ThreadPoolBuilder::new().num_threads(threads.unwrap_or(0)).build().map_err(|e| e.to_string())
+}
+
+//f of every job in parallel with a progress bar, in the order of the jobs
+//failed jobs are reported together once all jobs are done
+pub fn run_jobs<T: Sync,R: Send>(jobs: &[T],label: &str,threads: Option<usize>,f: impl Fn(&T) -> Result<R,String>+Sync) -> Result<Vec<R>,String>{
+    let progress = Mutex::new(Progress::new());
+    let bar = progress.lock().unwrap().bar(jobs.len(), label);
+    let done = AtomicUsize::new(0);
+    let outcomes: Vec<Result<R,String>> = thread_pool(threads)?.install(|| jobs.par_iter().map(|job|{
+        let outcome = f(job);
+        let done = done.fetch_add(1, Ordering::SeqCst)+1;
+        progress.lock().unwrap().set_and_draw(&bar, done);
+        outcome
+    }).collect());
+    let errors: Vec<String> = outcomes.iter().filter_map(|o| o.as_ref().err().cloned()).collect();
+    if !errors.is_empty(){
+        return Err(errors.join("\n"));
+    }
+    Ok(outcomes.into_iter().filter_map(|o| o.ok()).collect())
+}
+
+//a deterministic subset of the jobs for cluster array jobs, shard index of count takes every count-th job from index-1
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub struct Shard{
+    pub index: usize,
+    pub count: usize,
+}
+
+impl std::str::FromStr for Shard{
+    type Err = String;
+    //"2/8", the index counts from 1
+    fn from_str(text: &str) -> Result<Shard,String>{
+        let (index,count) = text.split_once('/').ok_or(format!("expected index/count, got {text}"))?;
+        let index: usize = index.trim().parse().map_err(|_| format!("invalid shard index {index}"))?;
+        let count: usize = count.trim().parse().map_err(|_| format!("invalid shard count {count}"))?;
+        if index==0 || index>count{
+            return Err(format!("shard index {index} is not between 1 and {count}"));
+        }
+        Ok(Shard { index, count })
+    }
+}
+
+impl Shard{
+    pub fn contains(&self, job: usize) -> bool{
+        job%self.count==self.index-1
+    }
+}
+
+//every point with every scenario saved in the results layout, runs that are already complete are skipped
+//index.json is written at the end, except for shards which are combined by merge_results
+pub fn run_batch(points: &[SweepPoint],scenarios: &[Scenario],options: &RunOptions,threads: Option<usize>,shard: Option<Shard>) -> Result<Vec<RunManifest>,String>{
+    let results = ResultsDir::new(&options.output);
+    let jobs: Vec<(&SweepPoint,&Scenario)> = points.iter().flat_map(|p| scenarios.iter().map(move |scenario| (p,scenario)))
+        .enumerate().filter(|(i,_)| shard.map_or(true, |s| s.contains(*i))).map(|(_,job)| job).collect();
+    let mut manifests = vec![];
+    let mut pending = vec![];
+    for (point,scenario) in jobs{
+        match results.completed(&point.settings, scenario, options).filter(|_| !options.rerun){
+            Some(manifest) => manifests.push(manifest),
+            None => pending.push((point,scenario)),
+        }
+    }
+    if !manifests.is_empty(){
+        println!("{} of {} runs already complete",manifests.len(),manifests.len()+pending.len());
+    }
+    let outcome = run_jobs(&pending, "runs", threads, |(point,scenario)| generate_trees(point,scenario,options).map_err(|e| format!("{}: {e}",scenario.name)));
+    if shard.is_none(){
+        results.write_index()?;
+    }
+    manifests.extend(outcome?);
+    Ok(manifests)
+}
+
+//runs of the given results directories copied into the output, then index.json of all runs written
+pub fn merge_results(inputs: &[std::path::PathBuf],output: &std::path::Path) -> Result<usize,String>{
+    let results = ResultsDir::new(output);
+    let mut copied = 0;
+    for input in inputs{
+        if input.canonicalize().ok()!=output.canonicalize().ok(){
+            copied += results.merge(&ResultsDir::new(input))?;
+        }
+    }
+    results.write_index()?;
+    Ok(copied)
+}
@@ -0,0 +1,608 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::expr::*;
+use crate::design::*;
+use crate::sweep::*;
+use crate::results::*;
+use crate::batch::*;
+use crate::metrics::*;
+
+//bounded Settings fields fitted to measured per-internode profiles, see sweeps/README.md
+//the simulated profile of a scenario is the mean over the replicates of the auxin and pin of every main stem internode,
+//replicates use the same seeds at every evaluation so the objective is deterministic
+//the optimisers work in the unit cube of the bounds, log scaled bounds are searched in the logarithm
+
+//one measured value, internodes are counted from the tip as in Tree::main_stem_internodes
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Measurement{
+    pub scenario: String,
+    pub internode: i32,
+    pub quantity: Quantity,
+    pub value: f32,
+    //standard deviation of the value, the residual is divided by it, 1 when missing
+    #[serde(default)]
+    pub sd: Option<f32>,
+}
+
+//rows of scenario,internode,quantity,value[,sd]
+pub fn load_measurements(path: &Path) -> Result<Vec<Measurement>,String>{
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path).map_err(|e| format!("{}: {e}",path.display()))?;
+    let measurements = reader.deserialize().collect::<Result<Vec<Measurement>,_>>().map_err(|e| format!("{}: {e}",path.display()))?;
+    if measurements.is_empty(){
+        return Err(format!("{}: no measurements",path.display()));
+    }
+    Ok(measurements)
+}
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Objective{
+    //sum of squared residuals divided by sd
+    #[default]
+    Sse,
+    //gaussian negative log likelihood, the noise of profiles without sd is estimated from their residuals
+    Likelihood,
+}
+
+//simulated profiles scaled per scenario and quantity before the comparison, measured units are usually not model units
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization{
+    None,
+    //to the mean of the measured values
+    Mean,
+    //by the weighted least squares factor
+    #[default]
+    Fitted,
+}
+
+impl Normalization{
+    //factor of the simulated values of one profile, 1 when it is not finite
+    fn factor(&self, rows: &[&Measurement], simulated: &[f32]) -> f32{
+        let weight = |row: &Measurement| 1./row.sd.unwrap_or(1.).powi(2);
+        let factor = match self{
+            Normalization::None => 1.,
+            Normalization::Mean => rows.iter().map(|r| r.value).sum::<f32>()/simulated.iter().sum::<f32>(),
+            Normalization::Fitted => rows.iter().zip(simulated).map(|(r,s)| weight(r)*r.value*s).sum::<f32>()
+                /rows.iter().zip(simulated).map(|(r,s)| weight(r)*s*s).sum::<f32>(),
+        };
+        if factor.is_finite() {factor} else {1.}
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(tag = "optimizer", rename_all = "snake_case")]
+pub enum Optimizer{
+    NelderMead{
+        //size of the initial simplex as a fraction of the bounds
+        #[serde(default = "default_step")]
+        step: f64,
+    },
+    //separable CMA-ES (Ros and Hansen, 2008), the population is evaluated in parallel
+    CmaEs{
+        //initial step size as a fraction of the bounds
+        #[serde(default = "default_sigma")]
+        sigma: f64,
+        #[serde(default)]
+        population: Option<usize>,
+    },
+}
+fn default_step() -> f64{
+    0.1
+}
+fn default_sigma() -> f64{
+    0.3
+}
+fn default_evaluations() -> usize{
+    200
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct CalibrationConfig{
+    #[serde(default)]
+    pub name: String,
+    //measurement table, relative to the configuration file
+    pub data: PathBuf,
+    //scenarios of the measurements to fit, all when empty
+    #[serde(default)]
+    pub scenarios: Vec<String>,
+    #[serde(default)]
+    pub replicates: Option<u64>,
+    #[serde(default)]
+    pub master_seed: Option<u64>,
+    #[serde(default)]
+    pub precision: Option<f32>,
+    #[serde(default)]
+    pub objective: Objective,
+    #[serde(default)]
+    pub normalization: Normalization,
+    #[serde(flatten)]
+    pub optimizer: Optimizer,
+    #[serde(default = "default_evaluations")]
+    pub max_evaluations: usize,
+    #[serde(default)]
+    pub seed: u64,
+    pub bounds: BTreeMap<String,Bounds>,
+    //initial values of bounded parameters, the centre of the bounds otherwise
+    #[serde(default)]
+    pub start: BTreeMap<String,f32>,
+    //values of other parameters, fixed during the fit
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    #[serde(default)]
+    pub derived: BTreeMap<String,Expr>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Residual{
+    pub scenario: String,
+    pub quantity: Quantity,
+    pub internode: i32,
+    pub measured: f32,
+    //after normalization
+    pub simulated: f32,
+    pub residual: f32,
+    pub sd: Option<f32>,
+    //residual divided by sd
+    pub scaled: f32,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Fit{
+    pub values: BTreeMap<String,f32>,
+    pub settings: Settings,
+    pub objective: f64,
+    pub residuals: Vec<Residual>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct CalibrationResult{
+    pub config: CalibrationConfig,
+    pub evaluations: usize,
+    //evaluations where a replicate failed, their objective is infinite
+    #[serde(default)]
+    pub failed: usize,
+    pub best: Fit,
+}
+
+impl CalibrationConfig{
+    pub fn load(path: &Path) -> Result<CalibrationConfig,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let config: Result<CalibrationConfig,String> = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        let mut config = config.and_then(|c| c.check().map(|_| c)).map_err(|e| format!("{}: {e}",path.display()))?;
+        if config.data.is_relative(){
+            config.data = path.parent().unwrap_or(Path::new(".")).join(&config.data);
+        }
+        Ok(config)
+    }
+    fn check(&self) -> Result<(),String>{
+        if self.bounds.is_empty(){
+            return Err("no bounds".to_string());
+        }
+        check_names(self.bounds.keys().chain(self.parameters.keys()), &self.derived)?;
+        for (name,bounds) in &self.bounds{
+            if self.parameters.contains_key(name){
+                return Err(format!("{name} is both bounded and fixed"));
+            }
+            bounds.check().map_err(|e| format!("{name}: {e}"))?;
+        }
+        if let Some(name) = self.start.keys().find(|name| !self.bounds.contains_key(*name)){
+            return Err(format!("start value of {name} which is not bounded"));
+        }
+        Ok(())
+    }
+
+    fn start(&self) -> Vec<f64>{
+        self.bounds.iter().map(|(name,bounds)| self.start.get(name).map_or(0.5, |&value| bounds.unit(value))).collect()
+    }
+
+    pub fn point(&self, base: &Settings, unit: &[f64]) -> Result<SweepPoint,String>{
+        let mut values = self.parameters.clone();
+        values.extend(self.bounds.iter().zip(unit).map(|((name,bounds),&u)| (name.clone(),bounds.value(u.clamp(0., 1.)))));
+        SweepPoint::new(base, &values, &self.derived)
+    }
+}
+
+//measurements with the scenarios they belong to
+pub struct Problem<'a>{
+    pub config: &'a CalibrationConfig,
+    pub measurements: Vec<Measurement>,
+    pub scenarios: Vec<Scenario>,
+    pub base: Settings,
+    pub options: &'a RunOptions,
+}
+
+impl<'a> Problem<'a>{
+    pub fn new(config: &'a CalibrationConfig, registry: &ScenarioRegistry, base: &Settings, options: &'a RunOptions) -> Result<Problem<'a>,String>{
+        let mut measurements = load_measurements(&config.data)?;
+        if !config.scenarios.is_empty(){
+            measurements.retain(|m| config.scenarios.contains(&m.scenario));
+        }
+        let mut names: Vec<String> = measurements.iter().map(|m| m.scenario.clone()).collect();
+        names.sort();
+        names.dedup();
+        if names.is_empty(){
+            return Err(format!("{}: no measurements of {}",config.data.display(),config.scenarios.join(", ")));
+        }
+        let scenarios = names.iter().map(|name| registry.get(name).cloned().ok_or(format!("unknown scenario {name}"))).collect::<Result<_,String>>()?;
+        Ok(Problem { config, measurements, scenarios, base: base.clone(), options })
+    }
+
+    pub fn fit(&self, unit: &[f64]) -> Result<Fit,String>{
+        let point = self.config.point(&self.base, unit)?;
+        let mut residuals = vec![];
+        for scenario in &self.scenarios{
+            let (trees,_) = simulate_replicates(&point.settings, scenario, self.options)?;
+            let profiles: Vec<Profile> = trees.iter().map(Profile::new).collect();
+            for quantity in [Quantity::Auxin,Quantity::Pin]{
+                let internodes: Vec<BTreeMap<i32,f32>> = profiles.iter().map(|p| p.internode_values(quantity, Aggregate::Mean)).collect();
+                let rows: Vec<&Measurement> = self.measurements.iter().filter(|m| m.scenario==scenario.name && m.quantity==quantity).collect();
+                let mut simulated = vec![];
+                for row in &rows{
+                    let values: Vec<f32> = internodes.iter().filter_map(|p| p.get(&row.internode).copied()).collect();
+                    if values.is_empty(){
+                        return Err(format!("{}: no internode {} in the simulated main stem",scenario.name,row.internode));
+                    }
+                    simulated.push(mean(&values));
+                }
+                let factor = self.config.normalization.factor(&rows, &simulated);
+                for (row,simulated) in rows.iter().zip(simulated){
+                    let simulated = factor*simulated;
+                    let residual = row.value-simulated;
+                    residuals.push(Residual {
+                        scenario: row.scenario.clone(), quantity, internode: row.internode, measured: row.value, simulated,
+                        residual, sd: row.sd, scaled: residual/row.sd.unwrap_or(1.),
+                    });
+                }
+            }
+        }
+        let objective = self.objective(&residuals);
+        Ok(Fit { values: point.values, settings: point.settings, objective, residuals })
+    }
+
+    fn objective(&self, residuals: &[Residual]) -> f64{
+        match self.config.objective{
+            Objective::Sse => residuals.iter().map(|r| (r.scaled as f64).powi(2)).sum(),
+            Objective::Likelihood => {
+                let half_log_2pi = 0.5*(2.*std::f64::consts::PI).ln();
+                let mut total = 0.;
+                //profiles without sd, by scenario and quantity: count and sum of squares
+                let mut unknown: BTreeMap<(&str,Quantity),(f64,f64)> = BTreeMap::new();
+                for r in residuals{
+                    match r.sd{
+                        Some(sd) => total += 0.5*(r.scaled as f64).powi(2)+(sd as f64).ln()+half_log_2pi,
+                        None => {
+                            let entry = unknown.entry((r.scenario.as_str(),r.quantity)).or_insert((0.,0.));
+                            *entry = (entry.0+1.,entry.1+(r.residual as f64).powi(2));
+                        }
+                    }
+                }
+                for (n,sum) in unknown.into_values(){
+                    total += n/2.*((sum/n).max(f64::MIN_POSITIVE).ln()+1.)+n*half_log_2pi;
+                }
+                total
+            }
+        }
+    }
+}
+
+//objective minimised by the optimisers in the unit cube
+trait Evaluator{
+    fn done(&self) -> bool;
+    fn evaluate(&mut self, points: &[Vec<f64>]) -> Vec<f64>;
+}
+
+//minimises the objective in the unit cube, records every evaluation
+struct Search<'a,'b>{
+    problem: &'b Problem<'a>,
+    pool: rayon::ThreadPool,
+    evaluations: usize,
+    failed: usize,
+    //first failure, reported when no evaluation succeeds
+    error: Option<String>,
+    trace: Vec<(f64,BTreeMap<String,f32>)>,
+    best: Option<Fit>,
+}
+
+impl<'a,'b> Evaluator for Search<'a,'b>{
+    fn done(&self) -> bool{
+        self.evaluations>=self.problem.config.max_evaluations
+    }
+    //objectives of the points evaluated in parallel, failed evaluations are infinite
+    fn evaluate(&mut self, points: &[Vec<f64>]) -> Vec<f64>{
+        let problem = self.problem;
+        let fits: Vec<Result<Fit,String>> = self.pool.install(|| points.par_iter().map(|u| problem.fit(u)).collect());
+        let objectives = fits.into_iter().map(|fit| match fit{
+            Ok(fit) => {
+                let objective = if fit.objective.is_finite() {fit.objective} else {f64::INFINITY};
+                self.trace.push((objective,fit.values.clone()));
+                //non-finite fits never become the best, so a NaN first fit does not stick
+                if objective.is_finite() && self.best.as_ref().is_none_or(|best| objective<best.objective){
+                    self.best = Some(fit);
+                }
+                objective
+            }
+            Err(e) => {
+                self.failed += 1;
+                self.error.get_or_insert(e);
+                f64::INFINITY
+            }
+        }).collect();
+        self.evaluations += points.len();
+        objectives
+    }
+}
+
+fn clamp_unit(x: Vec<f64>) -> Vec<f64>{
+    x.into_iter().map(|v| v.clamp(0., 1.)).collect()
+}
+
+fn nelder_mead(search: &mut impl Evaluator, start: Vec<f64>, step: f64){
+    let n = start.len();
+    let mut simplex = vec![start.clone()];
+    for i in 0..n{
+        let mut x = start.clone();
+        x[i] = if x[i]+step<=1. {x[i]+step} else {x[i]-step};
+        simplex.push(x);
+    }
+    let mut values = search.evaluate(&simplex);
+    while !search.done(){
+        let mut order: Vec<usize> = (0..=n).collect();
+        order.sort_by(|&a,&b| values[a].total_cmp(&values[b]));
+        simplex = order.iter().map(|&i| simplex[i].clone()).collect();
+        values = order.iter().map(|&i| values[i]).collect();
+        let size = simplex[1..].iter().flat_map(|x| x.iter().zip(&simplex[0]).map(|(a,b)| (a-b).abs())).fold(0., f64::max);
+        if size<1e-6 || (values[n]-values[0]).abs()<=1e-12*(values[0].abs()+1e-12){
+            break;
+        }
+        let centroid: Vec<f64> = (0..n).map(|j| simplex[..n].iter().map(|x| x[j]).sum::<f64>()/n as f64).collect();
+        let along = |t: f64| clamp_unit(centroid.iter().zip(&simplex[n]).map(|(c,w)| c+t*(c-w)).collect());
+        let reflected = along(1.);
+        let fr = search.evaluate(std::slice::from_ref(&reflected))[0];
+        if fr<values[0]{
+            let expanded = along(2.);
+            let fe = search.evaluate(std::slice::from_ref(&expanded))[0];
+            (simplex[n],values[n]) = if fe<fr {(expanded,fe)} else {(reflected,fr)};
+        } else if fr<values[n-1]{
+            (simplex[n],values[n]) = (reflected,fr);
+        } else {
+            let contracted = if fr<values[n] {along(0.5)} else {along(-0.5)};
+            let fc = search.evaluate(std::slice::from_ref(&contracted))[0];
+            if fc<fr.min(values[n]){
+                (simplex[n],values[n]) = (contracted,fc);
+            } else {
+                //shrink towards the best point
+                let best = simplex[0].clone();
+                let shrunk: Vec<Vec<f64>> = simplex[1..].iter().map(|x| x.iter().zip(&best).map(|(a,b)| b+0.5*(a-b)).collect()).collect();
+                let shrunk_values = search.evaluate(&shrunk);
+                for (i,(x,v)) in shrunk.into_iter().zip(shrunk_values).enumerate(){
+                    (simplex[i+1],values[i+1]) = (x,v);
+                }
+            }
+        }
+    }
+}
+
+fn normal(rng: &mut ChaCha8Rng) -> f64{
+    let u: f64 = rng.gen::<f64>().max(f64::MIN_POSITIVE);
+    (-2.*u.ln()).sqrt()*(2.*std::f64::consts::PI*rng.gen::<f64>()).cos()
+}
+
+//diagonal covariance CMA-ES, samples outside the unit cube are moved to its boundary
+fn cma_es(search: &mut impl Evaluator, start: Vec<f64>, sigma: f64, population: Option<usize>, seed: u64){
+    let n = start.len();
+    let nf = n as f64;
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let lambda = population.unwrap_or(4+(3.*nf.ln()).floor() as usize).max(2);
+    let mu = lambda/2;
+    let weights: Vec<f64> = (0..mu).map(|i| (mu as f64+0.5).ln()-((i+1) as f64).ln()).collect();
+    let total: f64 = weights.iter().sum();
+    let weights: Vec<f64> = weights.iter().map(|w| w/total).collect();
+    let mueff = 1./weights.iter().map(|w| w*w).sum::<f64>();
+    let cs = (mueff+2.)/(nf+mueff+5.);
+    let ds = 1.+2.*(((mueff-1.)/(nf+1.)).sqrt()-1.).max(0.)+cs;
+    let cc = (4.+mueff/nf)/(nf+4.+2.*mueff/nf);
+    let c1 = 2./((nf+1.3).powi(2)+mueff)*(nf+2.)/3.;
+    let cmu = (2.*(mueff-2.+1./mueff)/((nf+2.).powi(2)+mueff)*(nf+2.)/3.).min(1.-c1);
+    let chi = nf.sqrt()*(1.-1./(4.*nf)+1./(21.*nf*nf));
+
+    let mut mean = start;
+    let mut sigma = sigma;
+    let mut c: Vec<f64> = vec![1.; n];
+    let mut ps = vec![0.; n];
+    let mut pc = vec![0.; n];
+    let mut generation = 0;
+    while !search.done(){
+        let mut xs = vec![];
+        let mut ys = vec![];
+        for _ in 0..lambda{
+            let x = clamp_unit((0..n).map(|j| mean[j]+sigma*c[j].sqrt()*normal(&mut rng)).collect());
+            ys.push((0..n).map(|j| (x[j]-mean[j])/sigma).collect::<Vec<f64>>());
+            xs.push(x);
+        }
+        let values = search.evaluate(&xs);
+        let mut order: Vec<usize> = (0..lambda).collect();
+        order.sort_by(|&a,&b| values[a].total_cmp(&values[b]));
+        let yw: Vec<f64> = (0..n).map(|j| order[..mu].iter().zip(&weights).map(|(&k,w)| w*ys[k][j]).sum()).collect();
+        for j in 0..n{
+            mean[j] = (mean[j]+sigma*yw[j]).clamp(0., 1.);
+            ps[j] = (1.-cs)*ps[j]+(cs*(2.-cs)*mueff).sqrt()*yw[j]/c[j].sqrt();
+        }
+        generation += 1;
+        let ps_norm = ps.iter().map(|p| p*p).sum::<f64>().sqrt();
+        let hs = ps_norm/(1.-(1.-cs).powi(2*generation)).sqrt()<(1.4+2./(nf+1.))*chi;
+        let hs = if hs {1.} else {0.};
+        for j in 0..n{
+            pc[j] = (1.-cc)*pc[j]+hs*(cc*(2.-cc)*mueff).sqrt()*yw[j];
+            let rank_mu: f64 = order[..mu].iter().zip(&weights).map(|(&k,w)| w*ys[k][j]*ys[k][j]).sum();
+            c[j] = (1.-c1-cmu)*c[j]+c1*(pc[j]*pc[j]+(1.-hs)*cc*(2.-cc)*c[j])+cmu*rank_mu;
+        }
+        sigma *= ((cs/ds)*(ps_norm/chi-1.)).exp();
+        if sigma*c.iter().fold(0., |a: f64,&b| a.max(b)).sqrt()<1e-6{
+            break;
+        }
+    }
+}
+
+//best fit of the configuration, written with the trace of all evaluations and the residuals of the best fit
+//to {output}/calibration_{name}
+pub fn run_calibration(config: &CalibrationConfig, registry: &ScenarioRegistry, base: &Settings, options: &RunOptions, threads: Option<usize>) -> Result<CalibrationResult,String>{
+    let problem = Problem::new(config, registry, base, options)?;
+    let mut search = Search { problem: &problem, pool: thread_pool(threads)?, evaluations: 0, failed: 0, error: None, trace: vec![], best: None };
+    let start = config.start();
+    match config.optimizer{
+        Optimizer::NelderMead{ step } => nelder_mead(&mut search, start, step),
+        Optimizer::CmaEs{ sigma, population } => cma_es(&mut search, start, sigma, population, config.seed),
+    }
+    let best = match search.best{
+        Some(best) => best,
+        None => return Err(match search.error{
+            Some(e) => format!("no evaluation with a finite objective, first failure: {e}"),
+            None => "no evaluation with a finite objective".to_string(),
+        }),
+    };
+    let dir = options.output.join(format!("calibration_{}",config.name));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+    write_trace(&dir.join("trace.csv"), &search.trace)?;
+    write_residuals(&dir.join("residuals.csv"), &best.residuals)?;
+    let result = CalibrationResult { config: config.clone(), evaluations: search.evaluations, failed: search.failed, best };
+    let path = dir.join("best.json");
+    std::fs::write(&path, serde_json::to_string_pretty(&result).map_err(|e| e.to_string())?).map_err(|e| format!("{}: {e}",path.display()))?;
+    Ok(result)
+}
+
+fn write_trace(path: &PathBuf, trace: &[(f64,BTreeMap<String,f32>)]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    let names: Vec<&String> = trace.first().map_or(vec![], |t| t.1.keys().collect());
+    writer.write_record(["evaluation","objective"].into_iter().chain(names.iter().map(|n| n.as_str()))).map_err(error)?;
+    for (i,(objective,values)) in trace.iter().enumerate(){
+        writer.write_record([i.to_string(),objective.to_string()].into_iter().chain(names.iter().map(|n| values[*n].to_string()))).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+fn write_residuals(path: &PathBuf, residuals: &[Residual]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    for residual in residuals{
+        writer.serialize(residual).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //sum of squared distances to a point inside the cube
+    struct Quadratic{
+        centre: Vec<f64>,
+        evaluations: usize,
+        max_evaluations: usize,
+        best: (f64,Vec<f64>),
+    }
+    impl Quadratic{
+        fn new(centre: &[f64], max_evaluations: usize) -> Quadratic{
+            Quadratic { centre: centre.to_vec(), evaluations: 0, max_evaluations, best: (f64::INFINITY,vec![]) }
+        }
+        fn check(&self){
+            assert!(self.best.0<1e-6,"{:?}",self.best);
+            for (x,c) in self.best.1.iter().zip(&self.centre){
+                assert!((x-c).abs()<1e-3,"{:?}",self.best);
+            }
+        }
+    }
+    impl Evaluator for Quadratic{
+        fn done(&self) -> bool{
+            self.evaluations>=self.max_evaluations
+        }
+        fn evaluate(&mut self, points: &[Vec<f64>]) -> Vec<f64>{
+            self.evaluations += points.len();
+            points.iter().map(|x|{
+                let value = x.iter().zip(&self.centre).map(|(a,b)| (a-b).powi(2)).sum::<f64>();
+                if value<self.best.0{
+                    self.best = (value,x.clone());
+                }
+                value
+            }).collect()
+        }
+    }
+
+    #[test]
+    fn nelder_mead_minimises_a_quadratic(){
+        let mut quadratic = Quadratic::new(&[0.2,0.7,0.45], 400);
+        nelder_mead(&mut quadratic, vec![0.5; 3], default_step());
+        quadratic.check();
+    }
+
+    #[test]
+    fn cma_es_minimises_a_quadratic(){
+        let mut quadratic = Quadratic::new(&[0.2,0.7,0.45], 2000);
+        cma_es(&mut quadratic, vec![0.5; 3], default_sigma(), None, 1);
+        quadratic.check();
+    }
+
+    fn measurement(value: f32, sd: Option<f32>) -> Measurement{
+        Measurement { scenario: "WT".to_string(), internode: 1, quantity: Quantity::Auxin, value, sd }
+    }
+
+    #[test]
+    fn normalization_factors(){
+        let rows = [measurement(2., None),measurement(4., None),measurement(9., Some(2.))];
+        let rows: Vec<&Measurement> = rows.iter().collect();
+        let simulated = [1.,2.,3.];
+        assert_eq!(Normalization::None.factor(&rows, &simulated),1.);
+        assert!((Normalization::Mean.factor(&rows, &simulated)-15./6.).abs()<1e-6);
+        //(2+8+27/4)/(1+4+9/4)
+        assert!((Normalization::Fitted.factor(&rows, &simulated)-16.75/7.25).abs()<1e-6);
+        //a flat zero profile is not scaled
+        assert_eq!(Normalization::Fitted.factor(&rows, &[0.; 3]),1.);
+        assert_eq!(Normalization::Mean.factor(&rows, &[0.; 3]),1.);
+    }
+
+    fn residual(quantity: Quantity, residual: f32, sd: Option<f32>) -> Residual{
+        Residual { scenario: "WT".to_string(), quantity, internode: 1, measured: 0., simulated: 0., residual, sd, scaled: residual/sd.unwrap_or(1.) }
+    }
+
+    //gaussian negative log likelihood of one residual
+    fn nll(residual: f64, sd: f64) -> f64{
+        0.5*(residual/sd).powi(2)+sd.ln()+0.5*(2.*std::f64::consts::PI).ln()
+    }
+
+    #[test]
+    fn objectives(){
+        let mut config: CalibrationConfig = toml::from_str("data = \"measured.csv\"\noptimizer = \"nelder_mead\"\n[bounds.d]\nmin = 0.1\nmax = 1.0\n").unwrap();
+        let options = RunOptions::default();
+        let residuals = vec![
+            residual(Quantity::Auxin, 1., Some(1.)),
+            residual(Quantity::Auxin, 2., Some(2.)),
+            residual(Quantity::Pin, 1., None),
+            residual(Quantity::Pin, -1., None),
+            residual(Quantity::Pin, 2., None),
+            residual(Quantity::Pin, -2., None),
+        ];
+        let objective = |config: &CalibrationConfig|{
+            let problem = Problem { config, measurements: vec![], scenarios: vec![], base: Settings::default(), options: &options };
+            problem.objective(&residuals)
+        };
+        assert_eq!(config.objective,Objective::Sse);
+        assert!((objective(&config)-(1.+1.+1.+1.+4.+4.)).abs()<1e-9);
+
+        //the noise of the pin profile is its residual mean square, 10/4
+        config.objective = Objective::Likelihood;
+        let profile_sd = 2.5f64.sqrt();
+        let expected = nll(1., 1.)+nll(2., 2.)+[1.,-1.,2.,-2.].iter().map(|&r| nll(r, profile_sd)).sum::<f64>();
+        assert!((objective(&config)-expected).abs()<1e-9,"{} {expected}",objective(&config));
+    }
+}
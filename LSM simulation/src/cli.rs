@@ -0,0 +1,442 @@
+use std::path::{Path, PathBuf};
+use clap::{Args, Parser, Subcommand};
+use rayon::prelude::*;
+use serde_pickle::SerOptions;
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::growth::replicate_seed;
+use crate::results::*;
+use crate::batch::*;
+use crate::sweep::*;
+use crate::sensitivity::*;
+use crate::calibration::*;
+use crate::abc::*;
+use crate::metrics::*;
+use crate::assays::*;
+use crate::loaders::*;
+use crate::table::*;
+use crate::architecture::*;
+use crate::ordering::*;
+use crate::geometry::*;
+use crate::mesh::*;
+use crate::mtg::*;
+use crate::lsystem::*;
+use crate::gui_run::GuiOptions;
+
+//command line interface, without a subcommand the viewer is started
+
+#[derive(Parser,Debug)]
+#[command(version, about = "LSM model of auxin transport in trees")]
+pub struct Cli{
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand,Debug)]
+pub enum Command{
+    /// shows generated or stored trees in the 3D viewer
+    Gui{
+        /// stored trees or a run directory to show instead of generated trees
+        #[arg(long)]
+        open: Option<PathBuf>,
+        #[arg(long)]
+        replicate: Option<usize>,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// replicates of one scenario brought to the steady state and saved in the results layout
+    Simulate{
+        #[arg(long)]
+        scenario: String,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// every combination of the parameter values of a sweep file, see sweeps/README.md
+    Sweep{
+        config: PathBuf,
+        /// prints the points of the sweep as CSV instead of running them
+        #[arg(long)]
+        list: bool,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Morris or Sobol sensitivity indices of an output metric, see sweeps/README.md
+    Sensitivity{
+        config: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// Settings fields fitted to measured internode profiles, see sweeps/README.md
+    Calibrate{
+        config: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// posterior samples of Settings fields by approximate bayesian computation, see sweeps/README.md
+    Abc{
+        config: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// simulated main stem profiles compared with internode-sampled assays (PAT, IAA, sucrose), see sweeps/README.md
+    Compare{
+        config: PathBuf,
+        #[command(flatten)]
+        run: RunArgs,
+    },
+    /// runs of results directories, e.g. of shards, copied into one with index.json of all runs
+    Merge{
+        inputs: Vec<PathBuf>,
+        #[arg(short, long, default_value = "./results")]
+        output: PathBuf,
+    },
+    /// stored or generated trees brought to the steady state, with the convergence of every tree
+    SteadyState{
+        #[command(flatten)]
+        input: TreeInput,
+        #[arg(long)]
+        max_iterations: Option<usize>,
+        /// formats of the steady state trees written to the output directory
+        #[arg(long, value_delimiter = ',', default_value = "json")]
+        format: Vec<String>,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// stored or generated trees converted to other formats
+    Export{
+        #[command(flatten)]
+        input: TreeInput,
+        /// json, pickle, mtg, lsys, obj, ply, gltf, vtp, csv or parquet for the compartment table and metrics for the main stem metrics of all trees
+        #[arg(long, value_delimiter = ',', required = true)]
+        format: Vec<String>,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+    /// architecture traits of stored or generated trees written to architecture.csv
+    Stats{
+        #[command(flatten)]
+        input: TreeInput,
+        #[arg(long, default_value = "gravelius")]
+        scheme: String,
+        #[command(flatten)]
+        common: CommonArgs,
+    },
+}
+
+#[derive(Args,Debug)]
+pub struct CommonArgs{
+    /// output directory
+    #[arg(short, long, default_value = "./results")]
+    pub output: PathBuf,
+    /// worker threads, all cores by default
+    #[arg(short = 'j', long)]
+    pub threads: Option<usize>,
+    /// master seed of the replicates
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// steady state precision, the summed difference of 100 transport iterations
+    #[arg(long)]
+    pub precision: Option<f32>,
+    /// directory with scenario files added to the built-in scenarios
+    #[arg(long, default_value = "./scenarios")]
+    pub scenarios: PathBuf,
+}
+
+#[derive(Args,Debug)]
+pub struct RunArgs{
+    #[arg(long)]
+    pub replicates: Option<u64>,
+    #[arg(long)]
+    pub max_iterations: Option<usize>,
+    /// overrides of Settings fields, e.g. --set decay=0.2
+    #[arg(long = "set", value_parser = parse_assignment)]
+    pub parameters: Vec<(String,f32)>,
+    /// also save whole trees as trees.pickle
+    #[arg(long)]
+    pub save_trees: bool,
+    /// runs only this subset of the jobs of simulate or sweep, e.g. 2/8, index.json is then written by merge
+    #[arg(long)]
+    pub shard: Option<Shard>,
+    /// runs again jobs of simulate or sweep whose results are already complete
+    #[arg(long)]
+    pub rerun: bool,
+    #[command(flatten)]
+    pub common: CommonArgs,
+}
+
+//trees read from a file or run directory, or replicates grown from a scenario
+#[derive(Args,Debug)]
+pub struct TreeInput{
+    /// stored trees, see loaders.rs
+    #[arg(required_unless_present = "scenario")]
+    pub input: Option<PathBuf>,
+    #[arg(long, conflicts_with = "input")]
+    pub scenario: Option<String>,
+    /// replicates grown from the scenario
+    #[arg(long, default_value_t = 1)]
+    pub replicates: u64,
+    /// replicates picked from stored trees, all by default
+    #[arg(long, value_delimiter = ',')]
+    pub replicate: Vec<usize>,
+}
+
+fn parse_assignment(text: &str) -> Result<(String,f32),String>{
+    let (name,value) = text.split_once('=').ok_or(format!("expected name=value, got {text}"))?;
+    let value = value.trim().parse::<f32>().map_err(|_| format!("invalid value of {name}"))?;
+    Settings::default().set_parameter(name.trim(), value)?;
+    Ok((name.trim().to_string(),value))
+}
+
+impl CommonArgs{
+    fn registry(&self) -> Result<ScenarioRegistry,String>{
+        if self.scenarios.is_dir() {ScenarioRegistry::with_dir(&self.scenarios)} else {Ok(ScenarioRegistry::builtin())}
+    }
+    //scenario directory, dialog directory, seed and steady state of the viewer
+    pub fn gui_options(&self) -> GuiOptions{
+        GuiOptions { scenarios: self.scenarios.clone(), output: self.output.clone(), seed: self.seed, precision: self.precision }
+    }
+    fn run_options(&self) -> RunOptions{
+        let defaults = RunOptions::default();
+        RunOptions {
+            output: self.output.clone(),
+            master_seed: self.seed.unwrap_or(defaults.master_seed),
+            precision: self.precision.unwrap_or(defaults.precision),
+            ..defaults
+        }
+    }
+}
+
+impl RunArgs{
+    //settings with the --set values, recorded as the parameters of the run
+    fn point(&self) -> Result<SweepPoint,String>{
+        let values = self.parameters.iter().cloned().collect();
+        SweepPoint::new(&Settings::global_copy(), &values, &Default::default())
+    }
+    fn run_options(&self) -> RunOptions{
+        self.run_options_with(None, None, None)
+    }
+    //options with the values of a sweep or analysis file, command line flags take precedence
+    fn run_options_with(&self, replicates: Option<u64>, master_seed: Option<u64>, precision: Option<f32>) -> RunOptions{
+        let mut options = self.common.run_options();
+        options.replicates = self.replicates.or(replicates).unwrap_or(options.replicates);
+        options.master_seed = self.common.seed.or(master_seed).unwrap_or(options.master_seed);
+        options.precision = self.common.precision.or(precision).unwrap_or(options.precision);
+        options.max_iterations = self.max_iterations;
+        options.save_trees = self.save_trees;
+        options.rerun = self.rerun;
+        options
+    }
+}
+
+impl TreeInput{
+    fn trees(&self, common: &CommonArgs) -> Result<Vec<(String,Tree)>,String>{
+        let settings = Settings::global_copy();
+        let replicates = (!self.replicate.is_empty()).then_some(self.replicate.as_slice());
+        match (&self.input,&self.scenario){
+            (Some(path),_) => load_trees(path, &settings, replicates),
+            (None,Some(name)) => {
+                let registry = common.registry()?;
+                let scenario = registry.get(name).ok_or(format!("unknown scenario {name}"))?;
+                let master_seed = common.run_options().master_seed;
+                (0..self.replicates).map(|replicate|{
+                    let tree = scenario.build(&settings, replicate_seed(master_seed, name, replicate))?;
+                    Ok((format!("{name} #{replicate}"),tree))
+                }).collect()
+            }
+            (None,None) => Err("no input trees".to_string()),
+        }
+    }
+}
+
+//names of opened trees such as "WT #3" as file names
+fn file_name(name: &str) -> String{
+    name.chars().map(|c| if c.is_alphanumeric() || c=='-' || c=='_' {c} else {'_'}).collect::<String>().replace("__", "_")
+}
+
+fn create_dir(dir: &Path) -> Result<(),String>{
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}",dir.display()))
+}
+
+//trees written to the directory in every format, one file per tree except the compartment and metrics tables
+pub fn export_trees(trees: &[(String,Tree)], dir: &Path, formats: &[String]) -> Result<Vec<PathBuf>,String>{
+    create_dir(dir)?;
+    let mut written = vec![];
+    for format in formats{
+        let format = format.trim().to_lowercase();
+        if format=="csv" || format=="parquet"{
+            let mut table = CompartmentTable::new();
+            for (replicate,(name,tree)) in trees.iter().enumerate(){
+                table.add_tree(name, replicate as i32, tree, &GeometryParams::default());
+            }
+            let path = dir.join(format!("compartments.{format}"));
+            table.save(&path)?;
+            written.push(path);
+            continue;
+        }
+        if format=="metrics"{
+            let path = dir.join("metrics.csv");
+            let rows: Vec<(String,&Tree)> = trees.iter().map(|(name,tree)| (name.clone(),tree)).collect();
+            write_metrics_table(&path, &rows)?;
+            written.push(path);
+            continue;
+        }
+        for (name,tree) in trees{
+            let path = dir.join(format!("{}.{format}",file_name(name)));
+            let error = |e: String| format!("{}: {e}",path.display());
+            match format.as_str(){
+                "json" => std::fs::write(&path, serde_json::to_string(tree).map_err(|e| error(e.to_string()))?).map_err(|e| error(e.to_string()))?,
+                "pickle" | "pkl" => std::fs::write(&path, serde_pickle::to_vec(tree, SerOptions::new().proto_v2()).map_err(|e| error(e.to_string()))?).map_err(|e| error(e.to_string()))?,
+                "mtg" => save_mtg(&path, tree, &GeometryParams::default(), &MtgOptions::default())?,
+                "lsys" => save_lsystem(&path, tree)?,
+                "obj" | "ply" | "gltf" | "vtp" => save_mesh(&path, tree, &MeshParams::default())?,
+                _ => return Err(format!("unknown export format {format}")),
+            }
+            written.push(path);
+        }
+    }
+    Ok(written)
+}
+
+//one row per point with the sample of the design and the values of the parameters
+fn print_points(points: &[SweepPoint]) -> Result<(),String>{
+    let names: Vec<&String> = points.first().map_or(vec![], |p| p.values.keys().collect());
+    let mut writer = csv::Writer::from_writer(std::io::stdout());
+    writer.write_record(["point","sample"].into_iter().chain(names.iter().map(|n| n.as_str()))).map_err(|e| e.to_string())?;
+    for (i,point) in points.iter().enumerate(){
+        let sample = point.design.as_ref().map_or(String::new(), |d| d.sample.to_string());
+        let values = names.iter().map(|n| point.values[*n].to_string());
+        writer.write_record([i.to_string(),sample].into_iter().chain(values)).map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())
+}
+
+fn print_manifests(manifests: &[RunManifest], results: &ResultsDir){
+    for manifest in manifests{
+        let iterations = manifest.replicates.iter().map(|r| r.convergence.iterations).max().unwrap_or(0);
+        println!("{} {} converged: {}, max iterations {iterations}, {:.1} s",
+            manifest.scenario.name, results.run_dir(manifest).display(), manifest.converged, manifest.seconds);
+    }
+}
+
+//runs every subcommand except gui
+pub fn run_command(command: Command) -> Result<(),String>{
+    match command{
+        Command::Gui{..} => Err("the viewer is started by main".to_string()),
+        Command::Simulate{ scenario, run } => {
+            let registry = run.common.registry()?;
+            let scenario = registry.get(&scenario).ok_or(format!("unknown scenario {scenario}"))?.clone();
+            let options = run.run_options();
+            let manifests = run_batch(&[run.point()?], &[scenario], &options, run.common.threads, run.shard)?;
+            print_manifests(&manifests, &ResultsDir::new(&options.output));
+            Ok(())
+        }
+        Command::Sweep{ config, list, run } => {
+            let sweep = SweepConfig::load(&config)?;
+            let scenarios = sweep.scenarios(&run.common.registry()?)?;
+            let options = run.run_options_with(sweep.replicates, sweep.master_seed, sweep.precision);
+            let points = sweep.points(&run.point()?.settings)?;
+            if list{
+                return print_points(&points);
+            }
+            println!("{}: {} settings x {} scenarios",config.display(),points.len(),scenarios.len());
+            let manifests = run_batch(&points, &scenarios, &options, run.common.threads, run.shard)?;
+            print_manifests(&manifests, &ResultsDir::new(&options.output));
+            Ok(())
+        }
+        Command::Sensitivity{ config: path, run } => {
+            let config = SensitivityConfig::load(&path)?;
+            let scenarios = config.scenarios(&run.common.registry()?)?;
+            let options = run.run_options_with(config.replicates, config.master_seed, config.precision);
+            let results = run_sensitivity(&config, &scenarios, &run.point()?.settings, &options, run.common.threads)?;
+            for result in &results{
+                match &result.indices{
+                    SensitivityIndices::Morris{ mu, mu_star, sigma } => println!("{} {}: mu* {:.4} [{:.4}, {:.4}], mu {mu:.4}, sigma {sigma:.4}",
+                        result.scenario,result.parameter,mu_star.value,mu_star.low,mu_star.high),
+                    SensitivityIndices::Sobol{ first, total } => println!("{} {}: first {:.3} [{:.3}, {:.3}], total {:.3} [{:.3}, {:.3}]",
+                        result.scenario,result.parameter,first.value,first.low,first.high,total.value,total.low,total.high),
+                }
+            }
+            println!("{}",options.output.join(format!("sensitivity_{}",config.name)).display());
+            Ok(())
+        }
+        Command::Calibrate{ config: path, run } => {
+            let config = CalibrationConfig::load(&path)?;
+            let options = run.run_options_with(config.replicates, config.master_seed, config.precision);
+            let result = run_calibration(&config, &run.common.registry()?, &run.point()?.settings, &options, run.common.threads)?;
+            println!("objective {} after {} evaluations, {} failed",result.best.objective,result.evaluations,result.failed);
+            for (name,value) in &result.best.values{
+                println!("{name} = {value}");
+            }
+            println!("{}",options.output.join(format!("calibration_{}",config.name)).display());
+            Ok(())
+        }
+        Command::Abc{ config: path, run } => {
+            let config = AbcConfig::load(&path)?;
+            let options = run.run_options_with(config.replicates, None, config.precision);
+            let (result,_) = run_abc(&config, &run.common.registry()?, &run.point()?.settings, &options, run.common.threads)?;
+            for summary in &result.posterior{
+                println!("{}: mean {:.4}, median {:.4}, {}% credible interval [{:.4}, {:.4}]",
+                    summary.parameter,summary.mean,summary.median,(config.credible*100.).round(),summary.low,summary.high);
+            }
+            println!("{}",options.output.join(format!("abc_{}",config.name)).display());
+            Ok(())
+        }
+        Command::Compare{ config: path, run } => {
+            let config = AssayConfig::load(&path)?;
+            let options = run.run_options_with(config.replicates, config.master_seed, config.precision);
+            let comparison = run_comparison(&config, &run.common.registry()?, &run.point()?.settings, &options, run.common.threads)?;
+            if comparison.skipped_rows>0{
+                println!("{} rows without an internode or value skipped",comparison.skipped_rows);
+            }
+            let number = |v: Option<f32>| v.map_or("-".to_string(), |v| format!("{v:.3}"));
+            for group in &comparison.groups{
+                println!("{}: n {}, measured r {} (p {}), simulated r {} (p {}), slope ratio {}, profile r {}",
+                    [group.genotype.as_str(),group.treatment.as_str()].join(" ").trim(),group.n,number(group.measured_r),number(group.measured_p),
+                    number(group.simulated_r),number(group.simulated_p),number(group.slope_ratio),number(group.profile_r));
+            }
+            println!("{}",options.output.join(format!("assay_{}",config.name)).display());
+            Ok(())
+        }
+        Command::Merge{ inputs, output } => {
+            let copied = merge_results(&inputs, &output)?;
+            println!("{copied} runs copied, {}",output.join(INDEX_FILE).display());
+            Ok(())
+        }
+        Command::SteadyState{ input, max_iterations, format, common } => {
+            let trees = input.trees(&common)?;
+            let precision = common.run_options().precision;
+            let trees: Vec<(String,Tree)> = thread_pool(common.threads)?.install(|| trees.into_par_iter().map(|(name,tree)|{
+                let (tree,convergence) = Tree::steady_state(&tree, precision, max_iterations);
+                println!("{name}: converged {}, {} iterations, difference {}",convergence.converged,convergence.iterations,convergence.difference);
+                (name,tree)
+            }).collect());
+            for path in export_trees(&trees, &common.output, &format)?{
+                println!("{}",path.display());
+            }
+            Ok(())
+        }
+        Command::Export{ input, format, common } => {
+            for path in export_trees(&input.trees(&common)?, &common.output, &format)?{
+                println!("{}",path.display());
+            }
+            Ok(())
+        }
+        Command::Stats{ input, scheme, common } => {
+            let scheme = OrderingScheme::from_name(&scheme).ok_or(format!("unknown ordering scheme {scheme}"))?;
+            let trees = input.trees(&common)?;
+            let rows: Vec<(String,TreeArchitectureStats)> = thread_pool(common.threads)?.install(|| trees.par_iter().map(|(name,tree)|{
+                (name.clone(),TreeArchitectureStats::new(tree, scheme, &GeometryParams::default()))
+            }).collect());
+            for (name,stats) in &rows{
+                println!("{name}: height {:.1}, branches {}, total length {:.1}, active buds {}, dormant buds {}",
+                    stats.height,stats.branches,stats.total_length,stats.active_buds,stats.dormant_buds);
+            }
+            create_dir(&common.output)?;
+            let path = common.output.join("architecture.csv");
+            write_architecture_table(&path, &rows)?;
+            println!("{}",path.display());
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::sweep::Scale;
+
+//space-filling designs over bounded parameters, for when a full grid is too large
+//dimensions are the bounded parameters in alphabetical order, so a design file always gives the same points
+//latin_hypercube and random depend on the seed, sobol and halton sequences do not
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DesignMethod{
+    #[serde(alias = "lhs")]
+    LatinHypercube,
+    Sobol,
+    Halton,
+    //independent uniform samples, log-uniform for log scaled bounds
+    Random,
+}
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+pub struct Bounds{
+    pub min: f32,
+    pub max: f32,
+    #[serde(default)]
+    pub scale: Scale,
+}
+
+impl Bounds{
+    //value at u from [0,1), log scaled bounds are uniform in the logarithm
+    pub fn value(&self, u: f64) -> f32{
+        match self.scale{
+            Scale::Linear => (self.min as f64+u*(self.max as f64-self.min as f64)) as f32,
+            Scale::Log => (self.min.ln() as f64+u*(self.max.ln() as f64-self.min.ln() as f64)).exp() as f32,
+        }
+    }
+    //inverse of value, clamped to the bounds
+    pub fn unit(&self, value: f32) -> f64{
+        let u = match self.scale{
+            Scale::Linear => (value as f64-self.min as f64)/(self.max as f64-self.min as f64),
+            Scale::Log => (value.ln() as f64-self.min.ln() as f64)/(self.max.ln() as f64-self.min.ln() as f64),
+        };
+        if u.is_finite() {u.clamp(0., 1.)} else {0.}
+    }
+    pub fn check(&self) -> Result<(),String>{
+        if !(self.min<=self.max){
+            return Err("min is above max".to_string());
+        }
+        if self.scale==Scale::Log && self.min<=0.{
+            return Err("log scaled bounds need a positive min".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug,PartialEq)]
+pub struct Design{
+    pub method: DesignMethod,
+    pub samples: usize,
+    #[serde(default)]
+    pub seed: u64,
+    pub bounds: BTreeMap<String,Bounds>,
+}
+
+//a point of a design as recorded in the run manifest
+#[derive(Serialize, Deserialize,Clone,Debug,PartialEq)]
+pub struct DesignSample{
+    pub design: Design,
+    pub sample: usize,
+    //coordinates in the unit cube before scaling to the bounds
+    pub unit: Vec<f64>,
+}
+
+impl Design{
+    pub fn check(&self) -> Result<(),String>{
+        if self.samples==0{
+            return Err("design has no samples".to_string());
+        }
+        if self.bounds.is_empty(){
+            return Err("design has no bounds".to_string());
+        }
+        for (name,bounds) in &self.bounds{
+            bounds.check().map_err(|e| format!("{name}: {e}"))?;
+        }
+        let dimensions = self.bounds.len();
+        match self.method{
+            DesignMethod::Sobol if dimensions>SOBOL_DIRECTIONS.len()+1 => Err(format!("sobol designs support up to {} parameters",SOBOL_DIRECTIONS.len()+1)),
+            DesignMethod::Halton if dimensions>PRIMES.len() => Err(format!("halton designs support up to {} parameters",PRIMES.len())),
+            _ => Ok(()),
+        }
+    }
+
+    //samples x dimensions points in [0,1)
+    pub fn unit_points(&self) -> Vec<Vec<f64>>{
+        let dimensions = self.bounds.len();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        match self.method{
+            DesignMethod::LatinHypercube => latin_hypercube(self.samples, dimensions, &mut rng),
+            //the first points of both sequences are skipped, the origin for sobol
+            DesignMethod::Sobol => (1..=self.samples).map(|i| sobol(i as u32, dimensions)).collect(),
+            DesignMethod::Halton => (1..=self.samples).map(|i| halton(i as u64, dimensions)).collect(),
+            DesignMethod::Random => (0..self.samples).map(|_| (0..dimensions).map(|_| rng.gen::<f64>()).collect()).collect(),
+        }
+    }
+
+    //values of the bounded parameters of every sample
+    pub fn points(&self) -> Vec<(DesignSample,BTreeMap<String,f32>)>{
+        self.unit_points().into_iter().enumerate().map(|(sample,unit)|{
+            let values = self.bounds.iter().zip(&unit).map(|((name,bounds),&u)| (name.clone(),bounds.value(u))).collect();
+            (DesignSample { design: self.clone(), sample, unit },values)
+        }).collect()
+    }
+}
+
+//every dimension split into samples strata, each stratum used once at a random position
+pub fn latin_hypercube(samples: usize, dimensions: usize, rng: &mut ChaCha8Rng) -> Vec<Vec<f64>>{
+    let mut points = vec![vec![0.; dimensions]; samples];
+    for d in 0..dimensions{
+        let mut strata: Vec<usize> = (0..samples).collect();
+        strata.shuffle(rng);
+        for (point,stratum) in points.iter_mut().zip(strata){
+            point[d] = (stratum as f64+rng.gen::<f64>())/samples as f64;
+        }
+    }
+    points
+}
+
+const PRIMES: [u64;20] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71];
+
+fn radical_inverse(mut index: u64, base: u64) -> f64{
+    let mut result = 0.;
+    let mut fraction = 1./base as f64;
+    while index>0{
+        result += (index%base) as f64*fraction;
+        index /= base;
+        fraction /= base as f64;
+    }
+    result
+}
+
+pub fn halton(index: u64, dimensions: usize) -> Vec<f64>{
+    PRIMES[..dimensions].iter().map(|&base| radical_inverse(index, base)).collect()
+}
+
+//(degree, coefficients, initial direction numbers) of dimensions 2.. from new-joe-kuo-6.21201 (Joe and Kuo, 2008)
+const SOBOL_DIRECTIONS: [(u32,u32,&[u32]);20] = [
+    (1, 0, &[1]),
+    (2, 1, &[1, 3]),
+    (3, 1, &[1, 3, 1]),
+    (3, 2, &[1, 1, 1]),
+    (4, 1, &[1, 1, 3, 3]),
+    (4, 4, &[1, 3, 5, 13]),
+    (5, 2, &[1, 1, 5, 5, 17]),
+    (5, 4, &[1, 1, 5, 5, 5]),
+    (5, 7, &[1, 1, 7, 11, 19]),
+    (5, 11, &[1, 1, 5, 1, 1]),
+    (5, 13, &[1, 1, 1, 3, 11]),
+    (5, 14, &[1, 3, 5, 5, 31]),
+    (6, 1, &[1, 3, 3, 9, 7, 49]),
+    (6, 13, &[1, 1, 1, 15, 21, 21]),
+    (6, 16, &[1, 3, 1, 13, 27, 49]),
+    (6, 19, &[1, 1, 1, 15, 7, 5]),
+    (6, 22, &[1, 3, 1, 15, 13, 25]),
+    (6, 25, &[1, 1, 5, 5, 19, 61]),
+    (7, 1, &[1, 3, 7, 11, 23, 15, 103]),
+    (7, 4, &[1, 3, 7, 13, 13, 15, 69]),
+];
+
+//32 direction numbers of a dimension, scaled to 32 bits
+fn sobol_directions(dimension: usize) -> [u32;32]{
+    let mut v = [0u32;32];
+    if dimension==0{
+        for (k,v) in v.iter_mut().enumerate(){
+            *v = 1<<(31-k);
+        }
+        return v;
+    }
+    let (s,a,m) = SOBOL_DIRECTIONS[dimension-1];
+    let s = s as usize;
+    for k in 0..32{
+        v[k] = if k<s {
+            m[k]<<(31-k)
+        } else {
+            let mut value = v[k-s]^(v[k-s]>>s);
+            for t in 1..s{
+                if (a>>(s-1-t))&1==1{
+                    value ^= v[k-t];
+                }
+            }
+            value
+        };
+    }
+    v
+}
+
+//point index of the sobol sequence in gray code order
+pub fn sobol(index: u32, dimensions: usize) -> Vec<f64>{
+    let gray = index^(index>>1);
+    (0..dimensions).map(|d|{
+        let v = sobol_directions(d);
+        let x = (0..32).filter(|bit| (gray>>bit)&1==1).fold(0u32, |x,bit| x^v[bit]);
+        x as f64/(1u64<<32) as f64
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn sobol_matches_joe_kuo(){
+        //points 1..7 of the unscrambled 4 dimensional sequence, as given by scipy.stats.qmc.Sobol(4, scramble=False)
+        let expected = [
+            [0.5, 0.5, 0.5, 0.5],
+            [0.75, 0.25, 0.25, 0.25],
+            [0.25, 0.75, 0.75, 0.75],
+            [0.375, 0.375, 0.625, 0.875],
+            [0.875, 0.875, 0.125, 0.375],
+            [0.625, 0.125, 0.875, 0.625],
+            [0.125, 0.625, 0.375, 0.125],
+        ];
+        for (i,point) in expected.iter().enumerate(){
+            assert_eq!(sobol(i as u32+1, 4),point,"point {}",i+1);
+        }
+        assert_eq!(sobol(0, 21),vec![0.; 21]);
+    }
+
+    #[test]
+    fn sobol_points_are_stratified(){
+        //every dyadic interval of length 1/16 holds one of the first 16 points in every dimension
+        for d in 0..21{
+            let mut strata: Vec<usize> = (0..16).map(|i| (sobol(i, 21)[d]*16.) as usize).collect();
+            strata.sort();
+            assert_eq!(strata,(0..16).collect::<Vec<usize>>(),"dimension {d}");
+        }
+    }
+
+    #[test]
+    fn latin_hypercube_strata_used_once(){
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let points = latin_hypercube(25, 4, &mut rng);
+        assert_eq!(points.len(),25);
+        for d in 0..4{
+            let mut strata: Vec<usize> = points.iter().map(|p| (p[d]*25.) as usize).collect();
+            strata.sort();
+            assert_eq!(strata,(0..25).collect::<Vec<usize>>(),"dimension {d}");
+        }
+    }
+
+    #[test]
+    fn design_points_within_bounds(){
+        let design = Design {
+            method: DesignMethod::LatinHypercube,
+            samples: 10,
+            seed: 3,
+            bounds: [
+                ("a".to_string(),Bounds { min: 1., max: 2., scale: Scale::Linear }),
+                ("b".to_string(),Bounds { min: 0.01, max: 100., scale: Scale::Log }),
+            ].into(),
+        };
+        assert_eq!(design.points(),design.points());
+        for (_,values) in design.points(){
+            assert!((1. ..=2.).contains(&values["a"]) && (0.01..=100.).contains(&values["b"]),"{values:?}");
+        }
+    }
+}
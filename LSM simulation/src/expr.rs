@@ -0,0 +1,359 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+
+//small arithmetic expressions used in growth programs, e.g. "3*i", "ceil(0.5*i)", "i > 4 && i < 20"
+//comparisons and logical operators give 1 for true and 0 for false
+
+#[derive(Clone,Debug,PartialEq)]
+enum Ast{
+    Number(f64),
+    Variable(String),
+    Negate(Box<Ast>),
+    Not(Box<Ast>),
+    Binary(&'static str,Box<Ast>,Box<Ast>),
+    Call(String,Vec<Ast>),
+}
+
+pub trait ExprContext{
+    fn variable(&self, name: &str) -> Option<f64>;
+    //uniform number from [0,1) used by random() and bernoulli(p)
+    fn random(&mut self) -> Option<f64>{
+        None
+    }
+}
+
+impl ExprContext for BTreeMap<String,f64>{
+    fn variable(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+pub struct Expr{
+    source: String,
+    ast: Ast,
+}
+
+impl Expr{
+    pub fn parse(source: &str) -> Result<Expr,String>{
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let ast = parser.logical()?;
+        if parser.position!=parser.tokens.len(){
+            return Err(format!("unexpected {:?} in \"{source}\"",parser.tokens[parser.position]));
+        }
+        Ok(Expr { source: source.to_string(), ast })
+    }
+    pub fn constant(value: f64) -> Expr{
+        Expr { source: value.to_string(), ast: Ast::Number(value) }
+    }
+    pub fn source(&self) -> &str{
+        &self.source
+    }
+    pub fn eval(&self, context: &mut dyn ExprContext) -> Result<f64,String>{
+        eval(&self.ast, context).map_err(|e| format!("{e} in \"{}\"",self.source))
+    }
+    //names of variables used by the expression
+    pub fn variables(&self) -> Vec<String>{
+        let mut result = vec![];
+        collect_variables(&self.ast, &mut result);
+        result
+    }
+}
+
+impl Serialize for Expr{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+impl<'de> Deserialize<'de> for Expr{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Source{
+            Number(f64),
+            Text(String),
+        }
+        match Source::deserialize(deserializer)?{
+            Source::Number(value) => Ok(Expr::constant(value)),
+            Source::Text(text) => Expr::parse(&text).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Clone,Debug,PartialEq)]
+enum Token{
+    Number(f64),
+    Name(String),
+    Operator(&'static str),
+}
+
+const FUNCTIONS: [(&str,usize); 12] = [
+    ("floor",1),("ceil",1),("round",1),("abs",1),("sqrt",1),("exp",1),("ln",1),
+    ("min",2),("max",2),("pow",2),("random",0),("bernoulli",1),
+];
+
+const OPERATORS: [&str; 18] = ["&&","||","==","!=","<=",">=","<",">","+","-","*","/","%","^","!","(",")",","];
+
+fn tokenize(source: &str) -> Result<Vec<Token>,String>{
+    let mut tokens = vec![];
+    let chars: Vec<char> = source.chars().collect();
+    let mut i = 0;
+    while i<chars.len(){
+        let c = chars[i];
+        if c.is_whitespace(){
+            i+=1;
+        }
+        else if c.is_ascii_digit() || c=='.'{
+            let start = i;
+            while i<chars.len() && (chars[i].is_ascii_digit() || chars[i]=='.' || chars[i]=='e' || ((chars[i]=='-' || chars[i]=='+') && chars[i-1]=='e')){
+                i+=1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(text.parse().map_err(|_| format!("invalid number {text}"))?));
+        }
+        else if c.is_alphabetic() || c=='_'{
+            let start = i;
+            while i<chars.len() && (chars[i].is_alphanumeric() || chars[i]=='_' || chars[i]=='.'){
+                i+=1;
+            }
+            tokens.push(Token::Name(chars[start..i].iter().collect()));
+        }
+        else{
+            let rest: String = chars[i..].iter().take(2).collect();
+            match OPERATORS.iter().find(|op| rest.starts_with(*op)){
+                Some(op) => {
+                    tokens.push(Token::Operator(op));
+                    i+=op.len();
+                }
+                None => return Err(format!("unexpected character '{c}' in \"{source}\"")),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser{
+    tokens: Vec<Token>,
+    position: usize,
+}
+impl Parser{
+    fn peek_operator(&self, operators: &[&'static str]) -> Option<&'static str>{
+        match self.tokens.get(self.position){
+            Some(Token::Operator(op)) if operators.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+    fn expect(&mut self, operator: &str) -> Result<(),String>{
+        match self.tokens.get(self.position){
+            Some(Token::Operator(op)) if *op==operator => {
+                self.position+=1;
+                Ok(())
+            }
+            other => Err(format!("expected '{operator}', found {other:?}")),
+        }
+    }
+    fn binary(&mut self, operators: &[&'static str], next: fn(&mut Parser) -> Result<Ast,String>) -> Result<Ast,String>{
+        let mut left = next(self)?;
+        while let Some(op) = self.peek_operator(operators){
+            self.position+=1;
+            let right = next(self)?;
+            left = Ast::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+    fn logical(&mut self) -> Result<Ast,String>{
+        self.binary(&["||"], |p| p.binary(&["&&"], Parser::comparison))
+    }
+    fn comparison(&mut self) -> Result<Ast,String>{
+        self.binary(&["==","!=","<=",">=","<",">"], Parser::sum)
+    }
+    fn sum(&mut self) -> Result<Ast,String>{
+        self.binary(&["+","-"], Parser::product)
+    }
+    fn product(&mut self) -> Result<Ast,String>{
+        self.binary(&["*","/","%"], Parser::unary)
+    }
+    fn unary(&mut self) -> Result<Ast,String>{
+        match self.peek_operator(&["-","!"]){
+            Some("-") => {
+                self.position+=1;
+                Ok(Ast::Negate(Box::new(self.unary()?)))
+            }
+            Some(_) => {
+                self.position+=1;
+                Ok(Ast::Not(Box::new(self.unary()?)))
+            }
+            None => self.power(),
+        }
+    }
+    fn power(&mut self) -> Result<Ast,String>{
+        let base = self.primary()?;
+        if self.peek_operator(&["^"]).is_some(){
+            self.position+=1;
+            let exponent = self.unary()?;
+            return Ok(Ast::Binary("^", Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+    fn primary(&mut self) -> Result<Ast,String>{
+        let token = self.tokens.get(self.position).cloned();
+        self.position+=1;
+        match token{
+            Some(Token::Number(value)) => Ok(Ast::Number(value)),
+            Some(Token::Name(name)) => {
+                if self.peek_operator(&["("]).is_none(){
+                    return Ok(Ast::Variable(name));
+                }
+                self.position+=1;
+                let mut arguments = vec![];
+                if self.peek_operator(&[")"]).is_none(){
+                    arguments.push(self.logical()?);
+                    while self.peek_operator(&[","]).is_some(){
+                        self.position+=1;
+                        arguments.push(self.logical()?);
+                    }
+                }
+                self.expect(")")?;
+                match FUNCTIONS.iter().find(|(f,_)| *f==name){
+                    None => Err(format!("unknown function {name}")),
+                    Some((_,arity)) if *arity!=arguments.len() => Err(format!("{name} takes {arity} arguments")),
+                    Some(_) => Ok(Ast::Call(name, arguments)),
+                }
+            }
+            Some(Token::Operator("(")) => {
+                let inner = self.logical()?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            other => Err(format!("unexpected {other:?}")),
+        }
+    }
+}
+
+fn truth(value: bool) -> f64{
+    if value {1.} else {0.}
+}
+
+fn eval(ast: &Ast, context: &mut dyn ExprContext) -> Result<f64,String>{
+    Ok(match ast{
+        Ast::Number(value) => *value,
+        Ast::Variable(name) => context.variable(name).ok_or(format!("unknown variable {name}"))?,
+        Ast::Negate(inner) => -eval(inner, context)?,
+        Ast::Not(inner) => truth(eval(inner, context)?==0.),
+        Ast::Binary(op, left, right) => {
+            let a = eval(left, context)?;
+            let b = eval(right, context)?;
+            match *op{
+                "+" => a+b,
+                "-" => a-b,
+                "*" => a*b,
+                "/" => a/b,
+                "%" => a%b,
+                "^" => a.powf(b),
+                "==" => truth(a==b),
+                "!=" => truth(a!=b),
+                "<" => truth(a<b),
+                ">" => truth(a>b),
+                "<=" => truth(a<=b),
+                ">=" => truth(a>=b),
+                "&&" => truth(a!=0. && b!=0.),
+                "||" => truth(a!=0. || b!=0.),
+                _ => unreachable!(),
+            }
+        }
+        Ast::Call(name, arguments) => {
+            let mut values = vec![];
+            for argument in arguments{
+                values.push(eval(argument, context)?);
+            }
+            match name.as_str(){
+                "floor" => values[0].floor(),
+                "ceil" => values[0].ceil(),
+                "round" => values[0].round(),
+                "abs" => values[0].abs(),
+                "sqrt" => values[0].sqrt(),
+                "exp" => values[0].exp(),
+                "ln" => values[0].ln(),
+                "min" => values[0].min(values[1]),
+                "max" => values[0].max(values[1]),
+                "pow" => values[0].powf(values[1]),
+                "random" => context.random().ok_or("random() is not available here")?,
+                "bernoulli" => truth(context.random().ok_or("bernoulli() is not available here")?<values[0]),
+                _ => return Err(format!("unknown function {name}")),
+            }
+        }
+    })
+}
+
+fn collect_variables(ast: &Ast, result: &mut Vec<String>){
+    match ast{
+        Ast::Number(_) => {}
+        Ast::Variable(name) => {
+            if !result.contains(name){
+                result.push(name.clone());
+            }
+        }
+        Ast::Negate(inner) | Ast::Not(inner) => collect_variables(inner, result),
+        Ast::Binary(_, left, right) => {
+            collect_variables(left, result);
+            collect_variables(right, result);
+        }
+        Ast::Call(_, arguments) => {
+            for argument in arguments{
+                collect_variables(argument, result);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn value(source: &str) -> Result<f64,String>{
+        let variables: BTreeMap<String,f64> = [("i".to_string(),3.)].into();
+        Expr::parse(source)?.eval(&mut variables.clone())
+    }
+
+    #[test]
+    fn precedence(){
+        assert_eq!(value("1 + 2*3"),Ok(7.));
+        assert_eq!(value("(1 + 2)*3"),Ok(9.));
+        assert_eq!(value("2^3^2"),Ok(512.));
+        assert_eq!(value("7 % 4*2"),Ok(6.));
+        assert_eq!(value("i > 2 && i < 4 || 0"),Ok(1.));
+        assert_eq!(value("1 + 1 == 2"),Ok(1.));
+    }
+
+    #[test]
+    fn unary_minus(){
+        assert_eq!(value("-i"),Ok(-3.));
+        assert_eq!(value("-2^2"),Ok(-4.));
+        assert_eq!(value("2^-1"),Ok(0.5));
+        assert_eq!(value("3 - -1"),Ok(4.));
+        assert_eq!(value("!0 + !i"),Ok(1.));
+    }
+
+    #[test]
+    fn function_arity(){
+        assert_eq!(value("max(i, ceil(2.5))"),Ok(3.));
+        assert_eq!(value("min(1)"),Err("min takes 2 arguments".to_string()));
+        assert_eq!(value("floor(1, 2)"),Err("floor takes 1 arguments".to_string()));
+        assert!(value("random()").unwrap_err().contains("random() is not available"));
+        assert!(value("cube(2)").unwrap_err().contains("unknown function cube"));
+    }
+
+    #[test]
+    fn unknown_variables(){
+        assert_eq!(value("2*j"),Err("unknown variable j in \"2*j\"".to_string()));
+        assert_eq!(Expr::parse("i + j*max(i, k)").unwrap().variables(),["i","j","k"]);
+    }
+
+    #[test]
+    fn malformed_input(){
+        for source in ["", "1 +", "(1", "1)", "min(1,", "*2", "1..2", "3 $ 4", "i i", ",", "e-"]{
+            assert!(Expr::parse(source).is_err(),"{source:?} parsed");
+        }
+    }
+}
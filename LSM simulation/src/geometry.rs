@@ -0,0 +1,140 @@
+use std::f32::consts::PI;
+use serde::{Serialize, Deserialize};
+use three_d::*;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::ordering::*;
+
+//parameters of the synthetic tree shape, the same as used for rendering
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+#[serde(default)]
+pub struct GeometryParams{
+    pub divergence_angle: f32,
+    pub branching_angle: f32,
+    pub segment_length: f32,
+    pub initial_width: f32,
+    pub order_width_influence: f32,
+}
+impl Default for GeometryParams{
+    fn default() -> Self {
+        GeometryParams {
+            divergence_angle: PI*(137.5/180.),
+            branching_angle: PI*0.3,
+            segment_length: 2.,
+            initial_width: 2.5,
+            order_width_influence: 2.,
+        }
+    }
+}
+
+//white to blue with the auxin level relative to auxin_max, as drawn in the GUI
+pub fn auxin_color(auxin: f32, auxin_max: f32, exponent: f32) -> [f32;3]{
+    let coeff = (auxin.clamp(0., auxin_max)/auxin_max).powf(exponent);
+    [1.-coeff,1.-coeff,1.]
+}
+const ORDER_COLORS: [[f32;3]; 6] = [
+    [0.35,0.2,0.1],
+    [0.85,0.37,0.01],
+    [0.11,0.47,0.22],
+    [0.46,0.44,0.7],
+    [0.91,0.16,0.54],
+    [0.4,0.65,0.12],
+];
+pub fn order_color(order: i32) -> [f32;3]{
+    ORDER_COLORS[order.max(0) as usize%ORDER_COLORS.len()]
+}
+//value shown as the colour of the compartments
+#[derive(Serialize, Deserialize,Clone,Copy,PartialEq,Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorBy{
+    Auxin,
+    Order(OrderingScheme),
+}
+impl ColorBy{
+    pub fn name(&self) -> String{
+        match self{
+            ColorBy::Auxin => "auxin".to_string(),
+            ColorBy::Order(scheme) => format!("{} order",scheme.name()),
+        }
+    }
+}
+
+//rotation taking the y axis to the direction
+pub fn frame(direction: Vec3) -> Mat4{
+    if direction.magnitude2()==0.{
+        return Mat4::identity();
+    }
+    let y = direction.normalize();
+    let helper = if y.x.abs()<0.9 {vec3(1.,0.,0.)} else {vec3(0.,0.,1.)};
+    let z = helper.cross(y).normalize();
+    let x = y.cross(z);
+    Mat4::from_cols(x.extend(0.),y.extend(0.),z.extend(0.),vec4(0.,0.,0.,1.))
+}
+
+//one compartment as a straight piece from start to end
+#[derive(Clone,Copy,Debug)]
+pub struct CompartmentGeometry{
+    pub compartment: Compartment,
+    pub start: Vec3,
+    pub end: Vec3,
+    //width of the rendered cylinder, the diameter for measured compartments
+    pub width: f32,
+}
+impl CompartmentGeometry{
+    pub fn length(&self) -> f32{
+        (self.end-self.start).magnitude()
+    }
+    pub fn direction(&self) -> Vec3{
+        (self.end-self.start).normalize()
+    }
+}
+
+//world positions of all compartments, for every node its segments from the base followed by the node itself
+#[derive(Clone,Debug)]
+pub struct TreeGeometry{
+    nodes: Vec<Vec<CompartmentGeometry>>,
+}
+impl TreeGeometry{
+    pub fn new(tree: &Tree, params: &GeometryParams) -> TreeGeometry{
+        let transformations = tree.node_transformations(params.divergence_angle, params.branching_angle,
+            params.segment_length, params.initial_width, params.order_width_influence);
+        let nodes = tree.nodes.iter().zip(transformations).map(|(node,transformation)|{
+            let transformation = tree.transformation*transformation;
+            let width = params.initial_width/((node.initial_order as f32)*params.order_width_influence+1.);
+            let point = |i: usize| (transformation*vec4(0.,params.segment_length*i as f32,0.,1.)).truncate();
+            let segments = (0..node.segments.len()).map(Some).chain([None]);
+            segments.enumerate().map(|(i,segment)|{
+                let compartment = Compartment { node: node.index, segment };
+                let cylinder = match segment{
+                    Some(j) => node.segments[j].cylinder,
+                    None => node.cylinder,
+                };
+                match cylinder{
+                    Some(cylinder) if tree.measured => CompartmentGeometry {
+                        compartment,
+                        start: (tree.transformation*Vec3::from(cylinder.start).extend(1.)).truncate(),
+                        end: (tree.transformation*Vec3::from(cylinder.end).extend(1.)).truncate(),
+                        width: 2.*cylinder.radius,
+                    },
+                    _ => CompartmentGeometry { compartment, start: point(i), end: point(i+1), width },
+                }
+            }).collect()
+        }).collect();
+        TreeGeometry { nodes }
+    }
+    pub fn node(&self, id: NodeId) -> &[CompartmentGeometry]{
+        &self.nodes[id.0]
+    }
+    pub fn compartments(&self) -> impl Iterator<Item=&CompartmentGeometry>{
+        self.nodes.iter().flatten()
+    }
+    //base of the node, where its first compartment starts
+    pub fn base(&self, id: NodeId) -> Vec3{
+        self.nodes[id.0][0].start
+    }
+    //end of the node compartment
+    pub fn top(&self, id: NodeId) -> Vec3{
+        self.nodes[id.0].last().unwrap().end
+    }
+}
@@ -0,0 +1,420 @@
+use std::f32::consts::PI;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::growth_program::*;
+use crate::lsystem::*;
+
+//growth schedules of the genotypes, the defaults reproduce trees at week 11
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct LowestBranchesDecapitation{
+    pub step: i32,
+    pub number: usize,
+    pub skip: usize,
+    pub segments: i32,
+    //replaces extensions of order 1 per step after the decapitation
+    pub order_1_extensions_per_step: Option<f32>,
+}
+impl Default for LowestBranchesDecapitation{
+    fn default() -> Self {
+        LowestBranchesDecapitation { step: 20, number: 5, skip: 1, segments: 3, order_1_extensions_per_step: None }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct Decapitation{
+    pub step: i32,
+    //internodes grown after decapitation are longer by this amount of segments
+    pub segments_amount_increase: i32,
+    pub lowest_branches: Option<LowestBranchesDecapitation>,
+}
+impl Default for Decapitation{
+    fn default() -> Self {
+        Decapitation { step: 8, segments_amount_increase: 0, lowest_branches: None }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct WildTypeGrowth{
+    pub segments_amount: i32,
+    pub initial_extensions: i32,
+    pub steps: i32,
+    pub second_activation_probability: f32,
+    pub order_1_extensions_per_step: f32,
+    pub main_extensions_per_step: i32,
+    pub decapitation: Option<Decapitation>,
+}
+impl Default for WildTypeGrowth{
+    fn default() -> Self {
+        WildTypeGrowth {
+            segments_amount: 6,
+            initial_extensions: 10,
+            steps: 25,
+            second_activation_probability: 0.45,
+            order_1_extensions_per_step: 3.,
+            main_extensions_per_step: 5,
+            decapitation: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct Rnai60Growth{
+    pub segments_amount: i32,
+    pub steps: i32,
+    //second order branches are activated after this step
+    pub order_2_start: i32,
+    pub order_2_activation_probability: f32,
+    pub order_2_extensions_per_step: f32,
+    //extensions of order 1 per step are drawn from the pair, the first with given probability
+    pub order_1_extensions_per_step: (i32,i32),
+    pub order_1_extensions_probability: f32,
+    pub decapitation: Option<Decapitation>,
+}
+impl Default for Rnai60Growth{
+    fn default() -> Self {
+        Rnai60Growth {
+            segments_amount: 4,
+            steps: 25,
+            order_2_start: 4,
+            order_2_activation_probability: 0.5,
+            order_2_extensions_per_step: 0.5,
+            order_1_extensions_per_step: (3,2),
+            order_1_extensions_probability: 0.6,
+            decapitation: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct KanttarelliGrowth{
+    pub segments_amount: i32,
+    pub initial_extensions: i32,
+    pub steps: i32,
+    pub order_2_start: i32,
+    pub order_2_extensions_per_step: i32,
+    pub order_1_extensions_per_step: i32,
+    pub main_extensions_per_step: i32,
+}
+impl Default for KanttarelliGrowth{
+    fn default() -> Self {
+        KanttarelliGrowth {
+            segments_amount: 4,
+            initial_extensions: 4,
+            steps: 20,
+            order_2_start: 4,
+            order_2_extensions_per_step: 1,
+            order_1_extensions_per_step: 2,
+            main_extensions_per_step: 2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(default)]
+pub struct PoleGrowth{
+    pub size: i32,
+    //None makes the whole pole a single internode
+    pub internode_size: Option<i32>,
+    pub activated: bool,
+}
+impl Default for PoleGrowth{
+    fn default() -> Self {
+        PoleGrowth { size: 40, internode_size: None, activated: false }
+    }
+}
+
+//fixed architecture written as a bracketed L-system string, see lsystem.rs
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct LsystemGrowth{
+    pub axiom: String,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GrowthSchedule{
+    WildType(WildTypeGrowth),
+    Rnai60(Rnai60Growth),
+    Kanttarelli(KanttarelliGrowth),
+    Pole(PoleGrowth),
+    Program(GrowthProgram),
+    Lsystem(LsystemGrowth),
+}
+impl GrowthSchedule{
+    pub fn check(&self) -> Result<(),String>{
+        match self{
+            GrowthSchedule::Program(program) => program.check(),
+            GrowthSchedule::Lsystem(growth) => read_lsystem(&growth.axiom, &Settings::default()).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+    //programs and axioms come from scenario files, their errors are returned instead of stopping a batch
+    pub fn grow(&self, settings: &Settings, seed: u64) -> Result<Tree,String>{
+        match self{
+            GrowthSchedule::WildType(growth) => wild_type(settings, growth, seed),
+            GrowthSchedule::Rnai60(growth) => rnai60_growth(settings, growth, seed),
+            GrowthSchedule::Kanttarelli(growth) => Ok(kanttarelli(settings, growth, seed)),
+            GrowthSchedule::Pole(growth) => Ok(pole_growth(settings, growth, seed)),
+            GrowthSchedule::Program(program) => program.grow(settings, seed).map_err(|e| format!("growth program: {e}")),
+            GrowthSchedule::Lsystem(growth) => {
+                let mut tree = read_lsystem(&growth.axiom, settings).map_err(|e| format!("L-system axiom: {e}"))?;
+                tree.seed = seed;
+                *tree.rng() = ChaCha8Rng::seed_from_u64(seed);
+                Ok(tree)
+            }
+        }
+    }
+}
+
+pub fn random_growth(tree: &mut Tree, prob:f32){
+    if tree.random()>prob{
+        tree.branch_random();
+        tree.extend_random();
+    }
+    else{
+        tree.extend_random();
+    }
+
+}
+
+pub fn wild_type(_settings: &Settings, growth: &WildTypeGrowth, seed: u64) -> Result<Tree,String>{
+    let mut settings = _settings.clone();
+    settings.segments_amount=growth.segments_amount;
+    let mut tree = Tree::with_seed(&settings, seed);
+    for _ in 0..growth.initial_extensions{
+        tree.extend_main();
+    }
+    for i in 0..growth.steps{
+        let mut order_extension = (growth.order_1_extensions_per_step*i as f32).floor() as i32;
+        if let Some(decapitation) = &growth.decapitation{
+            if i == decapitation.step{
+                tree.decapitate_main()?;
+                settings.segments_amount+=decapitation.segments_amount_increase;
+                tree.new_settings(settings.clone())
+            }
+            if let Some(lowest) = &decapitation.lowest_branches{
+                if i==lowest.step{
+                    tree.decapitate_lowest_branches(lowest.number, lowest.skip, lowest.segments)?;
+                }
+                if let (true, Some(extensions)) = (i>=lowest.step, lowest.order_1_extensions_per_step){
+                    order_extension = (extensions*i as f32).floor() as i32;
+                }
+            }
+        }
+        tree.activate_random_with_order(1);
+        let do_it :f32 =tree.random();
+        if do_it<growth.second_activation_probability {tree.activate_random_with_order(1);}
+        for _ in 0..order_extension{
+        tree.extend_random_with_order(1);
+        }
+        for _ in 0..growth.main_extensions_per_step{
+        tree.extend_main();
+        }
+    }
+    if growth.decapitation.is_some(){
+        tree.recalculate_initial_order();
+    }
+    Ok(tree)
+}
+
+pub fn rnai60_growth(_settings: &Settings, growth: &Rnai60Growth, seed: u64) -> Result<Tree,String>{
+    let mut settings = _settings.clone();
+    settings.segments_amount=growth.segments_amount;
+    let mut tree = Tree::with_seed(&settings, seed);
+    for _ in 0..(settings.segments_amount-1)*2{
+        tree.extend_main();
+    }
+    for i in 0..growth.steps{
+        tree.activate_random_with_order(1);
+        if i>growth.order_2_start{
+            let do_it :f32 =tree.random();
+            if do_it<growth.order_2_activation_probability {tree.activate_random_with_order(2);}
+            for _ in 0..(growth.order_2_extensions_per_step*i as f32).ceil() as i32{
+                tree.extend_random_with_order(2);
+            }
+        }
+        if let Some(decapitation) = &growth.decapitation{
+            if i == decapitation.step{
+                tree.decapitate_main()?;
+                if decapitation.segments_amount_increase!=0{
+                    settings.segments_amount+=decapitation.segments_amount_increase;
+                    tree.new_settings(settings.clone())
+                }
+            }
+            if let Some(lowest) = &decapitation.lowest_branches{
+                if i==lowest.step{
+                    tree.decapitate_lowest_branches(lowest.number, lowest.skip, lowest.segments)?;
+                }
+            }
+        }
+        let (high,low) = growth.order_1_extensions_per_step;
+        let times = if tree.random()<growth.order_1_extensions_probability{ high } else{low};
+        for _ in 0..times*i{
+        tree.extend_random_with_order(1);
+        }
+        for _ in 0..(settings.segments_amount-1){
+        tree.extend_main();
+        }
+    }
+    if growth.decapitation.is_some(){
+        tree.recalculate_initial_order();
+    }
+    Ok(tree)
+}
+
+pub fn kanttarelli(_settings: &Settings, growth: &KanttarelliGrowth, seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=growth.segments_amount;
+    let mut tree = Tree::with_seed(&settings, seed);
+    for _ in 0..growth.initial_extensions{
+        tree.extend_main();
+    }
+    for i in 0..growth.steps{
+        tree.activate_random_with_order(1);
+        if i>growth.order_2_start{
+            for _ in 0..growth.order_2_extensions_per_step*i{
+                tree.extend_random_with_order(2);
+            }
+        }
+        for _ in 0..growth.order_1_extensions_per_step*i{
+        tree.extend_random_with_order(1);
+        }
+        for _ in 0..growth.main_extensions_per_step{
+        tree.extend_main();
+        }
+    }
+    tree
+}
+
+pub fn pole_growth(_settings: &Settings, growth: &PoleGrowth, seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=growth.internode_size.unwrap_or(growth.size+2);
+    let mut tree = Tree::with_seed(&settings, seed);
+    for _ in 0..growth.size{
+        tree.extend_main();
+    }
+    if growth.activated{
+        activate_pole(&mut tree);
+    }
+    tree
+}
+
+//deterministic seed of a replicate, the same master seed gives the same trees regardless of the order of the jobs
+pub fn replicate_seed(master_seed: u64, name: &str, replicate: u64) -> u64{
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in name.bytes(){
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    splitmix64(splitmix64(master_seed ^ hash).wrapping_add(replicate))
+}
+fn splitmix64(x: u64) -> u64{
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+pub fn wild_type_week_11(settings: &Settings, seed: u64) -> Result<Tree,String>{
+    wild_type(settings, &WildTypeGrowth::default(), seed)
+}
+pub fn wild_decapitated_week_11(settings: &Settings, seed: u64) -> Result<Tree,String>{
+    let growth = WildTypeGrowth{
+        decapitation: Some(Decapitation{
+            step: 8,
+            segments_amount_increase: 1,
+            lowest_branches: Some(LowestBranchesDecapitation{ order_1_extensions_per_step: Some(2.5), ..Default::default() }),
+        }),
+        ..Default::default()
+    };
+    wild_type(settings, &growth, seed)
+}
+pub fn wild_type_week_11_2(_settings: &Settings, seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=5;
+    let mut tree = Tree::with_seed(&settings, seed);
+    for _ in 0..(settings.segments_amount)*2{
+        tree.extend_main();
+    }
+    for i in 0..20{
+        tree.activate_random_with_order(1);
+        for _ in 0..(((settings.segments_amount as f32)/2.)*i as f32).floor() as i32{
+        tree.extend_random_with_order(1);
+        }
+        for _ in 0..(settings.segments_amount-1){
+        tree.extend_main();
+        }
+    }
+
+    tree
+}
+pub fn rnai60(settings: &Settings, seed: u64) -> Result<Tree,String>{
+    rnai60_growth(settings, &Rnai60Growth::default(), seed)
+}
+pub fn rnai60_decapitated(settings: &Settings, seed: u64) -> Result<Tree,String>{
+    let growth = Rnai60Growth{
+        decapitation: Some(Decapitation{
+            step: 8,
+            segments_amount_increase: 0,
+            lowest_branches: Some(LowestBranchesDecapitation::default()),
+        }),
+        ..Default::default()
+    };
+    rnai60_growth(settings, &growth, seed)
+}
+pub fn kanttarelli_week_11(settings: &Settings, seed: u64) -> Tree{
+    kanttarelli(settings, &KanttarelliGrowth::default(), seed)
+}
+pub fn random_tree(nodes:i32,initial_size:i32,prob:f32,settings: &Settings,seed: u64) -> Tree{
+    let mut tree = Tree::with_seed(&settings, seed);
+    for  _ in 0..initial_size{    tree.extend_main();}
+    //tree.branch_random();
+    //print!("{:#?}",tree);
+    for _ in 0..nodes{
+        random_growth(&mut tree, prob);
+    }
+    for _ in 0..nodes/2{
+        tree.extend_random();
+    }
+    let branching_angle = PI*0.2;
+    let divergence_angle = PI*(137./180.);
+    let segment_length = 2.;
+
+    //tree.update_transformations(divergence_angle,branching_angle,segment_length);
+    tree
+}
+pub fn pole(size:i32,_settings: &Settings,seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=size+2;
+    random_tree(0, size, 0.,&settings,seed)
+}
+pub fn pole_internode_size(size:i32,internode_size:i32,_settings: &Settings,seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=internode_size;
+    random_tree(0, size, 0.,&settings,seed)
+}
+pub fn pole_internode_size_active(size:i32,internode_size:i32,_settings: &Settings,seed: u64) -> Tree{
+    let mut settings = _settings.clone();
+    settings.segments_amount=internode_size;
+    let mut tree=random_tree(0, size, 0.,&settings,seed);
+    activate_pole(&mut tree);
+    tree
+}
+fn activate_pole(tree: &mut Tree){
+    for i in tree.node_ids().skip(1).collect::<Vec<_>>(){
+        if let BudState::DormantBud = tree[i].bud_state {
+        match tree[tree[i].parent.unwrap()].bud_state{
+            BudState::ActiveBud => {},
+            _=> {tree.activate(i);}
+            }
+        }
+    }
+    tree.debug_validate("activate_pole");
+}
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::expr::*;
+
+//declarative growth protocol interpreted against Tree methods
+//counts, probabilities and conditions are expressions of the loop variables, e.g.
+//
+//[[growth.program]]
+//repeat = { times = 25, body = [
+//    { activate = { order = 1 } },
+//    { activate = { order = 1 }, probability = 0.45 },
+//    { decapitate = "main", when = "i == 8" },
+//    { extend = { order = 1, count = "3*i" } },
+//    { extend_main = 5 },
+//] }
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DecapitationTarget{
+    Main,
+    LowestBranches{ number: usize, skip: usize, segments: i32 },
+    RandomOrder1{ number: usize },
+}
+
+fn one() -> Expr{
+    Expr::constant(1.)
+}
+fn loop_variable() -> String{
+    "i".to_string()
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action{
+    ExtendMain(Expr),
+    Extend{ order: usize, #[serde(default="one")] count: Expr },
+    Activate{ order: usize, #[serde(default="one")] count: Expr },
+    ExtendRandom(Expr),
+    BranchRandom(Expr),
+    Decapitate(DecapitationTarget),
+    //longer internodes for the rest of growth
+    IncreaseSegmentsAmount(i32),
+    Repeat{ times: Expr, #[serde(default="loop_variable")] var: String, body: Vec<Step> },
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Step{
+    #[serde(flatten)]
+    pub action: Action,
+    //step is skipped when the condition evaluates to 0
+    #[serde(default)]
+    pub when: Option<Expr>,
+    #[serde(default)]
+    pub probability: Option<Expr>,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct GrowthProgram{
+    pub segments_amount: Option<i32>,
+    pub program: Vec<Step>,
+}
+
+struct ProgramContext<'a>{
+    variables: &'a BTreeMap<String,f64>,
+    tree: &'a mut Tree,
+}
+impl<'a> ExprContext for ProgramContext<'a>{
+    fn variable(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+    fn random(&mut self) -> Option<f64> {
+        Some(self.tree.rng().gen::<f64>())
+    }
+}
+
+impl GrowthProgram{
+    pub fn parse_toml(text: &str) -> Result<GrowthProgram,String>{
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+    //every variable has to be a variable of an enclosing repeat
+    pub fn check(&self) -> Result<(),String>{
+        check_steps(&self.program, &mut vec![])
+    }
+    pub fn grow(&self, _settings: &Settings, seed: u64) -> Result<Tree,String>{
+        let mut settings = _settings.clone();
+        if let Some(segments_amount) = self.segments_amount{
+            settings.segments_amount=segments_amount;
+        }
+        let mut tree = Tree::with_seed(&settings, seed);
+        let mut variables = BTreeMap::new();
+        run_steps(&self.program, &mut tree, &mut settings, &mut variables)?;
+        tree.recalculate_initial_order();
+        Ok(tree)
+    }
+}
+
+fn check_steps(steps: &[Step], scope: &mut Vec<String>) -> Result<(),String>{
+    for step in steps{
+        let mut expressions: Vec<&Expr> = step.when.iter().chain(step.probability.iter()).collect();
+        match &step.action{
+            Action::ExtendMain(n) | Action::ExtendRandom(n) | Action::BranchRandom(n) => expressions.push(n),
+            Action::Extend { count, .. } | Action::Activate { count, .. } => expressions.push(count),
+            Action::Repeat { times, var, body } => {
+                expressions.push(times);
+                scope.push(var.clone());
+                check_steps(body, scope)?;
+                scope.pop();
+            }
+            _ => {}
+        }
+        for expr in expressions{
+            if let Some(name) = expr.variables().into_iter().find(|v| !scope.contains(v)){
+                return Err(format!("unknown variable {name} in \"{}\"",expr.source()));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn evaluate(expr: &Expr, variables: &BTreeMap<String,f64>, tree: &mut Tree) -> Result<f64,String>{
+    expr.eval(&mut ProgramContext { variables, tree })
+}
+//repetitions of one step, far above any growth protocol so a runaway expression is reported instead of growing for ever
+const MAX_COUNT: f64 = 100000.;
+
+fn count(expr: &Expr, variables: &BTreeMap<String,f64>, tree: &mut Tree) -> Result<i32,String>{
+    let value = evaluate(expr, variables, tree)?;
+    if !value.is_finite(){
+        return Err(format!("count \"{}\" is {value}",expr.source()));
+    }
+    if value>MAX_COUNT{
+        return Err(format!("count \"{}\" is {value}, above the limit of {MAX_COUNT}",expr.source()));
+    }
+    Ok(value.max(0.) as i32)
+}
+
+fn run_steps(steps: &[Step], tree: &mut Tree, settings: &mut Settings, variables: &mut BTreeMap<String,f64>) -> Result<(),String>{
+    for step in steps{
+        if let Some(condition) = &step.when{
+            if evaluate(condition, variables, tree)?==0.{
+                continue;
+            }
+        }
+        if let Some(probability) = &step.probability{
+            let p = evaluate(probability, variables, tree)?;
+            if tree.rng().gen::<f64>()>=p{
+                continue;
+            }
+        }
+        match &step.action{
+            Action::ExtendMain(n) => {
+                for _ in 0..count(n, variables, tree)?{
+                    tree.extend_main();
+                }
+            }
+            Action::Extend { order, count: n } => {
+                for _ in 0..count(n, variables, tree)?{
+                    tree.extend_random_with_order(*order);
+                }
+            }
+            Action::Activate { order, count: n } => {
+                for _ in 0..count(n, variables, tree)?{
+                    tree.activate_random_with_order(*order);
+                }
+            }
+            Action::ExtendRandom(n) => {
+                for _ in 0..count(n, variables, tree)?{
+                    tree.extend_random();
+                }
+            }
+            Action::BranchRandom(n) => {
+                for _ in 0..count(n, variables, tree)?{
+                    tree.branch_random();
+                }
+            }
+            Action::Decapitate(target) => {
+                match target{
+                    DecapitationTarget::Main => tree.decapitate_main()?,
+                    DecapitationTarget::LowestBranches { number, skip, segments } => tree.decapitate_lowest_branches(*number, *skip, *segments)?,
+                    DecapitationTarget::RandomOrder1 { number } => {
+                        tree.decapitate_random_order_1(*number).map_err(|_| format!("could not decapitate {number} order 1 branches"))?;
+                    }
+                }
+            }
+            Action::IncreaseSegmentsAmount(n) => {
+                settings.segments_amount+=n;
+                tree.new_settings(settings.clone());
+            }
+            Action::Repeat { times, var, body } => {
+                let previous = variables.get(var).copied();
+                for i in 0..count(times, variables, tree)?{
+                    variables.insert(var.clone(), i as f64);
+                    run_steps(body, tree, settings, variables)?;
+                }
+                match previous{
+                    Some(value) => variables.insert(var.clone(), value),
+                    None => variables.remove(var),
+                };
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::topology::*;
+
+    fn grow(text: &str) -> Result<Tree,String>{
+        let program = GrowthProgram::parse_toml(text)?;
+        program.check()?;
+        program.grow(&Settings::default(), 1)
+    }
+
+    #[test]
+    fn repeat_with_expressions(){
+        let tree = grow("program = [{ repeat = { times = 4, body = [{ extend_main = \"i\", when = \"i % 2 == 1\" }] } }]").unwrap();
+        let plain = grow("program = [{ extend_main = 4 }]").unwrap();
+        assert_eq!(tree.nodes.len(),plain.nodes.len());
+    }
+
+    #[test]
+    fn unknown_variables(){
+        let error = grow("program = [{ extend_main = \"2*j\" }]").unwrap_err();
+        assert!(error.contains("j"),"{error}");
+        assert!(grow("program = [{ repeat = { times = 2, var = \"j\", body = [{ extend_main = \"2*j\" }] } }]").is_ok());
+    }
+
+    #[test]
+    fn malformed_expressions(){
+        assert!(grow("program = [{ extend_main = \"2*\" }]").is_err());
+        assert!(grow("program = [{ extend_main = \"min(2)\" }]").is_err());
+    }
+
+    #[test]
+    fn decapitating_seedlings(){
+        assert!(grow("program = [{ decapitate = \"main\" }]").unwrap_err().contains("main decapitation"));
+        assert!(grow("program = [{ extend_main = 5 }, { decapitate = { random_order1 = { number = 2 } } }]").unwrap_err().contains("order 1 branches"));
+    }
+
+    #[test]
+    fn decapitating_more_branches_than_the_stem_has(){
+        let tree = grow("program = [{ extend_main = 12 }, { activate = { order = 1, count = 20 } }, { extend = { order = 1, count = 40 } }, { decapitate = { lowest_branches = { number = 20, skip = 0, segments = 1 } } }]").unwrap();
+        let main_stem: Vec<NodeId> = tree.axis(tree.root()).collect();
+        let decapitated: Vec<NodeId> = tree.node_ids().filter(|&id| tree[id].bud_state==BudState::DecapitatedSegment).collect();
+        assert!(!decapitated.is_empty());
+        assert!(decapitated.iter().all(|id| !main_stem.contains(id) && tree[*id].order>0),"{decapitated:?}");
+        assert!(tree.validate().is_empty());
+    }
+
+    #[test]
+    fn runaway_counts(){
+        let error = grow("program = [{ repeat = { times = 2, var = \"age\", body = [{ extend_main = \"1/age\" }] } }]").unwrap_err();
+        assert!(error.contains("is inf"),"{error}");
+        assert!(grow("program = [{ extend_main = \"sqrt(-1)\" }]").unwrap_err().contains("is NaN"));
+        assert!(grow("program = [{ extend_main = \"10^9\" }]").unwrap_err().contains("above the limit"));
+        assert!(grow("program = [{ extend_main = \"-3\" }]").is_ok());
+    }
+}
@@ -5,14 +5,32 @@ use rand::thread_rng;
 use three_d::*;
 
 
-use crate::{vec_tree::*, instance_data::*};
+use crate::{vec_tree::*, instance_data::*, scenario::*, ordering::OrderingScheme, geometry::ColorBy};
 
 #[cfg(not(target_arch = "wasm32"))]
-use tinyfiledialogs::save_file_dialog;
+use tinyfiledialogs::{save_file_dialog, open_file_dialog};
 
 #[derive(PartialEq)]
 enum PlotType { Auxin, PIN }
 
+//command line options of the viewer, see CommonArgs in cli.rs
+#[derive(Clone,Debug)]
+pub struct GuiOptions{
+    //scenario files added to the built-in scenarios
+    pub scenarios: std::path::PathBuf,
+    //initial directory of the open and save dialogs
+    pub output: std::path::PathBuf,
+    //seed of the generated trees, a random one per tree when None
+    pub seed: Option<u64>,
+    //the simulation pauses when the summed difference of 100 transport iterations of every tree is below it
+    pub precision: Option<f32>,
+}
+impl Default for GuiOptions{
+    fn default() -> Self {
+        GuiOptions { scenarios: "./scenarios".into(), output: "./".into(), seed: None, precision: None }
+    }
+}
+
 
 pub fn distance_direction(pos: Vector3<f32>, direction: Vector3<f32>,other: Vector3<f32>) -> (f32,f32) {
     let v = pos - other;
@@ -21,69 +39,52 @@ pub fn distance_direction(pos: Vector3<f32>, direction: Vector3<f32>,other: Vect
     let dist = direction.cross(other_dir).magnitude()*magnitude;
     (dist,magnitude)
 }
-pub fn generate_trees_showcase(distance:f32, decapitated: bool)-> Vec<Tree>{
-    let settings = Settings::global_copy();
-    
-    let mut trees = vec![];
+//scenarios shown in the GUI, decapitated variant is used when the registry has one
+pub fn showcase_scenarios(registry: &ScenarioRegistry, selected: &[String], decapitated: bool) -> Vec<Scenario>{
+    selected.iter().filter_map(|name|{
+        match registry.get(&format!("{name}_decapitated")){
+            Some(scenario) if decapitated => Some(scenario.clone()),
+            _ => registry.get(name).cloned(),
+        }
+    }).collect()
+}
 
+fn showcase_positions(distance: f32) -> [Vec3;4]{
     let d = distance;
-    let mut tree=if decapitated{
-         rnai60_decapitated(&settings)
-    }
-    else{
-        rnai60(&settings)
-    };
-    tree.transformation = Mat4::from_translation(vec3(d,0.,d));
-
-    // let branching_angle = PI*0.1;
-    // let divergence_angle = PI*(137./180.);
-    // let segment_length = 2.;
-
-    // tree.update_transformations(divergence_angle,branching_angle,segment_length);
+    [vec3(d,0.,d),vec3(-d,0.,-d),vec3(-d,0.,d),vec3(d,0.,-d)]
+}
 
-    trees.push(tree);
+//trees are grown from the seed when given, otherwise every tree gets a random one
+pub fn generate_trees_showcase(distance:f32, scenarios: &[Scenario], seed: Option<u64>)-> Vec<Tree>{
+    let settings = Settings::global_copy();
+    let positions = showcase_positions(distance);
+
+    scenarios.iter().zip(positions).map(|(scenario,position)|{
+        //a failing scenario file shows a seedling instead of stopping the viewer
+        let seed = seed.unwrap_or_else(rand::random);
+        let mut tree = scenario.build(&settings,seed).unwrap_or_else(|e|{
+            println!("{e}");
+            Tree::with_seed(&settings, seed)
+        });
+        tree.transformation = Mat4::from_translation(position);
+        tree
+    }).collect()
+}
 
-    //let mut tree = wild_type_week_11(&settings);
-    let mut tree=if decapitated{
-        wild_decapitated_week_11(&settings)
+//stored trees are shown instead of generated ones when opened is not empty, see loaders.rs
+//opened trees at the showcase positions, at most four of them
+fn place_opened_trees(opened: Vec<(String,Tree)>, distance: f32) -> (Vec<String>,Vec<Tree>){
+    let positions = showcase_positions(distance);
+    if opened.len()>positions.len(){
+        println!("showing the first {} of {} trees",positions.len(),opened.len());
     }
-    else{
-        wild_type_week_11(&settings)
-    };
-    tree.transformation = Mat4::from_translation(vec3(-d,0.,-d));
-
-    // let branching_angle = PI*0.4;
-    // let divergence_angle = PI*(137./180.);
-    // let segment_length = 2.;
-
-    // tree.update_transformations(divergence_angle,branching_angle,segment_length);
-    trees.push(tree);
-
-    //poles 
-    // let mut tree = pole(40,&settings);
-    // tree.transformation = Mat4::from_translation(vec3(-d,0.,d));
-
-    // let branching_angle = PI*0.4;
-    // let divergence_angle = PI*(137./180.);
-    // let segment_length = 2.;
-
-    // tree.update_transformations(divergence_angle,branching_angle,segment_length);
-    // trees.push(tree);
-
-    // let mut tree = pole_internode_size_active(40,5,&settings);
-    // tree.transformation = Mat4::from_translation(vec3(d,0.,-d));
-
-    // let branching_angle = PI*0.4;
-    // let divergence_angle = PI*(137./180.);
-    // let segment_length = 2.;
-
-    // tree.update_transformations(divergence_angle,branching_angle,segment_length);
-
-    //trees = trees.iter().map(|t|{ Tree::calculate_static_distribution(t,0.001)}).collect();
-    trees
+    opened.into_iter().zip(positions).map(|((name,mut tree),position)|{
+        tree.transformation = Mat4::from_translation(position)*tree.transformation;
+        (name,tree)
+    }).unzip()
 }
 
-pub async fn run_gui_showcase() {
+pub async fn run_gui_showcase(opened: Vec<(String,Tree)>, options: GuiOptions) {
     //SETTINGS.lock().unwrap().segments_amount=40;
     //SETTINGS.lock().unwrap().decay=0.;
     //SETTINGS.lock().unwrap().init_pin=50.;
@@ -114,9 +115,25 @@ pub async fn run_gui_showcase() {
     let mut initial_size = 20;
     let prob = 0.75;
     let mut decapitated = false;
+    #[cfg(not(target_arch = "wasm32"))]
+    let registry = ScenarioRegistry::with_dir(&options.scenarios).unwrap_or_else(|e|{
+        println!("{e}");
+        ScenarioRegistry::builtin()
+    });
+    #[cfg(target_arch = "wasm32")]
+    let registry = ScenarioRegistry::builtin();
+    let mut selected_scenarios = vec!["rnai60".to_string(),"WT".to_string()];
     //let mut tree = pole(40);
     //let mut tree = wild_type_week_11();
-    let mut trees = generate_trees_showcase(80.,decapitated);
+    let mut trees = generate_trees_showcase(80.,&showcase_scenarios(&registry,&selected_scenarios,decapitated),options.seed);
+    //names shown next to the trees, the scenarios or the names of opened trees
+    let mut tree_names = selected_scenarios.clone();
+    //opened trees keep their saved state until resumed
+    let mut paused = false;
+    if !opened.is_empty(){
+        (tree_names,trees) = place_opened_trees(opened, 80.);
+        paused = true;
+    }
     
     // for i in 1..tree.get_size(){
     //     println!("{} {}",tree.nodes[i].main_child,tree.nodes[i].secondary_child);
@@ -173,7 +190,7 @@ pub async fn run_gui_showcase() {
     let mut render_params: RenderParams=Default::default();
     let colors = [Color::new(217, 95, 2,55),Color::new(27, 120, 55,255)];
     let mut instances_datas:Vec<IntancesData> = (&mut trees).iter_mut().enumerate().map(|(_i,t)|{
-        render_params.max_color=colors[_i];
+        render_params.max_color=colors[_i%colors.len()];
 
         IntancesData::from_tree(&context, t, &segment_mesh, &branching_mesh, &bud_mesh, &render_params)
     }).collect();
@@ -193,7 +210,6 @@ pub async fn run_gui_showcase() {
 
     let mut i=0;
     let mut growTree = false;
-    let mut paused = false;
     let mut clicks_positions = vec![];
 
 
@@ -228,6 +244,9 @@ pub async fn run_gui_showcase() {
     let mut pin_production_1:String = SETTINGS.lock().unwrap().pin_production.0.to_string();
     let mut pin_production_2:String = SETTINGS.lock().unwrap().pin_production.1.to_string();
     let mut render_buds=false;
+    let mut seed:String = options.seed.map_or(String::new(), |s| s.to_string());
+    #[cfg(not(target_arch = "wasm32"))]
+    let dialog_dir = format!("{}/",options.output.display());
 
 
     let mut show_plot=false;
@@ -239,24 +258,36 @@ pub async fn run_gui_showcase() {
 
     window.render_loop(move |mut frame_input| {
         let mut new_trees = vec![];
+        let mut steady = !trees.is_empty();
         for tree in &mut trees{
             i+=1;
             let mut new_tree=tree.clone();
             if !paused{
+                let mut difference = 0.;
                 for _ in 0..30{
                     simulation_step+=1;
-                    new_tree=Tree::update_tree_copy(&new_tree);
+                    let next=Tree::update_tree_copy(&new_tree);
+                    if options.precision.is_some(){
+                        difference+=new_tree.tree_difference(&next);
+                    }
+                    new_tree=next;
                 }
+                //scaled to the blocks of 100 iterations of Tree::steady_state
+                steady &= options.precision.is_some_and(|precision| difference*100./30.<=precision);
             }
             if growTree{random_growth(&mut new_tree, prob);}
             //new_tree.update_transformations(divergence_angle, branching_angle, segment_length);
             new_trees.push(new_tree);
         }
         trees = new_trees;
+        if !paused && !growTree && steady{
+            paused = true;
+            println!("steady state after {simulation_step} steps");
+        }
         //print!("{:?}",tree.nodes[0]);
 
         let mut instances_datas:Vec<IntancesData> = trees.iter_mut().enumerate().map(|(_i,t)|{
-            render_params.max_color=colors[_i];
+            render_params.max_color=colors[_i%colors.len()];
             IntancesData::from_tree(&context, t, &segment_mesh, &branching_mesh, &bud_mesh,&render_params)
         }).collect();
         raycast_data = IntancesData::instances_datas_to_positions(&instances_datas);
@@ -320,6 +351,9 @@ pub async fn run_gui_showcase() {
                     // let mut pin_production_1 :String = SETTINGS.lock().unwrap().pin_production.0.to_string();
                     // let mut pin_production_2 :String = SETTINGS.lock().unwrap().pin_production.1.to_string();
                     ui.label(format!("simulation step {simulation_step}"));
+                    ui.label(format!("seeds {:?}",trees.iter().map(|t| t.seed).collect::<Vec<u64>>()));
+                    ui.label("seed (empty for random)");
+                    ui.text_edit_singleline(&mut seed);
                     ui.label("active gain");
                     ui.text_edit_singleline(&mut active_gain);
                     ui.label("dormant gain");
@@ -347,7 +381,29 @@ pub async fn run_gui_showcase() {
                     };
 
 
+                    let mut scenarios_changed = false;
+                    for (i,selected) in selected_scenarios.iter_mut().enumerate(){
+                        egui::ComboBox::from_id_source(format!("scenario {i}")).selected_text(selected.as_str()).show_ui(ui, |ui|{
+                            for name in registry.names(){
+                                if ui.selectable_label(*selected==name, name.as_str()).clicked() && *selected!=name{
+                                    *selected=name;
+                                    scenarios_changed=true;
+                                }
+                            }
+                        });
+                    }
+                    if scenarios_changed{
+                        simulation_step=0;
+                        trees = generate_trees_showcase(80.,&showcase_scenarios(&registry,&selected_scenarios,decapitated),seed.parse().ok());
+                        tree_names = selected_scenarios.clone();
+                    }
                     ui.checkbox(&mut render_params.render_buds, "Show buds");
+                    egui::ComboBox::from_label("Colour by").selected_text(render_params.color_by.name()).show_ui(ui, |ui|{
+                        ui.selectable_value(&mut render_params.color_by, ColorBy::Auxin, ColorBy::Auxin.name());
+                        for scheme in OrderingScheme::ALL{
+                            ui.selectable_value(&mut render_params.color_by, ColorBy::Order(scheme), ColorBy::Order(scheme).name());
+                        }
+                    });
                     if ui.checkbox(&mut decapitated, "Decapitated").clicked() {
                         simulation_step=0;
                         let settings = Settings::global_copy();
@@ -357,7 +413,8 @@ pub async fn run_gui_showcase() {
                         SETTINGS.lock().unwrap().pin_decay=pin_decay.parse::<f32>().unwrap_or(settings.pin_decay);
                         SETTINGS.lock().unwrap().pin_production=(pin_production_1.parse::<f32>().unwrap_or(settings.pin_production.0),pin_production_2.parse::<f32>().unwrap_or(settings.pin_production.1));
 
-                        trees = generate_trees_showcase(80.,decapitated);
+                        trees = generate_trees_showcase(80.,&showcase_scenarios(&registry,&selected_scenarios,decapitated),seed.parse().ok());
+                        tree_names = selected_scenarios.clone();
 
                     };
                     let response = ui.add(egui::Slider::new(&mut render_params.auxin_max, 0. ..=2.));
@@ -379,30 +436,17 @@ pub async fn run_gui_showcase() {
                         SETTINGS.lock().unwrap().pin_decay=pin_decay.parse::<f32>().unwrap_or(settings.pin_decay);
                         SETTINGS.lock().unwrap().pin_production=(pin_production_1.parse::<f32>().unwrap_or(settings.pin_production.0),pin_production_2.parse::<f32>().unwrap_or(settings.pin_production.1));
 
-                        trees = generate_trees_showcase(80.,decapitated);
+                        trees = generate_trees_showcase(80.,&showcase_scenarios(&registry,&selected_scenarios,decapitated),seed.parse().ok());
+                        tree_names = selected_scenarios.clone();
                     }
                     ui.checkbox(&mut show_plot, "Show plots");
                     if show_plot{
                         
                         ui.radio_value(&mut plot_type, PlotType::Auxin, "Auxin");
                         ui.radio_value(&mut plot_type, PlotType::PIN, "Pin");
-                        for (tree,name) in trees.iter().zip(["Kantarelli","Wild Type","Pole"]){
-                            let values = tree.main_stem_values();
-                            let mut first_peak = 0.;
-                            for i in 0..tree.settings.segments_amount{
-                                if (first_peak<values[i as usize].0){
-                                    first_peak=values[i as usize].0;
-                                }
-                            }
-                            let mut last_peak=0.;
-                            for _i in 0..tree.settings.segments_amount{
-                                let i = values.len() as i32-1-_i;
-                                if (last_peak<values[i as usize].0){
-                                    last_peak=values[i as usize].0;
-                                }
-                            }
-                            let ratio = (last_peak-first_peak)/(first_peak+0.001);
-                            Window::new(name).default_size(vec2(200.,400.)).show( gui_context, |ui| {
+                        for (tree,name) in trees.iter().zip(tree_names.iter()){
+                            let ratio = crate::metrics::Profile::new(tree).peak_ratio();
+                            Window::new(name.as_str()).default_size(vec2(200.,400.)).show( gui_context, |ui| {
                                 ui.label(format!("{ratio:.2}"));
                                 let values = tree.main_stem_values();
                                 let auxin: plot::PlotPoints = (0..values.len()).map(|i| {
@@ -425,18 +469,66 @@ pub async fn run_gui_showcase() {
                         }
                     }
                     #[cfg(not(target_arch = "wasm32"))]{
+                    if ui.button("open").clicked(){
+                        //saved trees (.json, .pickle, .mtg, .lsys, QSM .txt/.csv) or the manifest.json of a run
+                        if let Some(path) = open_file_dialog("open trees", &dialog_dir, None){
+                            match crate::loaders::load_trees(std::path::Path::new(&path), &Settings::global_copy(), None){
+                                Ok(opened) if !opened.is_empty() => {
+                                    simulation_step=0;
+                                    paused=true;
+                                    (tree_names,trees) = place_opened_trees(opened, 80.);
+                                }
+                                Ok(_) => println!("{path}: no trees"),
+                                Err(e) => println!("{e}"),
+                            }
+                        }
+                    }
                     if ui.button("save result").clicked(){
                         //let result=save_file_dialog("file location", "./");
-                        if let Some(path) =save_file_dialog("file location", "./"){
+                        if let Some(path) =save_file_dialog("file location", &dialog_dir){
                             println!("{path}");
-                            for (tree,name) in trees.iter().zip(["Kantarelli","Wild Type","Pole"]){
-                                let values = tree.main_stem_values();
+                            for (tree,name) in trees.iter().zip(tree_names.iter()){
                                 let mut wtr = Writer::from_path(format!("{path} {name}.csv")).unwrap();
                                 wtr.write_record(&["internode","Auxin","PIN"]).unwrap();
-                                for (i,(a,b)) in values.iter().enumerate(){
-                                    wtr.write_record(&[format!("{}",(i as i32 + tree.settings.segments_amount-tree.nodes[tree.get_oringal_tip_index() as usize].segments.len() as i32-1)/tree.settings.segments_amount+1),format!("{a:.5}"),format!("{b:.5}")]).unwrap();
+                                for (internode,a,b) in tree.main_stem_internodes(){
+                                    wtr.write_record(&[format!("{internode}"),format!("{a:.5}"),format!("{b:.5}")]).unwrap();
                                 }
                                 wtr.flush().unwrap();
+                                let mut table = crate::table::CompartmentTable::new();
+                                table.add_tree(name, 0, tree, &render_params.geometry_params());
+                                if let Err(e) = table.write_csv(std::path::Path::new(&format!("{path} {name}.compartments.csv"))){
+                                    println!("{e}");
+                                }
+                                let mtg_path = format!("{path} {name}.mtg");
+                                if let Err(e) = crate::mtg::save_mtg(std::path::Path::new(&mtg_path), tree, &render_params.geometry_params(), &Default::default()){
+                                    println!("{e}");
+                                }
+                            }
+                            let rows: Vec<(String,&Tree)> = tree_names.iter().cloned().zip(trees.iter()).collect();
+                            if let Err(e) = crate::metrics::write_metrics_table(std::path::Path::new(&format!("{path} metrics.csv")), &rows){
+                                println!("{e}");
+                            }
+                        }
+                    }
+                    if ui.button("export mesh").clicked(){
+                        //format from the extension: .obj, .ply, .gltf or .vtp
+                        if let Some(path) =save_file_dialog("mesh file (.obj, .ply, .gltf, .vtp)", &format!("{dialog_dir}tree.gltf")){
+                            let params = crate::mesh::MeshParams {
+                                geometry: render_params.geometry_params(),
+                                color_by: render_params.color_by,
+                                auxin_max: render_params.auxin_max,
+                                color_exp: render_params.color_exp,
+                                buds: render_params.render_buds,
+                                ..Default::default()
+                            };
+                            let path = std::path::Path::new(&path);
+                            for (tree,name) in trees.iter().zip(tree_names.iter()){
+                                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                                let extension = path.extension().unwrap_or_default().to_string_lossy();
+                                let tree_path = path.with_file_name(format!("{stem} {name}.{extension}"));
+                                if let Err(e) = crate::mesh::save_mesh(&tree_path, tree, &params){
+                                    println!("{e}");
+                                }
                             }
                         }
                     }
@@ -2,18 +2,16 @@ use std::{f32::consts::PI, iter::Enumerate};
 
 use three_d::*;
 use crate::vec_tree::*;
+use crate::ordering::*;
+use crate::geometry::*;
+use crate::topology::*;
 
 //pub struct 
 
 fn segment_color(max_color:Color,auxin_level: f32, render_params:&RenderParams) -> Color{
-    let white = vec3(1.,1.,1.);
-    let blue = vec3(0.0,0.,1.);
     //let blue: Vec3 = max_color.to_vec3();
     //println!("{blue:?}");
-    let maximum=render_params.auxin_max;
-    let coeff = (auxin_level.clamp(0., maximum)/maximum).powf(render_params.color_exp);
-    let c = white*(1.-coeff)+blue*coeff;
-    Color::from_rgb_slice(&[c.x,c.y,c.z])
+    Color::from_rgb_slice(&auxin_color(auxin_level, render_params.auxin_max, render_params.color_exp))
 }
 fn branching_color(max_color:Color,auxin_level: f32, render_params:&RenderParams) -> Color{
     segment_color(max_color,auxin_level, render_params)
@@ -28,7 +26,8 @@ pub struct RenderParams{
     pub branching_angle: f32,
     pub divergence_angle: f32,
     pub segment_length:f32,
-    pub max_color: Color
+    pub max_color: Color,
+    pub color_by: ColorBy,
 }
 impl Default for RenderParams{
     fn default() -> Self {
@@ -41,10 +40,29 @@ impl Default for RenderParams{
             divergence_angle: PI*(137.5/180.),
             segment_length: 2.,
             max_color: Color::from_rgb_slice(&[1.,0.,0.]),
+            color_by: ColorBy::Auxin,
         }
     }
 }
 
+impl RenderParams{
+    pub fn geometry_params(&self) -> GeometryParams{
+        GeometryParams {
+            divergence_angle: self.divergence_angle,
+            branching_angle: self.branching_angle,
+            segment_length: self.segment_length,
+            initial_width: self.initial_width,
+            order_width_influence: self.order_width_influence,
+        }
+    }
+}
+
+//instance transformation of a measured compartment, meshes are 2 units long and end at the origin
+fn compartment_transformation(compartment: &CompartmentGeometry) -> Mat4{
+    Mat4::from_translation(compartment.end)*frame(compartment.end-compartment.start)*
+    Mat4::from_nonuniform_scale(compartment.width,compartment.length()/2.,compartment.width)
+}
+
 pub struct IntancesData{
     segment_instances: Instances,
     branching_instances: Instances,
@@ -72,6 +90,16 @@ impl IntancesData{
 
         tree.update_transformations(render_params.divergence_angle, render_params.branching_angle, 
             render_params.segment_length, render_params.initial_width, render_params.order_width_influence);
+        let ordering = match render_params.color_by{
+            ColorBy::Auxin => None,
+            ColorBy::Order(scheme) => Some(tree.orders(scheme)),
+        };
+        //measured trees are drawn from their cylinders
+        let geometry = if tree.measured {Some(TreeGeometry::new(tree, &render_params.geometry_params()))} else {None};
+        let color = |node: usize, auxin: f32| match &ordering{
+            None => segment_color(render_params.max_color,auxin,render_params),
+            Some(ordering) => Color::from_rgb_slice(&order_color(ordering.orders()[node])),
+        };
         for i in 0..tree.get_size(){
             let mut transformation = tree.transformation*tree.nodes[i].transformation;    
 
@@ -80,17 +108,23 @@ impl IntancesData{
 
        
             for (j,segment) in tree.nodes[i].segments.iter().enumerate(){
-                transformation = transformation*Mat4::from_translation(vec3(0.,2.,0.));
+                transformation = match &geometry{
+                    Some(geometry) => compartment_transformation(&geometry.node(NodeId(i))[j]),
+                    None => transformation*Mat4::from_translation(vec3(0.,2.,0.)),
+                };
                 segments_transformations.push(transformation);
-                segments_colors.push(segment_color(render_params.max_color,segment.data.auxin,render_params));
+                segments_colors.push(color(i,segment.data.auxin));
                 objects_position_index.push(((transformation*vec4(0.,0.,0.,1.)).truncate(),i as i32,j as i32));
             }
-            transformation = transformation*Mat4::from_translation(vec3(0.,2.,0.));
+            transformation = match &geometry{
+                Some(geometry) => compartment_transformation(geometry.node(NodeId(i)).last().unwrap()),
+                None => transformation*Mat4::from_translation(vec3(0.,2.,0.)),
+            };
             objects_position_index.push(((transformation*vec4(0.,0.,0.,1.)).truncate(),i as i32,-1));
             match tree.nodes[i].bud_state{
                 BudState::BranchingSegment => {
                     branching_transformations.push(transformation);
-                    branching_colors.push(segment_color(render_params.max_color,tree.nodes[i].data.auxin,render_params));
+                    branching_colors.push(color(i,tree.nodes[i].data.auxin));
 
                 },
                 BudState::ActiveBud => {
@@ -8,6 +8,30 @@ mod instance_data;
 #[cfg(target_arch = "wasm32")]
 mod model_functions;
 #[cfg(target_arch = "wasm32")]
+mod topology;
+#[cfg(target_arch = "wasm32")]
+mod validation;
+#[cfg(target_arch = "wasm32")]
+mod ordering;
+#[cfg(target_arch = "wasm32")]
+mod geometry;
+#[cfg(target_arch = "wasm32")]
+mod measured;
+#[cfg(target_arch = "wasm32")]
+mod lsystem;
+#[cfg(target_arch = "wasm32")]
+mod treatments;
+#[cfg(target_arch = "wasm32")]
+mod growth;
+#[cfg(target_arch = "wasm32")]
+mod scenario;
+#[cfg(target_arch = "wasm32")]
+mod expr;
+#[cfg(target_arch = "wasm32")]
+mod growth_program;
+#[cfg(target_arch = "wasm32")]
+mod metrics;
+#[cfg(target_arch = "wasm32")]
 mod gui_run;
 
 // Entry point for wasm
@@ -24,6 +48,6 @@ pub async fn start() -> Result<(), JsValue> {
     info!("eeeeee");
 
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-    gui_run::run_gui_showcase().await;
+    gui_run::run_gui_showcase(vec![], gui_run::GuiOptions::default()).await;
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,228 @@
+use std::path::Path;
+
+use crate::vec_tree::*;
+use crate::results::*;
+use crate::qsm::*;
+use crate::mtg::*;
+use crate::lsystem::*;
+
+//trees read back from the saved formats, named for the viewer:
+//.json - a serde_json Tree or list of trees
+//.pickle/.pkl - trees pickled by save_tree, other extensions and files without one are tried as pickles
+//.mtg - MTG, see mtg.rs
+//.lsys - bracketed L-system string, see lsystem.rs
+//.txt/.csv - QSM cylinder table, see qsm.rs
+//a run directory of the results layout or its manifest.json - the replicates of the run, from trees.pickle when it was saved,
+//otherwise grown again from the scenario, settings and seeds in the manifest and brought to the steady state
+//settings are used for the formats that do not store them
+
+fn read(path: &Path) -> Result<Vec<u8>,String>{
+    std::fs::read(path).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+//replicates with the given indices, all when None
+fn select(trees: Vec<Tree>, replicates: Option<&[usize]>, name: &str) -> Result<Vec<(String,Tree)>,String>{
+    let count = trees.len();
+    let mut named: Vec<Option<Tree>> = trees.into_iter().map(Some).collect();
+    let indices: Vec<usize> = replicates.map_or_else(|| (0..count).collect(), |r| r.to_vec());
+    let single = count==1 && replicates.is_none();
+    indices.into_iter().map(|i|{
+        let tree = named.get_mut(i).and_then(|t| t.take()).ok_or(format!("{name}: no replicate {i} in {count} trees"))?;
+        Ok((if single {name.to_string()} else {format!("{name} #{i}")},tree))
+    }).collect()
+}
+
+fn load_pickle(path: &Path) -> Result<Vec<Tree>,String>{
+    let bytes = read(path)?;
+    let error = |e: serde_pickle::Error| format!("{}: {e}",path.display());
+    let trees = match serde_pickle::from_slice::<Vec<Tree>>(&bytes, serde_pickle::DeOptions::new()){
+        Ok(trees) => trees,
+        Err(_) => vec![serde_pickle::from_slice::<Tree>(&bytes, serde_pickle::DeOptions::new()).map_err(error)?],
+    };
+    trees.into_iter().map(|t| t.repaired().map_err(|e| format!("{}: {e}",path.display()))).collect()
+}
+
+fn load_json(path: &Path) -> Result<Vec<Tree>,String>{
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+    let trees = match serde_json::from_str::<Vec<Tree>>(&text){
+        Ok(trees) => trees,
+        Err(_) => vec![serde_json::from_str::<Tree>(&text).map_err(|e| format!("{}: {e}",path.display()))?],
+    };
+    trees.into_iter().map(|t| t.repaired().map_err(|e| format!("{}: {e}",path.display()))).collect()
+}
+
+//replicates of a run of the results layout
+pub fn load_run(dir: &Path, replicates: Option<&[usize]>) -> Result<Vec<(String,Tree)>,String>{
+    let manifest = ResultsDir::read_manifest(&dir.join(MANIFEST_FILE))?;
+    let name = manifest.scenario.name.clone();
+    if let Some(file) = manifest.files.get("trees"){
+        return select(load_pickle(&dir.join(file))?, replicates, &name);
+    }
+    let indices: Vec<usize> = replicates.map_or_else(|| (0..manifest.replicates.len()).collect(), |r| r.to_vec());
+    indices.into_iter().map(|i|{
+        let record = manifest.replicates.get(i).ok_or(format!("{}: no replicate {i} in {} replicates",dir.display(),manifest.replicates.len()))?;
+        let tree = manifest.scenario.build(&manifest.settings, record.seed)?;
+        let (tree,_) = Tree::steady_state(&tree, manifest.precision, manifest.max_iterations);
+        Ok((format!("{name} #{}",record.replicate),tree))
+    }).collect()
+}
+
+pub fn load_trees(path: &Path, settings: &Settings, replicates: Option<&[usize]>) -> Result<Vec<(String,Tree)>,String>{
+    if path.is_dir(){
+        return load_run(path, replicates);
+    }
+    if path.file_name().is_some_and(|n| n==MANIFEST_FILE){
+        return load_run(path.parent().unwrap_or(Path::new(".")), replicates);
+    }
+    let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+    let trees = match extension.as_str(){
+        "json" => load_json(path)?,
+        "pickle" | "pkl" => load_pickle(path)?,
+        "mtg" => vec![load_mtg(path, settings, &MtgOptions::default())?],
+        "lsys" => vec![load_lsystem(path, settings)?],
+        "txt" | "csv" => vec![load_qsm(path, settings, &QsmOptions::default())?],
+        //save_tree used to write pickles without an extension, named by their settings, e.g. "active_gain 0.700 ... pin_production_2 0.06"
+        _ => {
+            let trees = load_pickle(path).map_err(|e| format!("{e}, unknown tree format"))?;
+            return select(trees, replicates, &path.file_name().unwrap_or_default().to_string_lossy());
+        }
+    };
+    select(trees, replicates, &name)
+}
+
+#[cfg(test)]
+mod tests{
+    use serde::Serialize;
+    use serde_pickle::SerOptions;
+    use three_d::Mat4;
+    use super::*;
+    use crate::scenario::*;
+    use crate::topology::*;
+
+    //Tree as pickled by save_tree before node indices became NodeId
+    #[derive(Serialize)]
+    struct LegacyData{
+        order: i32,
+        age: i32,
+        auxin: f32,
+        strigolactin: f32,
+        pin: f32,
+        auxin_flow: f32,
+    }
+    #[derive(Serialize)]
+    struct LegacySegment{
+        data: LegacyData,
+    }
+    #[derive(Serialize)]
+    struct LegacyNode{
+        bud_state: BudState,
+        index: i32,
+        parent: i32,
+        order: i32,
+        initial_order: i32,
+        main_child: i32,
+        secondary_child: i32,
+        data: LegacyData,
+        #[serde(with = "Matrix4Def")]
+        transformation: Mat4,
+        segments: Vec<LegacySegment>,
+        segments_amount: i32,
+        settings: Settings,
+    }
+    #[derive(Serialize)]
+    struct LegacyTree{
+        tip_indices: Vec<i32>,
+        decapitated_tip_index: Option<i32>,
+        segments_amount: i32,
+        #[serde(with = "Matrix4Def")]
+        transformation: Mat4,
+        settings: Settings,
+        nodes: Vec<LegacyNode>,
+        orders_indexed: Vec<Vec<usize>>,
+    }
+
+    fn legacy_data(data: &Data) -> LegacyData{
+        LegacyData { order: data.order, age: data.age, auxin: data.auxin, strigolactin: data.strigolactin, pin: data.pin, auxin_flow: data.auxin_flow }
+    }
+    fn legacy(tree: &Tree) -> LegacyTree{
+        let index = |id: Option<NodeId>| id.map_or(-1, |id| id.index() as i32);
+        LegacyTree {
+            tip_indices: tree.tip_indices.iter().map(|id| id.index() as i32).collect(),
+            decapitated_tip_index: tree.decapitated_tip_index.map(|id| id.index() as i32),
+            segments_amount: tree.segments_amount,
+            transformation: tree.transformation,
+            settings: tree.settings.clone(),
+            nodes: tree.nodes.iter().map(|node| LegacyNode {
+                bud_state: node.bud_state.clone(),
+                index: node.index.index() as i32,
+                parent: index(node.parent),
+                order: node.order,
+                initial_order: node.initial_order,
+                main_child: index(node.main_child),
+                secondary_child: index(node.secondary_child),
+                data: legacy_data(&node.data),
+                transformation: node.transformation,
+                segments: node.segments.iter().map(|s| LegacySegment { data: legacy_data(&s.data) }).collect(),
+                segments_amount: node.segments_amount,
+                settings: node.settings.clone(),
+            }).collect(),
+            orders_indexed: tree.orders_indexed.iter().map(|o| o.iter().map(|id| id.index()).collect()).collect(),
+        }
+    }
+
+    fn grown() -> Tree{
+        ScenarioRegistry::builtin().get("WT").unwrap().build(&Settings::global_copy(), 1).unwrap()
+    }
+
+    fn assert_same_topology(loaded: &Tree, tree: &Tree){
+        assert_eq!(loaded.nodes.len(),tree.nodes.len());
+        for (a,b) in loaded.nodes.iter().zip(&tree.nodes){
+            assert_eq!((a.index,a.parent,a.main_child,a.secondary_child),(b.index,b.parent,b.main_child,b.secondary_child));
+        }
+        assert_eq!(loaded.tip_indices,tree.tip_indices);
+        assert!(loaded.nodes[0].parent.is_none());
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf{
+        let dir = std::env::temp_dir().join(format!("tree_d_{name}_{}",std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn legacy_pickle_without_extension(){
+        let tree = grown();
+        let dir = temp_dir("legacy_pickle");
+        let path = dir.join("active_gain 0.700 dormant_gain 0.120 decay 0.155 pin_decay 0.050 pin_production_1 1.000 pin_production_2 0.06");
+        let pickle = serde_pickle::to_vec(&vec![legacy(&tree),legacy(&tree)], SerOptions::new().proto_v2()).unwrap();
+        std::fs::write(&path, pickle).unwrap();
+        let loaded = load_trees(&path, &Settings::global_copy(), None);
+        std::fs::remove_dir_all(&dir).ok();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.len(),2);
+        assert!(loaded[0].0.starts_with("active_gain 0.700"));
+        assert_same_topology(&loaded[0].1, &tree);
+    }
+
+    #[test]
+    fn legacy_json(){
+        let tree = grown();
+        let dir = temp_dir("legacy_json");
+        let path = dir.join("tree.json");
+        std::fs::write(&path, serde_json::to_string(&legacy(&tree)).unwrap()).unwrap();
+        let loaded = load_trees(&path, &Settings::global_copy(), None);
+        std::fs::remove_dir_all(&dir).ok();
+        assert_same_topology(&loaded.unwrap()[0].1, &tree);
+    }
+
+    #[test]
+    fn unknown_files_are_errors(){
+        let dir = temp_dir("unknown");
+        let path = dir.join("notes.md");
+        std::fs::write(&path, "not a tree").unwrap();
+        let loaded = load_trees(&path, &Settings::global_copy(), None);
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(loaded.unwrap_err().contains("unknown tree format"));
+    }
+}
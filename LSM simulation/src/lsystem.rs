@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::measured::*;
+
+//trees as bracketed L-system strings, e.g. I(0.1,1)I(0.1,1)X(0.2,1.1,0)[B(dormant,0.1,1,1)]A(0.3,1,0)
+//I(auxin,pin) - segment
+//X(auxin,pin,order,initial_order) - node compartment of a branching node, followed by its lateral in brackets and the main child
+//A(auxin,pin,order,initial_order) - active bud
+//B(state,auxin,pin,order,initial_order) - bud, state is dormant, active, branching or decapitated
+//K(auxin,pin,order,initial_order) - decapitated node
+//arguments are optional, missing or empty values take the initial auxin and PIN and the Gravelius order, e.g. I(,0.5) has PIN 0.5
+//a lateral in brackets is borne by the module before it, so I[...] works like X[...] and X without a lateral is read as I
+//other modules (turtle commands such as F + - & ^ / \ |) are skipped with their arguments
+
+fn state_name(state: &BudState) -> &'static str{
+    match state{
+        BudState::DormantBud => "dormant",
+        BudState::ActiveBud => "active",
+        BudState::BranchingSegment => "branching",
+        BudState::DecapitatedSegment => "decapitated",
+    }
+}
+fn state_from_name(name: &str) -> Option<BudState>{
+    [BudState::DormantBud,BudState::ActiveBud,BudState::BranchingSegment,BudState::DecapitatedSegment]
+        .into_iter().find(|s| state_name(s)==name || format!("{s:?}")==name)
+}
+
+fn module(symbol: char, data: &Data, node: Option<&Node>) -> String{
+    let mut arguments = vec![];
+    if let Some(node) = node.filter(|_| symbol=='B'){
+        arguments.push(state_name(&node.bud_state).to_string());
+    }
+    arguments.extend([data.auxin.to_string(),data.pin.to_string()]);
+    if let Some(node) = node{
+        arguments.extend([node.order.to_string(),node.initial_order.to_string()]);
+    }
+    format!("{symbol}({})",arguments.join(","))
+}
+
+fn write_axis(tree: &Tree, first: NodeId, text: &mut String){
+    for id in tree.axis(first){
+        let node = &tree[id];
+        for segment in &node.segments{
+            *text += &module('I', &segment.data, None);
+        }
+        let symbol = match node.bud_state{
+            BudState::BranchingSegment => 'X',
+            BudState::ActiveBud => 'A',
+            BudState::DormantBud => 'B',
+            BudState::DecapitatedSegment => 'K',
+        };
+        *text += &module(symbol, &node.data, Some(node));
+        if let Some(lateral) = node.secondary_child{
+            text.push('[');
+            write_axis(tree, lateral, text);
+            text.push(']');
+        }
+    }
+}
+
+pub fn write_lsystem(tree: &Tree) -> String{
+    let mut text = String::new();
+    write_axis(tree, tree.root(), &mut text);
+    text
+}
+
+pub fn read_lsystem(text: &str, settings: &Settings) -> Result<Tree,String>{
+    let mut chars = text.chars().filter(|c| !c.is_whitespace()).peekable();
+    let mut compartments: Vec<MeasuredCompartment> = vec![];
+    //compartment the next module grows from, and whether it starts a lateral
+    let mut current: Option<usize> = None;
+    let mut lateral = false;
+    let mut stack: Vec<Option<usize>> = vec![];
+    while let Some(symbol) = chars.next(){
+        match symbol{
+            '[' => {
+                if current.is_none(){
+                    return Err("branch before the first module".to_string());
+                }
+                stack.push(current);
+                lateral = true;
+                continue;
+            }
+            ']' => {
+                current = stack.pop().ok_or("unbalanced ]")?;
+                lateral = false;
+                continue;
+            }
+            _ => {}
+        }
+        let mut arguments = vec![];
+        if chars.peek()==Some(&'('){
+            chars.next();
+            let mut argument = String::new();
+            loop{
+                match chars.next(){
+                    Some(')') => break,
+                    Some(',') => arguments.push(std::mem::take(&mut argument)),
+                    Some(c) => argument.push(c),
+                    None => return Err(format!("unclosed arguments of {symbol}")),
+                }
+            }
+            arguments.push(argument);
+        }
+        if !"IXABK".contains(symbol){
+            continue;
+        }
+        let bud_state = match symbol{
+            'I' => None,
+            'X' => Some(BudState::BranchingSegment),
+            'A' => Some(BudState::ActiveBud),
+            'K' => Some(BudState::DecapitatedSegment),
+            _ => {
+                let state = if arguments.is_empty() {String::new()} else {arguments.remove(0)};
+                let state = if state.is_empty() {"dormant"} else {&state};
+                Some(state_from_name(state).ok_or(format!("unknown bud state {state}"))?)
+            }
+        };
+        //empty arguments keep the position of the following ones and take the default value
+        let values = arguments.iter().map(|a| if a.is_empty() {Ok(None)} else {
+            a.parse::<f32>().map(Some).map_err(|_| format!("invalid argument {a} of {symbol}"))
+        }).collect::<Result<Vec<Option<f32>>,String>>()?;
+        let value = |i: usize| values.get(i).copied().flatten();
+        let data = (value(0).is_some() || value(1).is_some()).then(|| Data::new(0, value(0).unwrap_or(settings.init_auxin), value(1).unwrap_or(settings.init_pin)));
+        let orders = value(2).map(|order| (order as i32, value(3).map_or(order as i32, |o| o as i32)));
+        compartments.push(MeasuredCompartment { cylinder: None, parent: current, lateral, data, bud_state, orders });
+        current = Some(compartments.len()-1);
+        lateral = false;
+    }
+    if !stack.is_empty(){
+        return Err("unbalanced [".to_string());
+    }
+    if compartments.is_empty(){
+        return Err("L-system string has no modules".to_string());
+    }
+    tree_from_measured(&compartments, settings).map_err(|e| format!("L-system: {e}"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_lsystem(path: &Path, settings: &Settings) -> Result<Tree,String>{
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+    read_lsystem(&text, settings).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn save_lsystem(path: &Path, tree: &Tree) -> Result<(),String>{
+    std::fs::write(path, write_lsystem(tree)).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn empty_arguments_take_defaults(){
+        let settings = Settings { init_auxin: 3., init_pin: 2., ..Settings::default() };
+        let tree = read_lsystem("I(,0.5)I(0.25)I()X(,,1)[B(,0.75)]A", &settings).unwrap();
+        let root = &tree[tree.root()];
+        let values: Vec<(f32,f32)> = root.segments.iter().map(|s| (s.data.auxin,s.data.pin)).collect();
+        assert_eq!(values,[(3.,0.5),(0.25,2.),(3.,2.)]);
+        assert_eq!(root.order,1);
+        let bud = &tree[root.secondary_child.unwrap()];
+        assert!(matches!(bud.bud_state,BudState::DormantBud));
+        assert_eq!((bud.data.auxin,bud.data.pin),(0.75,2.));
+    }
+
+    #[test]
+    fn invalid_arguments(){
+        assert!(read_lsystem("I(0.1,x)A", &Settings::default()).unwrap_err().contains("invalid argument x of I"));
+        assert!(read_lsystem("IB(sleeping)", &Settings::default()).unwrap_err().contains("unknown bud state"));
+        assert!(read_lsystem("I(0.1", &Settings::default()).is_err());
+    }
+
+    #[test]
+    fn written_trees_read_back(){
+        let text = "I(0.1,1)I(0.2,1)X(0.3,1.5,0,0)[I(0.4,1)A(0.5,1,1,1)]I(0.6,1)A(0.7,1,0,0)";
+        let tree = read_lsystem(text, &Settings::default()).unwrap();
+        assert_eq!(read_lsystem(&write_lsystem(&tree), &Settings::default()).map(|t| write_lsystem(&t)),Ok(write_lsystem(&tree)));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::ordering::*;
+
+//building trees from measured compartment graphs, shared by the QSM and MTG importers
+
+//one measured compartment, linked to the compartment it grows from
+#[derive(Clone,Debug)]
+pub(crate) struct MeasuredCompartment{
+    //None when the file has no geometry, the tree is then drawn from the model shape
+    pub cylinder: Option<Cylinder>,
+    pub parent: Option<usize>,
+    //starts a new axis instead of continuing the axis of its parent
+    pub lateral: bool,
+    //hormone state read from the file, default values otherwise
+    pub data: Option<Data>,
+    //state of the node when the compartment ends an axis (e.g. decapitated or dormant)
+    pub bud_state: Option<BudState>,
+    //current and initial order of the node when the compartment is a node compartment, Gravelius order otherwise
+    pub orders: Option<(i32,i32)>,
+}
+
+struct Builder<'a>{
+    compartments: &'a [MeasuredCompartment],
+    settings: &'a Settings,
+    nodes: Vec<Node>,
+    orders: Vec<Option<(i32,i32)>>,
+}
+impl<'a> Builder<'a>{
+    fn add_node(&mut self, parent: Option<NodeId>, main: bool) -> NodeId{
+        let id = NodeId(self.nodes.len());
+        self.nodes.push(Node::new(id, parent, 0, self.settings.segments_amount, BudState::ActiveBud, self.settings.clone()));
+        self.orders.push(None);
+        if let Some(parent) = parent{
+            let parent = &mut self.nodes[parent.0];
+            if main {parent.main_child=Some(id)} else {parent.secondary_child=Some(id)}
+        }
+        id
+    }
+    fn segment(&self, compartment: usize) -> Segment{
+        let mut segment = Segment::new(0, self.settings.init_auxin, self.settings.init_pin);
+        segment.cylinder = self.compartments[compartment].cylinder;
+        if let Some(data) = &self.compartments[compartment].data{
+            segment.data = data.clone();
+        }
+        segment
+    }
+    fn set_node_compartment(&mut self, node: NodeId, compartment: usize){
+        let measured = &self.compartments[compartment];
+        self.orders[node.0] = measured.orders;
+        let node = &mut self.nodes[node.0];
+        node.cylinder = measured.cylinder;
+        if let Some(data) = &measured.data{
+            node.data = data.clone();
+        }
+    }
+    //zero length compartment at the end of the compartment, used where more than one branch leaves it
+    fn junction(&self, compartment: usize) -> Option<Cylinder>{
+        self.compartments[compartment].cylinder.map(|c| Cylinder { start: c.end, end: c.end, radius: c.radius })
+    }
+}
+
+//compartments become segments, the compartment where branches leave becomes the node compartment of a BranchingSegment
+//and the last compartment of every axis the compartment of an ActiveBud (or the bud state given in the file)
+pub(crate) fn tree_from_measured(compartments: &[MeasuredCompartment], settings: &Settings) -> Result<Tree,String>{
+    let mut children: Vec<Vec<usize>> = vec![vec![]; compartments.len()];
+    let mut roots = vec![];
+    for (i,compartment) in compartments.iter().enumerate(){
+        match compartment.parent{
+            Some(parent) if parent<compartments.len() => children[parent].push(i),
+            Some(parent) => return Err(format!("compartment {} has missing parent {}",i+1,parent+1)),
+            None => roots.push(i),
+        }
+    }
+    let root = match roots.as_slice(){
+        [root] => *root,
+        [] => return Err("no base compartment".to_string()),
+        _ => return Err(format!("{} compartments without a parent",roots.len())),
+    };
+    let continuation = |i: usize| children[i].iter().copied().find(|&c| !compartments[c].lateral);
+
+    let mut builder = Builder { compartments, settings, nodes: vec![], orders: vec![] };
+    //first compartment of an axis with the node it grows from
+    let mut axes: Vec<(usize,Option<NodeId>)> = vec![(root,None)];
+    let mut visited = 0;
+    while let Some((first,parent)) = axes.pop(){
+        let mut node = builder.add_node(parent, false);
+        let mut compartment = first;
+        loop{
+            visited+=1;
+            if visited>compartments.len(){
+                return Err("parent links contain a cycle".to_string());
+            }
+            let next = continuation(compartment);
+            let laterals: Vec<usize> = children[compartment].iter().copied().filter(|&c| Some(c)!=next).collect();
+            if laterals.is_empty() && next.is_some(){
+                let segment = builder.segment(compartment);
+                builder.nodes[node.0].segments.push(segment);
+                compartment = next.unwrap();
+                continue;
+            }
+            builder.set_node_compartment(node, compartment);
+            let state = compartments[compartment].bud_state.clone();
+            if laterals.is_empty(){
+                if let Some(state) = state.filter(|s| *s!=BudState::BranchingSegment){
+                    builder.nodes[node.0].bud_state = state;
+                }
+                break;
+            }
+            //a node ending its axis with a given state (e.g. decapitated) keeps its lateral without getting a new tip
+            let ending = match &state{
+                Some(state) if next.is_none() && laterals.len()==1 && *state!=BudState::BranchingSegment => Some(state.clone()),
+                _ => None,
+            };
+            builder.nodes[node.0].bud_state = ending.clone().unwrap_or(BudState::BranchingSegment);
+            let mut junction = node;
+            for (k,lateral) in laterals.into_iter().enumerate(){
+                if k>0{
+                    junction = builder.add_node(Some(junction), true);
+                    builder.nodes[junction.0].bud_state = BudState::BranchingSegment;
+                    builder.nodes[junction.0].cylinder = builder.junction(compartment);
+                }
+                axes.push((lateral,Some(junction)));
+            }
+            if ending.is_some(){
+                break;
+            }
+            node = builder.add_node(Some(junction), true);
+            match next{
+                Some(next) => compartment = next,
+                None => {
+                    builder.nodes[node.0].cylinder = builder.junction(compartment);
+                    break;
+                }
+            }
+        }
+    }
+
+    //internodes are as long as the longest measured one
+    let mut settings = settings.clone();
+    settings.segments_amount = builder.nodes.iter().map(|n| n.segments.len() as i32+1).max().unwrap_or(1).max(2);
+    let Builder { nodes, orders: given_orders, .. } = builder;
+    let mut tree = Tree::with_seed(&settings, 0);
+    tree.nodes = nodes;
+    tree.measured = compartments.iter().all(|c| c.cylinder.is_some());
+    for node in &mut tree.nodes{
+        node.segments_amount = settings.segments_amount;
+        node.settings = settings.clone();
+    }
+    let orders = tree.orders(OrderingScheme::Gravelius);
+    for id in tree.node_ids().collect::<Vec<_>>(){
+        let (order,initial_order) = given_orders[id.0].unwrap_or((orders.order(id),orders.order(id)));
+        let node = &mut tree[id];
+        node.order = order;
+        node.initial_order = initial_order;
+        node.data.order = order;
+        for segment in &mut node.segments{
+            segment.data.order = order;
+        }
+    }
+    //a main tip that is not an active bud is dropped from the tips by repair
+    let main_tip = tree.find_main_tip(tree.root());
+    tree.tip_indices = vec![main_tip];
+    if tree[main_tip].bud_state==BudState::DecapitatedSegment{
+        tree.decapitated_tip_index = Some(main_tip);
+    }
+    let violations = tree.repair();
+    if !violations.is_empty(){
+        return Err(violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n"));
+    }
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests{
+    use crate::vec_tree::*;
+    use crate::geometry::*;
+    use crate::mtg::*;
+    use crate::metrics::*;
+    use crate::scenario::*;
+
+    #[test]
+    fn decapitated_tree_through_mtg(){
+        let scenario = ScenarioRegistry::builtin().get("WT_decapitated").cloned().unwrap();
+        let tree = scenario.build(&Settings::default(), 3).unwrap();
+        let original_tip = tree.get_oringal_tip_index();
+        assert_eq!(tree[original_tip].bud_state,BudState::DecapitatedSegment);
+        let path = std::env::temp_dir().join(format!("tree_d_decapitated_{}.mtg",std::process::id()));
+        save_mtg(&path, &tree, &GeometryParams::default(), &MtgOptions::default()).unwrap();
+        let loaded = load_mtg(&path, &Settings::default(), &MtgOptions::default());
+        std::fs::remove_file(&path).ok();
+        let loaded = loaded.unwrap();
+        let tip = loaded.get_oringal_tip_index();
+        assert_eq!(loaded.decapitated_tip_index,Some(tip));
+        assert_eq!(loaded[tip].bud_state,BudState::DecapitatedSegment);
+        assert_eq!(Profile::new(&loaded).values.len(),Profile::new(&tree).values.len());
+    }
+}
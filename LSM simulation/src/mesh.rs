@@ -0,0 +1,342 @@
+use std::f32::consts::PI;
+use std::fmt::Write as _;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use three_d::*;
+
+use crate::vec_tree::*;
+use crate::ordering::*;
+use crate::geometry::*;
+use crate::vtk::*;
+
+//tube meshes of trees for Blender and other renderers, built on the CPU from TreeGeometry
+//every compartment becomes a capped tube of its width, values of the compartment are baked into vertex colours
+//and written as vertex attributes (PLY properties, glTF _AUXIN/_PIN/_AUXIN_FLOW/_ORDER)
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+#[serde(default)]
+pub struct MeshParams{
+    pub geometry: GeometryParams,
+    pub color_by: ColorBy,
+    pub auxin_max: f32,
+    pub color_exp: f32,
+    //vertices around a tube
+    pub sides: usize,
+    //node compartments of buds and decapitated nodes, hidden by default as in the GUI
+    pub buds: bool,
+}
+impl Default for MeshParams{
+    fn default() -> Self {
+        MeshParams {
+            geometry: GeometryParams::default(),
+            color_by: ColorBy::Auxin,
+            auxin_max: 0.7,
+            color_exp: 0.3,
+            sides: 8,
+            buds: false,
+        }
+    }
+}
+
+pub const MESH_SCALARS: [&str; 4] = ["auxin","pin","auxin_flow","order"];
+
+//kind of compartment a triangle belongs to, OBJ files get one material per kind
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum MeshPart{
+    Segment,
+    Branching,
+    ActiveBud,
+    DormantBud,
+    Decapitated,
+}
+impl MeshPart{
+    pub const ALL: [MeshPart; 5] = [MeshPart::Segment,MeshPart::Branching,MeshPart::ActiveBud,MeshPart::DormantBud,MeshPart::Decapitated];
+    pub fn name(&self) -> &'static str{
+        match self{
+            MeshPart::Segment => "segment",
+            MeshPart::Branching => "branching",
+            MeshPart::ActiveBud => "active_bud",
+            MeshPart::DormantBud => "dormant_bud",
+            MeshPart::Decapitated => "decapitated",
+        }
+    }
+    //bud colours of the GUI, compartments with hormone colours are white
+    fn color(&self) -> [f32;3]{
+        match self{
+            MeshPart::Segment | MeshPart::Branching => [1.,1.,1.],
+            MeshPart::ActiveBud => [0.,1.,0.],
+            MeshPart::DormantBud => [1.,1.,0.],
+            MeshPart::Decapitated => [0.,0.,0.],
+        }
+    }
+    pub fn of(tree: &Tree, compartment: Compartment) -> MeshPart{
+        match compartment.segment{
+            Some(_) => MeshPart::Segment,
+            None => match tree[compartment.node].bud_state{
+                BudState::BranchingSegment => MeshPart::Branching,
+                BudState::ActiveBud => MeshPart::ActiveBud,
+                BudState::DormantBud => MeshPart::DormantBud,
+                BudState::DecapitatedSegment => MeshPart::Decapitated,
+            },
+        }
+    }
+}
+
+impl MeshParams{
+    pub fn includes(&self, part: MeshPart) -> bool{
+        self.buds || matches!(part,MeshPart::Segment | MeshPart::Branching)
+    }
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct TreeMesh{
+    pub positions: Vec<[f32;3]>,
+    pub normals: Vec<[f32;3]>,
+    pub colors: Vec<[f32;3]>,
+    //values of MESH_SCALARS of the compartment the vertex belongs to
+    pub scalars: Vec<[f32;4]>,
+    //compartment every vertex belongs to
+    pub compartments: Vec<Compartment>,
+    pub triangles: Vec<[u32;3]>,
+    pub parts: Vec<MeshPart>,
+}
+
+impl TreeMesh{
+    pub fn new(tree: &Tree, params: &MeshParams) -> TreeMesh{
+        let geometry = TreeGeometry::new(tree, &params.geometry);
+        let orders = match params.color_by{
+            ColorBy::Auxin => None,
+            ColorBy::Order(scheme) => Some(tree.orders(scheme)),
+        };
+        let current = tree.orders(OrderingScheme::Current);
+        let mut mesh = TreeMesh::default();
+        for compartment in geometry.compartments(){
+            let node = &tree[compartment.compartment.node];
+            let data = match compartment.compartment.segment{
+                Some(segment) => &node.segments[segment].data,
+                None => &node.data,
+            };
+            let part = MeshPart::of(tree, compartment.compartment);
+            if compartment.length()==0. || !params.includes(part){
+                continue;
+            }
+            let color = match (&orders,part){
+                (_,MeshPart::ActiveBud | MeshPart::DormantBud | MeshPart::Decapitated) => part.color(),
+                (None,_) => auxin_color(data.auxin, params.auxin_max, params.color_exp),
+                (Some(orders),_) => order_color(orders.order(node.index)),
+            };
+            let scalars = [data.auxin,data.pin,data.auxin_flow,current.order(node.index) as f32];
+            mesh.add_tube(compartment, params.sides.max(3), color, scalars, part);
+        }
+        mesh
+    }
+
+    fn add_vertex(&mut self, position: Vec3, normal: Vec3, color: [f32;3], scalars: [f32;4], compartment: Compartment) -> u32{
+        self.compartments.push(compartment);
+        self.positions.push(position.into());
+        self.normals.push(normal.into());
+        self.colors.push(color);
+        self.scalars.push(scalars);
+        self.positions.len() as u32-1
+    }
+
+    fn add_tube(&mut self, compartment: &CompartmentGeometry, sides: usize, color: [f32;3], scalars: [f32;4], part: MeshPart){
+        let frame = frame(compartment.end-compartment.start);
+        let (x,axis,z) = (frame.x.truncate(),frame.y.truncate(),frame.z.truncate());
+        let radius = compartment.width/2.;
+        let around = |k: usize| {
+            let angle = 2.*PI*k as f32/sides as f32;
+            x*angle.cos()+z*angle.sin()
+        };
+        //sides with smooth normals
+        let first = self.positions.len() as u32;
+        for k in 0..sides{
+            let normal = around(k);
+            self.add_vertex(compartment.start+normal*radius, normal, color, scalars, compartment.compartment);
+            self.add_vertex(compartment.end+normal*radius, normal, color, scalars, compartment.compartment);
+        }
+        for k in 0..sides as u32{
+            let next = (k+1)%sides as u32;
+            let (a,b,c,d) = (first+2*k,first+2*k+1,first+2*next,first+2*next+1);
+            self.triangles.extend([[a,b,c],[b,d,c]]);
+            self.parts.extend([part,part]);
+        }
+        //flat caps
+        for (center,normal) in [(compartment.start,-axis),(compartment.end,axis)]{
+            let middle = self.add_vertex(center, normal, color, scalars, compartment.compartment);
+            let ring: Vec<u32> = (0..sides).map(|k| self.add_vertex(center+around(k)*radius, normal, color, scalars, compartment.compartment)).collect();
+            for k in 0..sides{
+                let (a,b) = (ring[k],ring[(k+1)%sides]);
+                self.triangles.push(if normal==axis {[middle,b,a]} else {[middle,a,b]});
+                self.parts.push(part);
+            }
+        }
+    }
+
+    //Wavefront OBJ with vertex colours (x y z r g b) and a material per MeshPart in the MTL file next to it
+    pub fn write_obj(&self, mtl_name: &str) -> String{
+        let mut text = format!("# tree mesh\nmtllib {mtl_name}\no tree\n");
+        for (p,c) in self.positions.iter().zip(&self.colors){
+            let _ = writeln!(text, "v {} {} {} {} {} {}",p[0],p[1],p[2],c[0],c[1],c[2]);
+        }
+        for n in &self.normals{
+            let _ = writeln!(text, "vn {} {} {}",n[0],n[1],n[2]);
+        }
+        let mut part = None;
+        for (triangle,&triangle_part) in self.triangles.iter().zip(&self.parts){
+            if part!=Some(triangle_part){
+                let _ = writeln!(text, "usemtl {}",triangle_part.name());
+                part = Some(triangle_part);
+            }
+            let [a,b,c] = triangle.map(|i| i+1);
+            let _ = writeln!(text, "f {a}//{a} {b}//{b} {c}//{c}");
+        }
+        text
+    }
+    pub fn write_mtl(&self) -> String{
+        let mut text = String::new();
+        for part in MeshPart::ALL{
+            let color = match part{
+                MeshPart::Segment | MeshPart::Branching => [0.8,0.8,0.8],
+                _ => part.color(),
+            };
+            let _ = writeln!(text, "newmtl {}\nKd {} {} {}\nKa 0 0 0\nKs 0.1 0.1 0.1\nNs 10\nd 1\nillum 2\n",part.name(),color[0],color[1],color[2]);
+        }
+        text
+    }
+
+    //ASCII PLY with colours and MESH_SCALARS as vertex properties
+    pub fn write_ply(&self) -> String{
+        let mut text = format!("ply\nformat ascii 1.0\ncomment tree mesh\nelement vertex {}\n",self.positions.len());
+        text += "property float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\n";
+        text += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+        for name in MESH_SCALARS{
+            let _ = writeln!(text, "property float {name}");
+        }
+        let _ = write!(text, "element face {}\nproperty list uchar int vertex_indices\nend_header\n",self.triangles.len());
+        for i in 0..self.positions.len(){
+            let (p,n,s) = (self.positions[i],self.normals[i],self.scalars[i]);
+            let c = self.colors[i].map(|v| (v.clamp(0., 1.)*255.).round() as u8);
+            let _ = writeln!(text, "{} {} {} {} {} {} {} {} {} {} {} {} {}",p[0],p[1],p[2],n[0],n[1],n[2],c[0],c[1],c[2],s[0],s[1],s[2],s[3]);
+        }
+        for [a,b,c] in &self.triangles{
+            let _ = writeln!(text, "3 {a} {b} {c}");
+        }
+        text
+    }
+
+    //glTF 2.0 with COLOR_0 and MESH_SCALARS as custom attributes, the binary buffer is written to bin_name
+    pub fn write_gltf(&self, bin_name: &str) -> (String,Vec<u8>){
+        let mut buffer: Vec<u8> = vec![];
+        let mut views = vec![];
+        let mut accessors = vec![];
+        let mut add_floats = |values: Vec<f32>, kind: &str, components: usize, bounds: bool| -> usize{
+            let offset = buffer.len();
+            for v in &values{
+                buffer.extend(v.to_le_bytes());
+            }
+            views.push(json!({"buffer": 0, "byteOffset": offset, "byteLength": values.len()*4, "target": 34962}));
+            let mut accessor = json!({"bufferView": views.len()-1, "componentType": 5126, "count": values.len()/components, "type": kind});
+            if bounds{
+                let min: Vec<f32> = (0..components).map(|c| values.iter().skip(c).step_by(components).copied().fold(f32::INFINITY,f32::min)).collect();
+                let max: Vec<f32> = (0..components).map(|c| values.iter().skip(c).step_by(components).copied().fold(f32::NEG_INFINITY,f32::max)).collect();
+                accessor["min"] = json!(min);
+                accessor["max"] = json!(max);
+            }
+            accessors.push(accessor);
+            accessors.len()-1
+        };
+        let position = add_floats(self.positions.concat(), "VEC3", 3, true);
+        let normal = add_floats(self.normals.concat(), "VEC3", 3, false);
+        let color = add_floats(self.colors.iter().flat_map(|c| c.map(|v| v.clamp(0., 1.))).collect(), "VEC3", 3, false);
+        let mut attributes = json!({"POSITION": position, "NORMAL": normal, "COLOR_0": color});
+        for (k,name) in MESH_SCALARS.iter().enumerate(){
+            let accessor = add_floats(self.scalars.iter().map(|s| s[k]).collect(), "SCALAR", 1, false);
+            attributes[format!("_{}",name.to_uppercase())] = json!(accessor);
+        }
+        let offset = buffer.len();
+        for index in self.triangles.iter().flatten(){
+            buffer.extend(index.to_le_bytes());
+        }
+        views.push(json!({"buffer": 0, "byteOffset": offset, "byteLength": self.triangles.len()*12, "target": 34963}));
+        accessors.push(json!({"bufferView": views.len()-1, "componentType": 5125, "count": self.triangles.len()*3, "type": "SCALAR"}));
+        let indices = accessors.len()-1;
+        let gltf = json!({
+            "asset": {"version": "2.0", "generator": "LSM simulation"},
+            "scene": 0,
+            "scenes": [{"nodes": [0]}],
+            "nodes": [{"mesh": 0, "name": "tree"}],
+            "meshes": [{"name": "tree", "primitives": [{"attributes": attributes, "indices": indices, "material": 0, "mode": 4}]}],
+            "materials": [{"name": "tree", "pbrMetallicRoughness": {"baseColorFactor": [1.,1.,1.,1.], "metallicFactor": 0., "roughnessFactor": 0.8}}],
+            "buffers": [{"uri": bin_name, "byteLength": buffer.len()}],
+            "bufferViews": views,
+            "accessors": accessors,
+        });
+        (serde_json::to_string_pretty(&gltf).unwrap(),buffer)
+    }
+}
+
+//format chosen by the extension: .obj (with .mtl), .ply, .gltf (with .bin) or .vtp (VTK tubes)
+pub fn save_mesh(path: &Path, tree: &Tree, params: &MeshParams) -> Result<(),String>{
+    let mesh = TreeMesh::new(tree, params);
+    let error = |e: std::io::Error| format!("{}: {e}",path.display());
+    let sibling = |extension: &str| path.with_extension(extension);
+    let file_name = |p: &Path| p.file_name().unwrap().to_string_lossy().to_string();
+    match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref(){
+        Some("obj") => {
+            std::fs::write(sibling("mtl"), mesh.write_mtl()).map_err(error)?;
+            std::fs::write(path, mesh.write_obj(&file_name(&sibling("mtl")))).map_err(error)
+        }
+        Some("ply") => std::fs::write(path, mesh.write_ply()).map_err(error),
+        Some("gltf") => {
+            let (gltf,buffer) = mesh.write_gltf(&file_name(&sibling("bin")));
+            std::fs::write(sibling("bin"), buffer).map_err(error)?;
+            std::fs::write(path, gltf).map_err(error)
+        }
+        Some("vtp") => save_vtp(path, tree, params, VtkStyle::Tubes),
+        _ => Err(format!("{}: mesh files are .obj, .ply, .gltf or .vtp",path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn obj_and_ply_counts(){
+        let mut tree = Tree::with_seed(&Settings::default(), 2);
+        for _ in 0..15{
+            tree.extend_main();
+        }
+        let params = MeshParams { sides: 6, ..MeshParams::default() };
+        let geometry = TreeGeometry::new(&tree, &params.geometry);
+        let tubes = geometry.compartments().filter(|c| c.length()>0. && params.includes(MeshPart::of(&tree, c.compartment))).count();
+        assert!(tubes>0);
+        //two rings of side vertices and two caps of a centre and a ring, two triangles per side and one per cap side
+        let (vertices,triangles) = (tubes*(2*6+2*7),tubes*(2*6+2*6));
+
+        let dir = std::env::temp_dir().join(format!("tree_d_mesh_{}",std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        save_mesh(&dir.join("tree.obj"), &tree, &params).unwrap();
+        save_mesh(&dir.join("tree.ply"), &tree, &params).unwrap();
+        let obj = std::fs::read_to_string(dir.join("tree.obj")).unwrap();
+        let mtl = std::fs::read_to_string(dir.join("tree.mtl")).unwrap();
+        let ply = std::fs::read_to_string(dir.join("tree.ply")).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+
+        let count = |prefix: &str| obj.lines().filter(|l| l.starts_with(prefix)).count();
+        assert_eq!((count("v "),count("vn "),count("f ")),(vertices,vertices,triangles));
+        assert!(obj.contains("mtllib tree.mtl"));
+        assert_eq!(mtl.matches("newmtl").count(),MeshPart::ALL.len());
+        //obj indices start at 1
+        let highest = obj.lines().filter(|l| l.starts_with("f ")).flat_map(|l| l.split_whitespace().skip(1).map(|v| v.split('/').next().unwrap().parse::<usize>().unwrap())).max();
+        assert_eq!(highest,Some(vertices));
+
+        assert!(ply.contains(&format!("element vertex {vertices}\n")) && ply.contains(&format!("element face {triangles}\n")));
+        let body: Vec<&str> = ply.split("end_header\n").nth(1).unwrap().lines().collect();
+        assert_eq!(body.len(),vertices+triangles);
+        assert!(body[..vertices].iter().all(|l| l.split_whitespace().count()==9+MESH_SCALARS.len()));
+        assert!(body[vertices..].iter().all(|l| l.starts_with("3 ") && l.split_whitespace().skip(1).all(|i| i.parse::<usize>().unwrap()<vertices)));
+    }
+}
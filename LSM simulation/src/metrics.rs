@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+
+//statistics of the auxin and pin profiles along the main stem, shared by the viewer, the exports, the batch runner and the fits
+//profiles are in the order of Tree::main_stem_values, from the tip to the base, and internodes are counted from the tip
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Eq,PartialOrd,Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Quantity{
+    #[serde(alias = "iaa")]
+    Auxin,
+    Pin,
+}
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregate{
+    #[default]
+    Mean,
+    Max,
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct Profile{
+    //(auxin, pin) of every main stem compartment
+    pub values: Vec<(f32,f32)>,
+    //internode of every value
+    pub internodes: Vec<i32>,
+    //compartments compared by peak_ratio at each end
+    pub segments_amount: i32,
+}
+
+impl Profile{
+    pub fn new(tree: &Tree) -> Profile{
+        let internodes = tree.main_stem_internodes();
+        Profile {
+            values: internodes.iter().map(|&(_,auxin,pin)| (auxin,pin)).collect(),
+            internodes: internodes.iter().map(|&(internode,_,_)| internode).collect(),
+            segments_amount: tree.settings.segments_amount.max(1),
+        }
+    }
+
+    pub fn quantity(&self, quantity: Quantity) -> Vec<f32>{
+        self.values.iter().map(|v| match quantity{
+            Quantity::Auxin => v.0,
+            Quantity::Pin => v.1,
+        }).collect()
+    }
+
+    //value of every internode
+    pub fn internode_values(&self, quantity: Quantity, aggregate: Aggregate) -> BTreeMap<i32,f32>{
+        let mut groups: BTreeMap<i32,Vec<f32>> = BTreeMap::new();
+        for (internode,value) in self.internodes.iter().zip(self.quantity(quantity)){
+            groups.entry(*internode).or_default().push(value);
+        }
+        groups.into_iter().map(|(internode,values)| (internode,match aggregate{
+            Aggregate::Mean => mean(&values),
+            Aggregate::Max => values.iter().fold(f32::MIN, |a,&b| a.max(b)),
+        })).collect()
+    }
+
+    //least squares slope against the compartment index from the base
+    pub fn slope(&self, quantity: Quantity) -> f32{
+        let mut values = self.quantity(quantity);
+        values.reverse();
+        let x: Vec<f32> = (0..values.len()).map(|i| i as f32).collect();
+        linear_fit(&x, &values).map_or(0., |(slope,_)| slope)
+    }
+
+    //slope relative to the mean value, the fraction the profile changes per compartment
+    pub fn steepness(&self, quantity: Quantity) -> f32{
+        let mean = mean(&self.quantity(quantity));
+        if mean.abs()>f32::EPSILON {self.slope(quantity)/mean} else {0.}
+    }
+
+    //(base peak - tip peak)/tip peak of auxin over segments_amount compartments at each end
+    pub fn peak_ratio(&self) -> f32{
+        let auxin = self.quantity(Quantity::Auxin);
+        let n = (self.segments_amount as usize).min(auxin.len());
+        let peak = |values: &[f32]| values.iter().fold(0f32, |a,&b| a.max(b));
+        let tip = peak(&auxin[..n]);
+        let base = peak(&auxin[auxin.len()-n..]);
+        (base-tip)/(tip+0.001)
+    }
+
+    pub fn mean(&self, quantity: Quantity) -> f32{
+        mean(&self.quantity(quantity))
+    }
+
+    //pearson correlation of pin and auxin over the compartments
+    pub fn correlation(&self) -> f32{
+        pearson(&self.quantity(Quantity::Pin), &self.quantity(Quantity::Auxin)).unwrap_or(0.)
+    }
+}
+
+pub fn mean(values: &[f32]) -> f32{
+    values.iter().sum::<f32>()/values.len().max(1) as f32
+}
+
+//(slope, intercept) of the least squares line, None for fewer than two distinct x
+pub fn linear_fit(x: &[f32], y: &[f32]) -> Option<(f32,f32)>{
+    let (mean_x,mean_y) = (mean(x),mean(y));
+    let sxx: f32 = x.iter().map(|x| (x-mean_x)*(x-mean_x)).sum();
+    let sxy: f32 = x.iter().zip(y).map(|(x,y)| (x-mean_x)*(y-mean_y)).sum();
+    (x.len()>=2 && sxx>0.).then(|| (sxy/sxx,mean_y-sxy/sxx*mean_x))
+}
+
+//None when either side is constant
+pub fn pearson(x: &[f32], y: &[f32]) -> Option<f32>{
+    let (mean_x,mean_y) = (mean(x),mean(y));
+    let sxx: f32 = x.iter().map(|x| (x-mean_x)*(x-mean_x)).sum();
+    let syy: f32 = y.iter().map(|y| (y-mean_y)*(y-mean_y)).sum();
+    let sxy: f32 = x.iter().zip(y).map(|(x,y)| (x-mean_x)*(y-mean_y)).sum();
+    (sxx>0. && syy>0.).then(|| sxy/(sxx*syy).sqrt())
+}
+
+//two-sided p-value of a pearson correlation of n pairs, from the t distribution with n-2 degrees of freedom as R's cor.test
+pub fn correlation_p_value(r: f32, n: usize) -> Option<f32>{
+    if n<3{
+        return None;
+    }
+    let df = (n-2) as f64;
+    let r2 = (r as f64*r as f64).min(1.);
+    if r2>=1.{
+        return Some(0.);
+    }
+    let t2 = r2*df/(1.-r2);
+    Some(incomplete_beta(df/2., 0.5, df/(df+t2)) as f32)
+}
+
+//Lanczos approximation
+fn ln_gamma(x: f64) -> f64{
+    const COEFFICIENTS: [f64;6] = [76.18009172947146,-86.50532032941677,24.01409824083091,-1.231739572450155,0.1208650973866179e-2,-0.5395239384953e-5];
+    let tmp = x+5.5;
+    let tmp = tmp-(x+0.5)*tmp.ln();
+    let mut y = x;
+    let mut series = 1.000000000190015;
+    for c in COEFFICIENTS{
+        y += 1.;
+        series += c/y;
+    }
+    -tmp+(2.5066282746310005*series/x).ln()
+}
+
+//regularized incomplete beta function I_x(a,b)
+fn incomplete_beta(a: f64, b: f64, x: f64) -> f64{
+    if x<=0.{
+        return 0.;
+    }
+    if x>=1.{
+        return 1.;
+    }
+    let front = (ln_gamma(a+b)-ln_gamma(a)-ln_gamma(b)+a*x.ln()+b*(1.-x).ln()).exp();
+    if x<(a+1.)/(a+b+2.){
+        front*beta_fraction(a, b, x)/a
+    } else {
+        1.-front*beta_fraction(b, a, 1.-x)/b
+    }
+}
+
+//continued fraction of the incomplete beta function by the modified Lentz method
+fn beta_fraction(a: f64, b: f64, x: f64) -> f64{
+    let tiny = 1e-30;
+    let guard = |v: f64| if v.abs()<tiny {tiny} else {v};
+    let mut c = 1.;
+    let mut d = 1./guard(1.-(a+b)*x/(a+1.));
+    let mut h = d;
+    for m in 1..300{
+        let m = m as f64;
+        let even = m*(b-m)*x/((a+2.*m-1.)*(a+2.*m));
+        d = 1./guard(1.+even*d);
+        c = guard(1.+even/c);
+        h *= d*c;
+        let odd = -(a+m)*(a+b+m)*x/((a+2.*m)*(a+2.*m+1.));
+        d = 1./guard(1.+odd*d);
+        c = guard(1.+odd/c);
+        h *= d*c;
+        if (d*c-1.).abs()<1e-12{
+            break;
+        }
+    }
+    h
+}
+
+//scalar output of a tree, used as the metric of sensitivity analyses and abc statistics, and written by the batch runner
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMetric{
+    //(base peak - tip peak)/tip peak of auxin over segments_amount compartments, as shown in the viewer
+    PeakRatio,
+    //least squares slope of auxin against the compartment index from the base
+    AuxinSlope,
+    PinSlope,
+    //slope divided by the mean
+    AuxinSteepness,
+    PinSteepness,
+    MeanAuxin,
+    MeanPin,
+    PinAuxinCorrelation,
+}
+
+impl OutputMetric{
+    pub const ALL: [OutputMetric;8] = [
+        OutputMetric::PeakRatio, OutputMetric::AuxinSlope, OutputMetric::PinSlope, OutputMetric::AuxinSteepness,
+        OutputMetric::PinSteepness, OutputMetric::MeanAuxin, OutputMetric::MeanPin, OutputMetric::PinAuxinCorrelation,
+    ];
+    pub fn name(&self) -> String{
+        serde_json::to_value(self).ok().and_then(|v| v.as_str().map(|s| s.to_string())).unwrap_or_default()
+    }
+    pub fn of_profile(&self, profile: &Profile) -> f32{
+        match self{
+            OutputMetric::PeakRatio => profile.peak_ratio(),
+            OutputMetric::AuxinSlope => profile.slope(Quantity::Auxin),
+            OutputMetric::PinSlope => profile.slope(Quantity::Pin),
+            OutputMetric::AuxinSteepness => profile.steepness(Quantity::Auxin),
+            OutputMetric::PinSteepness => profile.steepness(Quantity::Pin),
+            OutputMetric::MeanAuxin => profile.mean(Quantity::Auxin),
+            OutputMetric::MeanPin => profile.mean(Quantity::Pin),
+            OutputMetric::PinAuxinCorrelation => profile.correlation(),
+        }
+    }
+    pub fn value(&self, tree: &Tree) -> f32{
+        self.of_profile(&Profile::new(tree))
+    }
+}
+
+//every metric of a tree by name
+pub fn tree_metrics(tree: &Tree) -> BTreeMap<String,f32>{
+    let profile = Profile::new(tree);
+    OutputMetric::ALL.iter().map(|metric| (metric.name(),metric.of_profile(&profile))).collect()
+}
+
+//mean of every metric over the trees
+pub fn mean_metrics(trees: &[Tree]) -> BTreeMap<String,f32>{
+    let all: Vec<BTreeMap<String,f32>> = trees.iter().map(tree_metrics).collect();
+    OutputMetric::ALL.iter().map(|metric|{
+        let name = metric.name();
+        let values: Vec<f32> = all.iter().map(|m| m[&name]).collect();
+        (name,mean(&values))
+    }).collect()
+}
+
+//one row of metrics per tree
+pub fn write_metrics_table(path: &Path, trees: &[(String,&Tree)]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    let names: Vec<String> = OutputMetric::ALL.iter().map(|m| m.name()).collect();
+    writer.write_record(["tree","seed"].into_iter().chain(names.iter().map(|n| n.as_str()))).map_err(error)?;
+    for (name,tree) in trees{
+        let metrics = tree_metrics(tree);
+        writer.write_record([name.clone(),tree.seed.to_string()].into_iter().chain(names.iter().map(|n| metrics[n].to_string()))).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    const X: [f32;8] = [1.,2.,3.,4.,5.,6.,7.,8.];
+    const Y: [f32;8] = [2.1,3.9,3.2,5.8,4.9,7.4,6.1,8.3];
+
+    fn close(a: f32, b: f32, tolerance: f32){
+        assert!((a-b).abs()<tolerance,"{a} != {b}");
+    }
+
+    #[test]
+    fn p_values_match_cor_test(){
+        //r = 0.9158, t = 5.5856 with 6 degrees of freedom, p by numerical integration of the t density
+        let r = pearson(&X, &Y).unwrap();
+        close(r, 0.915809, 1e-5);
+        close(correlation_p_value(r, X.len()).unwrap(), 0.0013993, 1e-6);
+        //as reported by cor.test
+        close(correlation_p_value(0.5, 10).unwrap(), 0.14111, 1e-5);
+        //with 2 degrees of freedom p = 1 - t/sqrt(t^2+2), r = 0.6 gives t^2 = 1.125 and p = 0.4
+        close(correlation_p_value(0.6, 4).unwrap(), 0.4, 1e-6);
+        close(correlation_p_value(-0.6, 4).unwrap(), 0.4, 1e-6);
+        close(correlation_p_value(0., 10).unwrap(), 1., 1e-6);
+        assert_eq!(correlation_p_value(1., 10),Some(0.));
+        assert_eq!(correlation_p_value(0.5, 2),None);
+    }
+
+    #[test]
+    fn fits(){
+        let (slope,intercept) = linear_fit(&X, &Y).unwrap();
+        close(slope, 0.786905, 1e-5);
+        close(intercept, 1.671429, 1e-5);
+        assert_eq!(linear_fit(&[1.,1.,1.], &[1.,2.,3.]),None);
+        assert_eq!(linear_fit(&[1.], &[1.]),None);
+        close(pearson(&X, &X.map(|x| 3.-2.*x)).unwrap(), -1., 1e-6);
+        assert_eq!(pearson(&X, &[2.; 8]),None);
+    }
+
+    //auxin rises linearly from the tip to the base, pin is flat
+    fn linear_profile(segments_amount: i32) -> Profile{
+        Profile {
+            values: (1..=6).map(|i| (i as f32,2.)).collect(),
+            internodes: vec![1,1,2,2,3,3],
+            segments_amount,
+        }
+    }
+
+    #[test]
+    fn profile_metrics(){
+        let profile = linear_profile(2);
+        //counted from the base the auxin values are 6, 5, .., 1
+        assert_eq!(profile.slope(Quantity::Auxin),-1.);
+        close(profile.steepness(Quantity::Auxin), -1./3.5, 1e-6);
+        assert_eq!(profile.slope(Quantity::Pin),0.);
+        assert_eq!(profile.steepness(Quantity::Pin),0.);
+        assert_eq!(profile.correlation(),0.);
+        //peaks 2 at the tip and 6 at the base
+        close(profile.peak_ratio(), 4./2.001, 1e-6);
+        assert_eq!(profile.internode_values(Quantity::Auxin, Aggregate::Mean),BTreeMap::from([(1,1.5),(2,3.5),(3,5.5)]));
+        assert_eq!(profile.internode_values(Quantity::Auxin, Aggregate::Max),BTreeMap::from([(1,2.),(2,4.),(3,6.)]));
+        assert_eq!(OutputMetric::AuxinSlope.of_profile(&profile),-1.);
+        assert_eq!(OutputMetric::MeanPin.of_profile(&profile),2.);
+    }
+
+    #[test]
+    fn short_profiles(){
+        //both ends cover the whole profile, so the peaks are equal
+        assert_eq!(linear_profile(10).peak_ratio(),0.);
+        let empty = Profile { segments_amount: 10, ..Profile::default() };
+        assert_eq!(empty.peak_ratio(),0.);
+        assert_eq!(empty.slope(Quantity::Auxin),0.);
+        assert_eq!(empty.correlation(),0.);
+    }
+}
@@ -1,11 +1,11 @@
 use crate::vec_tree::*;
 
 pub fn outflow(data: &Data) -> f32{
-    -data.auxin*data.pin
+    -data.auxin*data.pin*data.transport
 }
 
 pub fn inflow(data: &Data) -> f32{
-    data.auxin*data.pin
+    data.auxin*data.pin*data.transport
 }
 pub fn production(data: &Data,gain:f32) -> f32{
     gain
@@ -13,6 +13,10 @@ pub fn production(data: &Data,gain:f32) -> f32{
 pub fn segment_production(data: &Data,settings: &Settings) -> f32{
     settings.segment_gain
 }
+//auxin applied from outside of the plant (e.g. IAA paste on decapitated stump)
+pub fn external_production(data: &Data) -> f32{
+    data.external_auxin
+}
 pub fn decay(data:&Data,settings: &Settings) -> f32{
     -data.auxin*settings.decay
 }
@@ -0,0 +1,367 @@
+use std::collections::HashMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use three_d::*;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::geometry::*;
+use crate::measured::*;
+
+//exchange of trees with OpenAlea in the MTG (multiscale tree graph) text format
+//scales: P plant (1), A axis (2), U growth unit (3) and I internode (4)
+//a node is a growth unit made of its segments followed by the node compartment, every compartment is an internode
+//lateral axes are borne by the node compartment, the secondary child of a node starts a new axis
+//XX YY ZZ are the end points of the internodes with ZZ pointing up and the plant base at ZZ = 0, TopDia their width
+//on import the finest scale is read as compartments, growth units without laterals are merged into one node
+//orders are read from the growth units, or computed from the topology when missing
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+#[serde(default)]
+pub struct MtgOptions{
+    //MTG units per model unit, positions and widths are multiplied by it on export and divided on import
+    pub scale: f32,
+}
+impl Default for MtgOptions{
+    fn default() -> Self {
+        MtgOptions { scale: 1. }
+    }
+}
+
+const NODE_FEATURES: [(&str,&str);3] = [("order","INT"),("initial_order","INT"),("bud_state","ALPHA")];
+const COMPARTMENT_FEATURES: [(&str,&str);10] = [
+    ("auxin","REAL"),("pin","REAL"),("strigolactin","REAL"),("auxin_flow","REAL"),("transport","REAL"),("external_auxin","REAL"),
+    ("XX","REAL"),("YY","REAL"),("ZZ","REAL"),("TopDia","REAL"),
+];
+
+fn bud_state_name(state: &BudState) -> &'static str{
+    match state{
+        BudState::DormantBud => "DormantBud",
+        BudState::ActiveBud => "ActiveBud",
+        BudState::BranchingSegment => "BranchingSegment",
+        BudState::DecapitatedSegment => "DecapitatedSegment",
+    }
+}
+fn bud_state_from_name(name: &str) -> Option<BudState>{
+    [BudState::DormantBud,BudState::ActiveBud,BudState::BranchingSegment,BudState::DecapitatedSegment]
+        .into_iter().find(|s| bud_state_name(s).eq_ignore_ascii_case(name))
+}
+
+struct MtgLine{
+    column: usize,
+    code: String,
+    //values of NODE_FEATURES followed by COMPARTMENT_FEATURES, empty when missing
+    features: Vec<String>,
+}
+
+struct MtgWriter<'a>{
+    tree: &'a Tree,
+    geometry: TreeGeometry,
+    options: &'a MtgOptions,
+}
+impl<'a> MtgWriter<'a>{
+    //axis starting at the node, written after the line of the compartment bearing it
+    fn axis(&self, first: NodeId, column: usize, lines: &mut Vec<MtgLine>, axes: &mut usize, internodes: &mut usize){
+        *axes+=1;
+        let features = NODE_FEATURES.len()+COMPARTMENT_FEATURES.len();
+        let code = if column==0 {format!("^/A{axes}")} else {format!("+A{axes}")};
+        lines.push(MtgLine { column, code, features: vec![] });
+        for (k,id) in self.tree.axis(first).enumerate(){
+            let node = &self.tree[id];
+            let mut values = vec![String::new(); features];
+            values[0] = node.order.to_string();
+            values[1] = node.initial_order.to_string();
+            values[2] = bud_state_name(&node.bud_state).to_string();
+            lines.push(MtgLine { column, code: format!("^{}U{}",if k==0 {'/'} else {'<'},id.0+1), features: values });
+            for (j,compartment) in self.geometry.node(id).iter().enumerate(){
+                let data = match compartment.compartment.segment{
+                    Some(segment) => &node.segments[segment].data,
+                    None => &node.data,
+                };
+                let end = compartment.end*self.options.scale;
+                let mut values = vec![String::new(); NODE_FEATURES.len()];
+                values.extend([data.auxin,data.pin,data.strigolactin,data.auxin_flow,data.transport,data.external_auxin,
+                    end.x,-end.z,end.y,compartment.width*self.options.scale].map(|v| v.to_string()));
+                *internodes+=1;
+                lines.push(MtgLine { column, code: format!("^{}I{internodes}",if j==0 {'/'} else {'<'}), features: values });
+            }
+            if let Some(lateral) = node.secondary_child{
+                self.axis(lateral, column+1, lines, axes, internodes);
+            }
+        }
+    }
+}
+
+pub fn write_mtg(tree: &Tree, params: &GeometryParams, options: &MtgOptions) -> String{
+    let writer = MtgWriter { tree, geometry: TreeGeometry::new(tree, params), options };
+    let mut lines = vec![MtgLine { column: 0, code: "/P1".to_string(), features: vec![] }];
+    writer.axis(tree.root(), 0, &mut lines, &mut 0, &mut 0);
+
+    let mut text = String::from("CODE:\tFORM-A\n\nCLASSES:\nSYMBOL\tSCALE\tDECOMPOSITION\tINDEXATION\tDEFINITION\n");
+    for class in ["$\t0\tFREE\tFREE\tIMPLICIT","P\t1\tCONNECTED\tFREE\tIMPLICIT","A\t2\t<-LINEAR\tFREE\tIMPLICIT","U\t3\t<-LINEAR\tFREE\tIMPLICIT","I\t4\tNONE\tFREE\tIMPLICIT"]{
+        text += class;
+        text.push('\n');
+    }
+    text += "\nDESCRIPTION:\nLEFT\tRIGHT\tRELTYPE\tMAX\nA\tA\t+\t?\nU\tU\t<\t1\nI\tI\t<\t1\n\nFEATURES:\nNAME\tTYPE\n";
+    for (name,kind) in NODE_FEATURES.iter().chain(COMPARTMENT_FEATURES.iter()){
+        text += &format!("{name}\t{kind}\n");
+    }
+    //features start in the column after the deepest topology column
+    let feature_column = lines.iter().map(|l| l.column).max().unwrap_or(0)+1;
+    let names: Vec<&str> = NODE_FEATURES.iter().chain(COMPARTMENT_FEATURES.iter()).map(|(name,_)| *name).collect();
+    text += &format!("\nMTG:\nENTITY-CODE{}{}\n","\t".repeat(feature_column),names.join("\t"));
+    for line in &lines{
+        let row = format!("{}{}{}{}","\t".repeat(line.column),line.code,"\t".repeat(feature_column-line.column),line.features.join("\t"));
+        text += row.trim_end_matches('\t');
+        text.push('\n');
+    }
+    text
+}
+
+pub fn save_mtg(path: &Path, tree: &Tree, params: &GeometryParams, options: &MtgOptions) -> Result<(),String>{
+    std::fs::write(path, write_mtg(tree, params, options)).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+struct Entity{
+    symbol: String,
+    relation: char,
+    //entity the relation refers to
+    reference: Option<usize>,
+    features: HashMap<String,String>,
+}
+
+//splits an entity code such as ^/U1<U2 into the continuation mark and (relation, symbol) pairs
+fn parse_code(code: &str) -> Result<(bool,Vec<(char,String)>),String>{
+    let continued = code.starts_with('^');
+    let mut chars = code.trim_start_matches('^').chars().peekable();
+    let mut entities = vec![];
+    while chars.peek().is_some(){
+        let relation = match chars.peek(){
+            Some(&c) if "/<+".contains(c) => {chars.next(); c},
+            //first entity of a file may be written without a relation
+            _ if entities.is_empty() => '/',
+            _ => return Err(format!("invalid entity code {code}")),
+        };
+        let mut symbol = String::new();
+        while let Some(&c) = chars.peek(){
+            if !c.is_alphabetic() && c!='$'{
+                break;
+            }
+            symbol.push(c);
+            chars.next();
+        }
+        while chars.peek().is_some_and(|c| c.is_ascii_digit()){
+            chars.next();
+        }
+        if symbol.is_empty(){
+            return Err(format!("invalid entity code {code}"));
+        }
+        entities.push((relation,symbol));
+    }
+    Ok((continued,entities))
+}
+
+fn read_entities(text: &str) -> Result<(HashMap<String,usize>,Vec<Entity>),String>{
+    let mut scales: HashMap<String,usize> = [("P",1),("A",2),("U",3),("I",4)].into_iter().map(|(s,k)| (s.to_string(),k)).collect();
+    let mut section = "";
+    let mut feature_names: Option<(usize,Vec<String>)> = None;
+    let mut entities: Vec<Entity> = vec![];
+    //last entity written in every topology column
+    let mut column_last: Vec<usize> = vec![];
+    for (number,line) in text.lines().enumerate(){
+        let line = line.split('#').next().unwrap().trim_end_matches(['\r',' ']);
+        let trimmed = line.trim();
+        if trimmed.is_empty(){
+            continue;
+        }
+        if let Some(name) = ["CODE:","CLASSES:","DESCRIPTION:","FEATURES:","MTG:"].into_iter().find(|s| trimmed.starts_with(s)){
+            section = name;
+            continue;
+        }
+        match section{
+            "CLASSES:" => {
+                let fields: Vec<&str> = trimmed.split_whitespace().collect();
+                if let [symbol,scale,..] = fields.as_slice(){
+                    if let Ok(scale) = scale.parse(){
+                        scales.insert(symbol.to_string(), scale);
+                    }
+                }
+            }
+            "MTG:" if feature_names.is_none() => {
+                let fields: Vec<&str> = line.split('\t').collect();
+                let start = fields.iter().skip(1).position(|f| !f.trim().is_empty()).map_or(fields.len(), |p| p+1);
+                feature_names = Some((start,fields[start..].iter().map(|f| f.trim().to_lowercase()).collect()));
+            }
+            "MTG:" => {
+                let (feature_column,names) = feature_names.as_ref().unwrap();
+                let fields: Vec<&str> = line.split('\t').collect();
+                let column = line.chars().take_while(|&c| c=='\t').count();
+                let (continued,codes) = parse_code(fields[column].trim()).map_err(|e| format!("line {}: {e}",number+1))?;
+                let mut reference = if continued{
+                    Some(*column_last.get(column).ok_or(format!("line {}: no entity above in this column",number+1))?)
+                }
+                else if column>0{
+                    Some(*column_last.get(column-1).ok_or(format!("line {}: no entity in the column to the left",number+1))?)
+                }
+                else{
+                    entities.len().checked_sub(1)
+                };
+                let features: HashMap<String,String> = names.iter().enumerate()
+                    .filter_map(|(k,name)| fields.get(feature_column+k).map(|v| (name.clone(),v.trim().to_string())))
+                    .filter(|(_,v)| !v.is_empty()).collect();
+                let count = codes.len();
+                for (k,(relation,symbol)) in codes.into_iter().enumerate(){
+                    if !scales.contains_key(&symbol){
+                        return Err(format!("line {}: unknown symbol {symbol}",number+1));
+                    }
+                    //features belong to the last entity of the line
+                    let features = if k+1==count {features.clone()} else {HashMap::new()};
+                    entities.push(Entity { symbol, relation, reference, features });
+                    reference = Some(entities.len()-1);
+                }
+                column_last.truncate(column);
+                column_last.resize(column, 0);
+                column_last.push(entities.len()-1);
+            }
+            _ => {}
+        }
+    }
+    if entities.is_empty(){
+        return Err("MTG file has no entities".to_string());
+    }
+    Ok((scales,entities))
+}
+
+pub fn read_mtg(text: &str, settings: &Settings, options: &MtgOptions) -> Result<Tree,String>{
+    let (scales,entities) = read_entities(text)?;
+    let scale = |e: usize| scales[&entities[e].symbol];
+    let finest = (0..entities.len()).map(scale).max().unwrap();
+
+    //complex (entity one scale up) of every entity and the finest entity its first component attaches to
+    let mut complex: Vec<Option<usize>> = vec![None; entities.len()];
+    let mut attach: Vec<Option<(usize,bool)>> = vec![None; entities.len()];
+    let mut last_fine: Vec<Option<usize>> = vec![None; entities.len()];
+    let mut compartments: Vec<usize> = vec![];
+    for e in 0..entities.len(){
+        if let Some(reference) = entities[e].reference{
+            if entities[e].relation=='/'{
+                complex[e] = Some(reference);
+                attach[e] = attach[reference];
+            }
+            else{
+                //the reference seen at the scale of the entity
+                let mut same_scale = Some(reference);
+                while let Some(r) = same_scale.filter(|&r| scale(r)>scale(e)){
+                    same_scale = complex[r];
+                }
+                complex[e] = same_scale.and_then(|r| complex[r]).or(complex[reference]);
+                let lateral = entities[e].relation=='+';
+                let bearer = if scale(reference)==finest {Some(reference)} else {last_fine[reference]};
+                attach[e] = match bearer{
+                    Some(bearer) => Some((bearer,lateral)),
+                    None => attach[reference].map(|(bearer,l)| (bearer,l||lateral)),
+                };
+            }
+        }
+        if scale(e)==finest{
+            compartments.push(e);
+            let mut c = Some(e);
+            while let Some(k) = c{
+                last_fine[k] = Some(e);
+                c = complex[k];
+            }
+        }
+    }
+
+    let number = |e: usize, name: &str| -> Option<f32> { entities[e].features.get(name).and_then(|v| v.parse().ok()) };
+    let position: HashMap<usize,usize> = compartments.iter().enumerate().map(|(i,&e)| (e,i)).collect();
+    let point = |e: usize| -> Option<[f32;3]> {
+        let [x,y,z] = ["xx","yy","zz"].map(|name| number(e, name).map(|v| v/options.scale));
+        Some([x?,z?,-y?])
+    };
+    let measured = compartments.iter().all(|&e| point(e).is_some());
+    let mut result = vec![];
+    for &e in &compartments{
+        let parent = attach[e].map(|(bearer,_)| position[&bearer]);
+        let cylinder = if measured{
+            let end = point(e).unwrap();
+            //the base compartment starts on the ground below its end
+            let start = attach[e].map_or([end[0],0.,end[2]], |(bearer,_)| point(bearer).unwrap());
+            let radius = number(e, "topdia").or(number(e, "diameter")).map(|d| d/2.).or(number(e, "radius")).unwrap_or(0.)/options.scale;
+            Some(Cylinder { start, end, radius })
+        }
+        else {None};
+        let hormones = ["auxin","pin","strigolactin","auxin_flow","transport","external_auxin"].map(|name| number(e, name));
+        let data = if hormones.iter().any(|v| v.is_some()){
+            let mut data = Data::new(0, hormones[0].unwrap_or(settings.init_auxin), hormones[1].unwrap_or(settings.init_pin));
+            data.strigolactin = hormones[2].unwrap_or(data.strigolactin);
+            data.auxin_flow = hormones[3].unwrap_or(data.auxin_flow);
+            data.transport = hormones[4].unwrap_or(data.transport);
+            data.external_auxin = hormones[5].unwrap_or(data.external_auxin);
+            Some(data)
+        }
+        else {None};
+        let bud_state = entities[e].features.get("bud_state")
+            .or_else(|| complex[e].and_then(|c| entities[c].features.get("bud_state")))
+            .and_then(|name| bud_state_from_name(name));
+        let orders = ["order","initial_order"].map(|name| entities[e].features.get(name)
+            .or_else(|| complex[e].filter(|_| last_fine[complex[e].unwrap()]==Some(e)).and_then(|c| entities[c].features.get(name)))
+            .and_then(|v| v.parse::<i32>().ok()));
+        let orders = match orders{
+            [Some(order),initial_order] => Some((order,initial_order.unwrap_or(order))),
+            _ => None,
+        };
+        result.push(MeasuredCompartment { cylinder, parent, lateral: attach[e].is_some_and(|(_,lateral)| lateral), data, bud_state, orders });
+    }
+    tree_from_measured(&result, settings).map_err(|e| format!("MTG: {e}"))
+}
+
+pub fn load_mtg(path: &Path, settings: &Settings, options: &MtgOptions) -> Result<Tree,String>{
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+    read_mtg(&text, settings, options).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //main stem with active and dormant laterals
+    fn grown() -> Tree{
+        let mut tree = Tree::with_seed(&Settings::default(), 6);
+        for _ in 0..25{
+            tree.extend_main();
+        }
+        for _ in 0..2{
+            tree.activate_random_with_order(1).unwrap();
+        }
+        for _ in 0..10{
+            tree.extend_random_with_order(1).unwrap();
+        }
+        tree
+    }
+
+    //(main child, secondary child, bud state, order, segments) in preorder, with preorder positions as links
+    fn shape(tree: &Tree) -> Vec<(Option<usize>,Option<usize>,BudState,i32,usize)>{
+        let order: Vec<NodeId> = tree.preorder().collect();
+        let position = |id: Option<NodeId>| id.map(|id| order.iter().position(|&o| o==id).unwrap());
+        order.iter().map(|&id|{
+            let node = &tree[id];
+            (position(node.main_child),position(node.secondary_child),node.bud_state.clone(),node.order,node.segments.len())
+        }).collect()
+    }
+
+    #[test]
+    fn round_trip(){
+        let tree = grown();
+        let states: Vec<(BudState,i32)> = shape(&tree).into_iter().map(|s| (s.2,s.3)).collect();
+        assert!(states.contains(&(BudState::ActiveBud,1)) && states.contains(&(BudState::DormantBud,1)));
+        let text = write_mtg(&tree, &GeometryParams::default(), &MtgOptions { scale: 10. });
+        let loaded = read_mtg(&text, &Settings::default(), &MtgOptions { scale: 10. }).unwrap();
+        assert!(loaded.validate().is_empty());
+        assert_eq!(shape(&loaded),shape(&tree));
+        for (a,b) in tree.preorder().zip(loaded.preorder()){
+            assert!((tree[a].data.auxin-loaded[b].data.auxin).abs()<1e-6);
+        }
+        assert_eq!(loaded.tip_indices.len(),tree.tip_indices.len());
+    }
+}
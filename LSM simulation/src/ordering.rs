@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::topology::*;
+
+//ways of assigning a branching order to every node, the main stem has order 0 in botanical schemes
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Eq,Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderingScheme{
+    //node.order, changed by decrease_order after decapitation
+    Current,
+    //node.initial_order as stored in the tree
+    Initial,
+    //botanical order used by the scanner, number of lateral links between the node and the base
+    Gravelius,
+    //1 at the tips, increased where two branches of equal order meet
+    Strahler,
+    //Strahler order of the whole stream, propagated from the base along the highest order child
+    Horton,
+}
+
+impl OrderingScheme{
+    pub const ALL: [OrderingScheme; 5] = [
+        OrderingScheme::Current,
+        OrderingScheme::Initial,
+        OrderingScheme::Gravelius,
+        OrderingScheme::Strahler,
+        OrderingScheme::Horton,
+    ];
+    pub fn name(&self) -> &'static str{
+        match self{
+            OrderingScheme::Current => "current",
+            OrderingScheme::Initial => "initial",
+            OrderingScheme::Gravelius => "gravelius",
+            OrderingScheme::Strahler => "strahler",
+            OrderingScheme::Horton => "horton",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<OrderingScheme>{
+        OrderingScheme::ALL.into_iter().find(|s| s.name()==name)
+    }
+}
+
+//order of every node of a tree under one scheme
+#[derive(Clone,Debug)]
+pub struct NodeOrders{
+    pub scheme: OrderingScheme,
+    orders: Vec<i32>,
+}
+
+impl NodeOrders{
+    pub fn order(&self, id: NodeId) -> i32{
+        self.orders[id.0]
+    }
+    pub fn orders(&self) -> &[i32]{
+        &self.orders
+    }
+    pub fn max_order(&self) -> i32{
+        self.orders.iter().copied().max().unwrap_or(0)
+    }
+    //nodes of every order, in node index order
+    pub fn group_by(&self) -> BTreeMap<i32,Vec<NodeId>>{
+        let mut groups: BTreeMap<i32,Vec<NodeId>> = BTreeMap::new();
+        for (i,order) in self.orders.iter().enumerate(){
+            groups.entry(*order).or_default().push(NodeId(i));
+        }
+        groups
+    }
+    //auxin and PIN of all compartments (node and its segments) grouped by order
+    pub fn values_by_order(&self, tree: &Tree) -> BTreeMap<i32,Vec<(f32,f32)>>{
+        let mut groups: BTreeMap<i32,Vec<(f32,f32)>> = BTreeMap::new();
+        for node in &tree.nodes{
+            let values = groups.entry(self.order(node.index)).or_default();
+            values.push((node.data.auxin,node.data.pin));
+            values.extend(node.segments.iter().map(|s| (s.data.auxin,s.data.pin)));
+        }
+        groups
+    }
+}
+
+impl Tree{
+    pub fn orders(&self, scheme: OrderingScheme) -> NodeOrders{
+        let orders = match scheme{
+            OrderingScheme::Current => self.nodes.iter().map(|n| n.order).collect(),
+            OrderingScheme::Initial => self.nodes.iter().map(|n| n.initial_order).collect(),
+            OrderingScheme::Gravelius => gravelius(self),
+            OrderingScheme::Strahler => strahler(self),
+            OrderingScheme::Horton => horton(self),
+        };
+        NodeOrders { scheme, orders }
+    }
+}
+
+fn is_lateral(tree: &Tree, id: NodeId) -> bool{
+    match tree[id].parent{
+        Some(parent) => tree[parent].main_child!=Some(id),
+        None => false,
+    }
+}
+
+fn gravelius(tree: &Tree) -> Vec<i32>{
+    let mut orders = vec![0; tree.get_size()];
+    for id in tree.preorder(){
+        if let Some(parent) = tree[id].parent{
+            orders[id.0] = orders[parent.0] + is_lateral(tree, id) as i32;
+        }
+    }
+    orders
+}
+
+//dormant buds are not branches yet, they get order 0 and do not count at junctions
+fn strahler(tree: &Tree) -> Vec<i32>{
+    let mut orders = vec![0; tree.get_size()];
+    for id in tree.postorder(){
+        if tree[id].bud_state==BudState::DormantBud{
+            continue;
+        }
+        let children: Vec<i32> = tree[id].children().map(|c| orders[c.0]).filter(|&o| o>0).collect();
+        let highest = children.iter().copied().max().unwrap_or(0);
+        orders[id.0] = match children.iter().filter(|&&o| o==highest).count(){
+            0 => 1,
+            1 => highest,
+            _ => highest+1,
+        };
+    }
+    orders
+}
+
+fn horton(tree: &Tree) -> Vec<i32>{
+    let strahler = strahler(tree);
+    let mut orders = vec![0; tree.get_size()];
+    orders[NodeId::ROOT.0] = strahler[NodeId::ROOT.0];
+    for id in tree.preorder(){
+        //the stream continues into the child of highest Strahler order, the main child on ties
+        let continuation = tree[id].children().max_by_key(|c| (strahler[c.0], tree[id].main_child==Some(*c)));
+        for child in tree[id].children(){
+            orders[child.0] = if Some(child)==continuation && strahler[child.0]>0 {orders[id.0]} else {strahler[child.0]};
+        }
+    }
+    orders
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::topology::tests::hand_built;
+
+    fn orders(tree: &Tree, scheme: OrderingScheme) -> Vec<i32>{
+        tree.orders(scheme).orders().to_vec()
+    }
+
+    //two active laterals meet at node 1, the dormant bud 6 is not a branch
+    //   3   5 6
+    //   |   |/
+    //   1---4
+    //   |
+    //   0---2
+    #[test]
+    fn confluence_of_first_order_streams(){
+        let tree = hand_built(&[
+            (Some(1),Some(2),BudState::BranchingSegment),
+            (Some(3),Some(4),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+            (Some(5),Some(6),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::DormantBud),
+        ]);
+        assert_eq!(orders(&tree,OrderingScheme::Gravelius),vec![0,0,1,0,1,1,2]);
+        assert_eq!(orders(&tree,OrderingScheme::Strahler),vec![2,2,1,1,1,1,0]);
+        assert_eq!(orders(&tree,OrderingScheme::Horton),vec![2,2,1,2,1,1,0]);
+        assert_eq!(orders(&tree,OrderingScheme::Current),orders(&tree,OrderingScheme::Gravelius));
+    }
+
+    //two second order streams meet at the root
+    //   3 4 5 6
+    //   |/  |/
+    //   1   2
+    //   |  /
+    //   0
+    #[test]
+    fn confluence_of_second_order_streams(){
+        let tree = hand_built(&[
+            (Some(1),Some(2),BudState::BranchingSegment),
+            (Some(3),Some(4),BudState::BranchingSegment),
+            (Some(5),Some(6),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+        ]);
+        let strahler = tree.orders(OrderingScheme::Strahler);
+        assert_eq!(strahler.orders(),&[3,2,2,1,1,1,1]);
+        assert_eq!(strahler.max_order(),3);
+        assert_eq!(strahler.group_by()[&2],vec![NodeId(1),NodeId(2)]);
+        assert_eq!(orders(&tree,OrderingScheme::Horton),vec![3,3,2,3,1,2,1]);
+        assert_eq!(orders(&tree,OrderingScheme::Gravelius),vec![0,0,1,0,1,1,2]);
+    }
+
+    //the stream continues into the lateral when it has the higher Strahler order
+    #[test]
+    fn horton_follows_the_higher_order_child(){
+        let tree = hand_built(&[
+            (Some(1),Some(2),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (Some(3),Some(4),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+        ]);
+        assert_eq!(orders(&tree,OrderingScheme::Strahler),vec![2,1,2,1,1]);
+        assert_eq!(orders(&tree,OrderingScheme::Horton),vec![2,1,2,2,1]);
+    }
+
+    #[test]
+    fn scheme_names(){
+        for scheme in OrderingScheme::ALL{
+            assert_eq!(OrderingScheme::from_name(scheme.name()),Some(scheme));
+        }
+        assert_eq!(OrderingScheme::from_name("shreve"),None);
+    }
+}
@@ -0,0 +1,171 @@
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::measured::*;
+
+//import of TreeQSM cylinder tables (one row per cylinder) as measured trees
+//columns are matched by name, units in brackets are ignored:
+//radius, length, start_x start_y start_z (or start / start_point spanning 3 columns),
+//axis_x axis_y axis_z (or axis / axis_direction), parent, and optionally extension and branch
+//parent and extension are 1-based with 0 for none as written by TreeQSM, a file with -1 in parent is read as 0-based
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+#[serde(default)]
+pub struct QsmOptions{
+    //multiplies positions, lengths and radii, e.g. 100 for metres to centimetres
+    pub scale: f32,
+    //scanner coordinates have z pointing up, the model uses y
+    pub z_up: bool,
+}
+impl Default for QsmOptions{
+    fn default() -> Self {
+        QsmOptions { scale: 1., z_up: true }
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct QsmCylinder{
+    pub start: [f32;3],
+    pub axis: [f32;3],
+    pub length: f32,
+    pub radius: f32,
+    pub parent: Option<usize>,
+    pub extension: Option<usize>,
+    pub branch: Option<usize>,
+}
+
+impl QsmCylinder{
+    fn cylinder(&self, options: &QsmOptions) -> Cylinder{
+        let convert = |p: [f32;3]| {
+            let p = p.map(|x| x*options.scale);
+            if options.z_up {[p[0],p[2],-p[1]]} else {p}
+        };
+        let end = [0,1,2].map(|i| self.start[i]+self.axis[i]*self.length);
+        Cylinder { start: convert(self.start), end: convert(end), radius: self.radius*options.scale }
+    }
+}
+
+fn column_name(header: &str) -> String{
+    let name = match header.find('('){
+        Some(i) => &header[..i],
+        None => header,
+    };
+    name.trim().trim_start_matches('#').to_lowercase().replace([' ','.','-'], "_")
+}
+
+//header names with vector columns (start, axis) expanded to their components
+fn expand_header(header: &[String], columns: usize) -> Vec<String>{
+    let mut extra = columns.saturating_sub(header.len());
+    let mut result = vec![];
+    for name in header{
+        let vector = match name.as_str(){
+            "start" | "start_point" => Some("start"),
+            "axis" | "axis_direction" => Some("axis"),
+            _ => None,
+        };
+        match vector{
+            Some(vector) if extra>=2 => {
+                result.extend(["x","y","z"].map(|c| format!("{vector}_{c}")));
+                extra-=2;
+            }
+            _ => result.push(name.clone()),
+        }
+    }
+    result
+}
+
+pub fn read_qsm(text: &str) -> Result<Vec<QsmCylinder>,String>{
+    let mut lines = text.lines().filter(|l| !l.trim().is_empty());
+    let header_line = lines.next().ok_or("empty QSM file")?;
+    let delimiter = ['\t',';',','].into_iter().find(|d| header_line.contains(*d));
+    let split = |line: &str| -> Vec<String> {
+        match delimiter{
+            Some(d) => line.split(d).map(|s| s.trim().to_string()).collect(),
+            None => line.split_whitespace().map(|s| s.to_string()).collect(),
+        }
+    };
+    let header: Vec<String> = split(header_line).iter().map(|h| column_name(h)).filter(|h| !h.is_empty()).collect();
+    let rows: Vec<Vec<String>> = lines.map(|l| split(l)).collect();
+    let columns = rows.first().map_or(header.len(), |r| r.len());
+    let header = expand_header(&header, columns);
+    let position = |name: &str| header.iter().position(|h| h==name);
+    let required = |name: &str| position(name).ok_or(format!("QSM file has no {name} column"));
+
+    let radius = required("radius")?;
+    let length = required("length")?;
+    let start = [required("start_x")?,required("start_y")?,required("start_z")?];
+    let axis = [required("axis_x")?,required("axis_y")?,required("axis_z")?];
+    let parent = required("parent")?;
+    let extension = position("extension");
+    let branch = position("branch");
+
+    let mut parsed = vec![];
+    for (line,row) in rows.iter().enumerate(){
+        let number = |column: usize| -> Result<f32,String> {
+            row.get(column).and_then(|v| v.parse().ok()).ok_or(format!("row {}: invalid value in column {}",line+2,header[column]))
+        };
+        parsed.push((
+            start.map(|c| number(c)),
+            axis.map(|c| number(c)),
+            number(length)?,
+            number(radius)?,
+            number(parent)? as i64,
+            extension.map(|c| number(c)).transpose()?.map(|v| v as i64),
+            branch.map(|c| number(c)).transpose()?.map(|v| v as usize),
+        ));
+    }
+    let zero_based = parsed.iter().any(|p| p.4<0);
+    let link = |value: i64| -> Option<usize> {
+        if zero_based {usize::try_from(value).ok()} else {usize::try_from(value-1).ok()}
+    };
+    let mut cylinders = vec![];
+    for (start,axis,length,radius,parent,extension,branch) in parsed{
+        let [x,y,z] = start;
+        let [ax,ay,az] = axis;
+        cylinders.push(QsmCylinder {
+            start: [x?,y?,z?],
+            axis: [ax?,ay?,az?],
+            length,
+            radius,
+            parent: link(parent),
+            extension: extension.and_then(link),
+            branch,
+        });
+    }
+    Ok(cylinders)
+}
+
+//cylinders become segments, the cylinder where branches leave becomes the node compartment of a BranchingSegment
+//and the last cylinder of every branch the compartment of an ActiveBud
+pub fn tree_from_qsm(cylinders: &[QsmCylinder], settings: &Settings, options: &QsmOptions) -> Result<Tree,String>{
+    let mut children: Vec<Vec<usize>> = vec![vec![]; cylinders.len()];
+    for (i,cylinder) in cylinders.iter().enumerate(){
+        match cylinder.parent{
+            Some(parent) if parent<cylinders.len() => children[parent].push(i),
+            Some(parent) => return Err(format!("cylinder {} has missing parent {}",i+1,parent+1)),
+            None => {}
+        }
+    }
+    //child continuing the branch: extension column, otherwise a child of the same branch, otherwise the first child
+    let continuation = |i: usize| -> Option<usize> {
+        cylinders[i].extension.filter(|e| children[i].contains(e))
+            .or_else(|| children[i].iter().copied().find(|&c| cylinders[c].branch.is_some() && cylinders[c].branch==cylinders[i].branch))
+            .or_else(|| if cylinders[i].branch.is_none() {children[i].first().copied()} else {None})
+    };
+    let compartments: Vec<MeasuredCompartment> = cylinders.iter().enumerate().map(|(i,cylinder)| MeasuredCompartment {
+        cylinder: Some(cylinder.cylinder(options)),
+        parent: cylinder.parent,
+        lateral: cylinder.parent.is_some_and(|p| continuation(p)!=Some(i)),
+        data: None,
+        bud_state: None,
+        orders: None,
+    }).collect();
+    tree_from_measured(&compartments, settings).map_err(|e| format!("QSM: {e}"))
+}
+
+pub fn load_qsm(path: &Path, settings: &Settings, options: &QsmOptions) -> Result<Tree,String>{
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+    let cylinders = read_qsm(&text).map_err(|e| format!("{}: {e}",path.display()))?;
+    tree_from_qsm(&cylinders, settings, options).map_err(|e| format!("{}: {e}",path.display()))
+}
@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::design::DesignSample;
+
+//layout of batch results, one directory per run (settings, scenario and master seed):
+//{dir}/{scenario}/{run_id}/manifest.json next to the files of the run, and {dir}/index.json listing all runs
+//the run id is a hash of the settings, scenario and master seed, so a parameter set always maps to the same directory
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+pub const INDEX_FILE: &str = "index.json";
+
+//batch run configuration shared by the runner and the manifest
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunOptions{
+    pub output: PathBuf,
+    pub master_seed: u64,
+    pub replicates: u64,
+    pub precision: f32,
+    //transport iterations before a replicate is given up as not converged, unlimited when None
+    pub max_iterations: Option<usize>,
+    //whole trees as trees.pickle, so replicates can be opened in the viewer without growing them again
+    pub save_trees: bool,
+    //run again runs whose results are already complete instead of skipping them
+    #[serde(default)]
+    pub rerun: bool,
+}
+impl Default for RunOptions{
+    fn default() -> Self{
+        RunOptions { output: PathBuf::from("./results"), master_seed: 2023, replicates: 20, precision: 0.001, max_iterations: None, save_trees: false, rerun: false }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ReplicateRecord{
+    pub replicate: u64,
+    pub seed: u64,
+    pub seconds: f32,
+    pub convergence: Convergence,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RunManifest{
+    pub run_id: String,
+    pub scenario: Scenario,
+    pub settings: Settings,
+    //values of the sweep or design point the settings come from, parameters and derived parameters
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    //design and sample index when the point comes from a space-filling design
+    #[serde(default)]
+    pub design: Option<DesignSample>,
+    pub master_seed: u64,
+    pub precision: f32,
+    pub max_iterations: Option<usize>,
+    pub version: String,
+    pub git_hash: Option<String>,
+    //unix time in seconds
+    pub started: u64,
+    pub seconds: f32,
+    //every replicate reached the precision
+    pub converged: bool,
+    pub replicates: Vec<ReplicateRecord>,
+    //mean of every main stem metric over the replicates, see metrics.rs
+    #[serde(default)]
+    pub metrics: BTreeMap<String,f32>,
+    //files of the run by kind, relative to the run directory
+    pub files: BTreeMap<String,String>,
+}
+
+impl RunManifest{
+    pub fn new(settings: &Settings, scenario: &Scenario, options: &RunOptions) -> RunManifest{
+        RunManifest {
+            run_id: run_id(settings, &scenario.name, options.master_seed),
+            scenario: scenario.clone(),
+            settings: settings.clone(),
+            parameters: BTreeMap::new(),
+            design: None,
+            master_seed: options.master_seed,
+            precision: options.precision,
+            max_iterations: options.max_iterations,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: git_hash(),
+            started: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            seconds: 0.,
+            converged: true,
+            replicates: vec![],
+            metrics: BTreeMap::new(),
+            files: BTreeMap::new(),
+        }
+    }
+    pub fn add_replicate(&mut self, record: ReplicateRecord){
+        self.converged &= record.convergence.converged;
+        self.replicates.push(record);
+    }
+    pub fn add_file(&mut self, kind: &str, file: &str){
+        self.files.insert(kind.to_string(), file.to_string());
+    }
+}
+
+//FNV-1a of the parameter values in the order of PARAMETER_NAMES, the scenario name and the master seed,
+//so the id does not depend on how Settings is serialized
+pub fn run_id(settings: &Settings, scenario: &str, master_seed: u64) -> String{
+    let mut bytes = vec![];
+    for name in PARAMETER_NAMES{
+        let value = settings.parameter(name).unwrap_or_default();
+        bytes.extend(name.bytes().chain([0]).chain(value.to_bits().to_le_bytes()));
+    }
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes.into_iter().chain(scenario.bytes()).chain([0]).chain(master_seed.to_le_bytes()){
+        hash = (hash ^ byte as u64).wrapping_mul(0x100000001b3);
+    }
+    format!("{hash:016x}")
+}
+
+//commit the binary was built from, set by build.rs, None when it was built outside a git checkout
+pub fn git_hash() -> Option<String>{
+    option_env!("GIT_HASH").map(|hash| hash.to_string())
+}
+
+//written next to the target and renamed, so a reader or a concurrent shard never sees a partial file
+fn write_atomic(path: &Path, text: &str) -> Result<(),String>{
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{file_name}.{}.tmp",std::process::id()));
+    std::fs::write(&temporary, text).map_err(|e| format!("{}: {e}",temporary.display()))?;
+    std::fs::rename(&temporary, path).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+//files of a run directory except the manifest
+fn copy_run_files(from: &Path, to: &Path) -> Result<(),String>{
+    std::fs::create_dir_all(to).map_err(|e| format!("{}: {e}",to.display()))?;
+    for entry in std::fs::read_dir(from).map_err(|e| format!("{}: {e}",from.display()))?.flatten(){
+        if entry.file_name()==MANIFEST_FILE{
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir(){
+            copy_run_files(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target).map_err(|e| format!("{}: {e}",target.display()))?;
+        }
+    }
+    Ok(())
+}
+
+//entry of index.json
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexEntry{
+    pub run_id: String,
+    pub scenario: String,
+    pub master_seed: u64,
+    pub converged: bool,
+    //run directory relative to the results directory
+    pub dir: String,
+    pub settings: Settings,
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    #[serde(default)]
+    pub metrics: BTreeMap<String,f32>,
+}
+
+pub struct ResultsDir{
+    pub root: PathBuf,
+}
+impl ResultsDir{
+    pub fn new(root: &Path) -> ResultsDir{
+        ResultsDir { root: root.to_path_buf() }
+    }
+    fn relative(scenario: &str, run_id: &str) -> String{
+        format!("{scenario}/{run_id}")
+    }
+    pub fn run_dir(&self, manifest: &RunManifest) -> PathBuf{
+        self.root.join(ResultsDir::relative(&manifest.scenario.name, &manifest.run_id))
+    }
+    pub fn create_run_dir(&self, manifest: &RunManifest) -> Result<PathBuf,String>{
+        let dir = self.run_dir(manifest);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+        Ok(dir)
+    }
+    //path of a file of the run, recorded in the manifest under kind
+    pub fn file(&self, manifest: &mut RunManifest, kind: &str, file: &str) -> PathBuf{
+        manifest.add_file(kind, file);
+        self.run_dir(manifest).join(file)
+    }
+    //removed before the files of a run are written again, so an interrupted rerun is not taken as complete
+    pub fn remove_manifest(&self, manifest: &RunManifest) -> Result<(),String>{
+        let path = self.run_dir(manifest).join(MANIFEST_FILE);
+        match std::fs::remove_file(&path){
+            Err(e) if e.kind()!=std::io::ErrorKind::NotFound => Err(format!("{}: {e}",path.display())),
+            _ => Ok(()),
+        }
+    }
+    //the manifest is written after the files of the run and marks it as complete
+    pub fn write_manifest(&self, manifest: &RunManifest) -> Result<(),String>{
+        let path = self.create_run_dir(manifest)?.join(MANIFEST_FILE);
+        let text = serde_json::to_string_pretty(manifest).map_err(|e| e.to_string())?;
+        write_atomic(&path, &text)
+    }
+    pub fn read_manifest(path: &Path) -> Result<RunManifest,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        serde_json::from_str(&text).map_err(|e| format!("{}: {e}",path.display()))
+    }
+    //manifests of all runs in the results directory
+    pub fn manifests(&self) -> Result<Vec<RunManifest>,String>{
+        let mut manifests = vec![];
+        let Ok(scenarios) = std::fs::read_dir(&self.root) else {return Ok(manifests)};
+        for scenario in scenarios.flatten().filter(|e| e.path().is_dir()){
+            let runs = std::fs::read_dir(scenario.path()).map_err(|e| format!("{}: {e}",scenario.path().display()))?;
+            for run in runs.flatten(){
+                let path = run.path().join(MANIFEST_FILE);
+                if path.is_file(){
+                    manifests.push(ResultsDir::read_manifest(&path)?);
+                }
+            }
+        }
+        manifests.sort_by(|a,b| (&a.scenario.name,&a.run_id).cmp(&(&b.scenario.name,&b.run_id)));
+        Ok(manifests)
+    }
+    //manifest of the run with exactly these settings, if it was saved
+    pub fn find(&self, settings: &Settings, scenario: &str, master_seed: u64) -> Option<RunManifest>{
+        let path = self.root.join(ResultsDir::relative(scenario, &run_id(settings, scenario, master_seed))).join(MANIFEST_FILE);
+        ResultsDir::read_manifest(&path).ok()
+    }
+    //manifest of a complete run with exactly these settings and options, with all its files present
+    //a run of an edited scenario file with an unchanged name is not complete
+    pub fn completed(&self, settings: &Settings, scenario: &Scenario, options: &RunOptions) -> Option<RunManifest>{
+        let manifest = self.find(settings, &scenario.name, options.master_seed)?;
+        let dir = self.run_dir(&manifest);
+        let definition = |scenario: &Scenario| serde_json::to_value(Scenario { description: String::new(), ..scenario.clone() }).ok();
+        let same_scenario = definition(&manifest.scenario)==definition(scenario);
+        let complete = same_scenario
+            && manifest.replicates.len() as u64==options.replicates
+            && manifest.precision==options.precision
+            && manifest.max_iterations==options.max_iterations
+            && (!options.save_trees || manifest.files.contains_key("trees"))
+            && manifest.files.values().all(|file| dir.join(file).is_file());
+        complete.then_some(manifest)
+    }
+    //runs of other results directories, e.g. of shards run on other machines, copied into this one
+    //runs already here are kept, returns the number of copied runs
+    pub fn merge(&self, other: &ResultsDir) -> Result<usize,String>{
+        let mut copied = 0;
+        for manifest in other.manifests()?{
+            let target = self.run_dir(&manifest);
+            if target.join(MANIFEST_FILE).is_file(){
+                continue;
+            }
+            copy_run_files(&other.run_dir(&manifest), &target)?;
+            self.write_manifest(&manifest)?;
+            copied += 1;
+        }
+        Ok(copied)
+    }
+    //index.json rebuilt from the manifests, written once the batch is done since runs finish in parallel
+    pub fn write_index(&self) -> Result<(),String>{
+        let index: Vec<IndexEntry> = self.manifests()?.into_iter().map(|m| IndexEntry {
+            dir: ResultsDir::relative(&m.scenario.name, &m.run_id),
+            run_id: m.run_id,
+            scenario: m.scenario.name,
+            master_seed: m.master_seed,
+            converged: m.converged,
+            settings: m.settings,
+            parameters: m.parameters,
+            metrics: m.metrics,
+        }).collect();
+        std::fs::create_dir_all(&self.root).map_err(|e| format!("{}: {e}",self.root.display()))?;
+        let text = serde_json::to_string_pretty(&index).map_err(|e| e.to_string())?;
+        write_atomic(&self.root.join(INDEX_FILE), &text)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn run_id_of_parameter_values(){
+        let settings = Settings::default();
+        let id = run_id(&settings, "WT", 2023);
+        assert_eq!(id.len(),16);
+        assert_eq!(run_id(&settings.clone(), "WT", 2023),id);
+        let mut changed = settings.clone();
+        changed.set_parameter("pin_production_2", 0.07).unwrap();
+        assert_ne!(run_id(&changed, "WT", 2023),id);
+        assert_ne!(run_id(&settings, "rnai60", 2023),id);
+        assert_ne!(run_id(&settings, "WT", 2024),id);
+    }
+
+    #[test]
+    fn removed_manifest_is_not_complete(){
+        let root = std::env::temp_dir().join(format!("tree_d_results_{}",std::process::id()));
+        let results = ResultsDir::new(&root);
+        let options = RunOptions { output: root.clone(), replicates: 0, ..Default::default() };
+        let scenario = ScenarioRegistry::builtin().get("WT").cloned().unwrap();
+        let settings = Settings::default();
+        let manifest = RunManifest::new(&settings, &scenario, &options);
+        results.remove_manifest(&manifest).unwrap();
+        results.write_manifest(&manifest).unwrap();
+        assert!(results.completed(&settings, &scenario, &options).is_some());
+        let mut edited = scenario.clone();
+        edited.description = "edited".to_string();
+        assert!(results.completed(&settings, &edited, &options).is_some());
+        edited.growth = ScenarioRegistry::builtin().get("rnai60").unwrap().growth.clone();
+        assert!(results.completed(&settings, &edited, &options).is_none());
+        results.remove_manifest(&manifest).unwrap();
+        assert!(results.completed(&settings, &scenario, &options).is_none());
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
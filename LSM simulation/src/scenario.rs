@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::growth::*;
+use crate::treatments::*;
+
+//genotype or experiment definition loaded from TOML/JSON, see ./scenarios
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Scenario{
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    //overrides of Settings fields by name, see PARAMETER_NAMES
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    pub growth: GrowthSchedule,
+    #[serde(default)]
+    pub treatments: Vec<TreatmentSpec>,
+}
+
+impl Scenario{
+    pub fn from_toml(text: &str) -> Result<Scenario,String>{
+        let scenario: Scenario = toml::from_str(text).map_err(|e| e.to_string())?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+    pub fn from_json(text: &str) -> Result<Scenario,String>{
+        let scenario: Scenario = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        scenario.check()?;
+        Ok(scenario)
+    }
+    pub fn load(path: &Path) -> Result<Scenario,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let scenario = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => Scenario::from_json(&text),
+            _ => Scenario::from_toml(&text),
+        };
+        scenario.map_err(|e| format!("{}: {e}",path.display()))
+    }
+    fn check(&self) -> Result<(),String>{
+        self.growth.check()?;
+        let mut settings = Settings::default();
+        for (name,value) in &self.parameters{
+            settings.set_parameter(name, *value)?;
+        }
+        Ok(())
+    }
+
+    pub fn settings(&self, base: &Settings) -> Settings{
+        let mut settings = base.clone();
+        for (name,value) in &self.parameters{
+            settings.set_parameter(name, *value).unwrap();
+        }
+        settings
+    }
+    //grows the tree and schedules the treatments on it, the same seed gives the same tree
+    pub fn build(&self, base: &Settings, seed: u64) -> Result<Tree,String>{
+        let settings = self.settings(base);
+        let mut tree = self.growth.grow(&settings, seed).map_err(|e| format!("scenario {}: {e}",self.name))?;
+        for spec in &self.treatments{
+            let treatment = spec.resolve(&tree).ok_or(format!("scenario {}: treatment {:?} has no site on the tree",self.name,spec))?;
+            tree.add_treatment(treatment);
+        }
+        Ok(tree)
+    }
+}
+
+const BUILTIN_SCENARIOS: [&str; 7] = [
+    include_str!("../scenarios/wild_type.toml"),
+    include_str!("../scenarios/wild_type_decapitated.toml"),
+    include_str!("../scenarios/rnai60.toml"),
+    include_str!("../scenarios/rnai60_decapitated.toml"),
+    include_str!("../scenarios/kanttarelli.toml"),
+    include_str!("../scenarios/pole.toml"),
+    include_str!("../scenarios/pole_segments_activated.toml"),
+];
+
+//scenarios listed by the batch runner and the GUI
+#[derive(Clone,Debug)]
+pub struct ScenarioRegistry{
+    scenarios: Vec<Scenario>,
+}
+impl ScenarioRegistry{
+    pub fn builtin() -> ScenarioRegistry{
+        let mut registry = ScenarioRegistry { scenarios: vec![] };
+        for text in BUILTIN_SCENARIOS{
+            registry.insert(Scenario::from_toml(text).expect("invalid builtin scenario"));
+        }
+        registry
+    }
+    //builtin scenarios together with *.toml and *.json files from the directory, files replace builtins with the same name
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_dir(dir: &Path) -> Result<ScenarioRegistry,String>{
+        let mut registry = ScenarioRegistry::builtin();
+        if !dir.is_dir(){
+            return Ok(registry);
+        }
+        let mut paths: Vec<_> = std::fs::read_dir(dir).map_err(|e| e.to_string())?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| matches!(path.extension().and_then(|e| e.to_str()), Some("toml") | Some("json")))
+            .collect();
+        paths.sort();
+        for path in paths{
+            registry.insert(Scenario::load(&path)?);
+        }
+        Ok(registry)
+    }
+    pub fn insert(&mut self, scenario: Scenario){
+        match self.scenarios.iter().position(|s| s.name==scenario.name){
+            Some(i) => self.scenarios[i]=scenario,
+            None => self.scenarios.push(scenario),
+        }
+    }
+    pub fn get(&self, name: &str) -> Option<&Scenario>{
+        self.scenarios.iter().find(|s| s.name==name)
+    }
+    pub fn names(&self) -> Vec<String>{
+        self.scenarios.iter().map(|s| s.name.clone()).collect()
+    }
+    pub fn scenarios(&self) -> &[Scenario]{
+        &self.scenarios
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn builtin_scenarios_build(){
+        for scenario in ScenarioRegistry::builtin().scenarios(){
+            assert!(scenario.build(&Settings::default(), 1).is_ok(),"{}",scenario.name);
+        }
+    }
+
+    #[test]
+    fn unresolved_treatments_are_errors(){
+        let text = "name = \"stump_iaa\"\n[growth]\nkind = \"pole\"\nsize = 5\n[[treatments]]\ntype = \"iaa\"\nsite = \"stump\"\nrate = 1\n";
+        let error = Scenario::from_toml(text).unwrap().build(&Settings::default(), 1).unwrap_err();
+        assert!(error.contains("scenario stump_iaa: treatment") && error.contains("no site"),"{error}");
+        let text = text.replace("site = \"stump\"", "site = \"main_tip\"");
+        assert_eq!(Scenario::from_toml(&text).unwrap().build(&Settings::default(), 1).unwrap().treatments.len(),1);
+    }
+}
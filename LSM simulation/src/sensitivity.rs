@@ -0,0 +1,382 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::expr::*;
+use crate::design::*;
+use crate::sweep::*;
+use crate::results::*;
+use crate::batch::*;
+use crate::metrics::{OutputMetric, mean};
+
+//global sensitivity of an output metric to bounded Settings fields, see sweeps/README.md:
+//morris - elementary effects along random one-at-a-time trajectories, trajectories*(parameters+1) points
+//sobol - first and total order indices from Saltelli sampling on a Sobol sequence, samples*(parameters+2) points
+//every point is the mean of the metric over the replicates, replicates share seeds between points
+//so differences come from the parameters and not from the growth
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SensitivityMethod{
+    Morris{
+        trajectories: usize,
+        //grid levels of every parameter, steps are levels/(2*(levels-1)) of the range
+        #[serde(default = "default_levels")]
+        levels: usize,
+    },
+    Sobol{
+        //base samples, the Sobol sequence is used for both sample matrices so parameters+parameters <= 21
+        samples: usize,
+    },
+}
+fn default_levels() -> usize{
+    4
+}
+fn default_bootstrap() -> usize{
+    1000
+}
+fn default_confidence() -> f32{
+    0.95
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct SensitivityConfig{
+    #[serde(default)]
+    pub name: String,
+    pub scenarios: Vec<String>,
+    #[serde(default)]
+    pub replicates: Option<u64>,
+    #[serde(default)]
+    pub master_seed: Option<u64>,
+    #[serde(default)]
+    pub precision: Option<f32>,
+    #[serde(flatten)]
+    pub method: SensitivityMethod,
+    pub metric: OutputMetric,
+    //seed of the morris trajectories and of the bootstrap
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_bootstrap")]
+    pub bootstrap: usize,
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+    //ranges of the analysed parameters
+    pub bounds: BTreeMap<String,Bounds>,
+    //values of other parameters, fixed during the analysis
+    #[serde(default)]
+    pub parameters: BTreeMap<String,f32>,
+    #[serde(default)]
+    pub derived: BTreeMap<String,Expr>,
+}
+
+//index with its bootstrap confidence interval
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+pub struct Estimate{
+    pub value: f32,
+    pub low: f32,
+    pub high: f32,
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SensitivityIndices{
+    //mean, mean of absolute values and standard deviation of the elementary effects, in metric units per parameter range
+    Morris{ mu: f32, mu_star: Estimate, sigma: f32 },
+    Sobol{ first: Estimate, total: Estimate },
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct SensitivityResult{
+    pub scenario: String,
+    pub parameter: String,
+    pub indices: SensitivityIndices,
+}
+
+impl SensitivityConfig{
+    pub fn load(path: &Path) -> Result<SensitivityConfig,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let config: Result<SensitivityConfig,String> = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        config.and_then(|c| c.check().map(|_| c)).map_err(|e| format!("{}: {e}",path.display()))
+    }
+    fn check(&self) -> Result<(),String>{
+        if self.scenarios.is_empty(){
+            return Err("no scenarios".to_string());
+        }
+        if self.bounds.is_empty(){
+            return Err("no bounds".to_string());
+        }
+        check_names(self.bounds.keys().chain(self.parameters.keys()), &self.derived)?;
+        for (name,bounds) in &self.bounds{
+            if self.parameters.contains_key(name){
+                return Err(format!("{name} is both bounded and fixed"));
+            }
+            bounds.check().map_err(|e| format!("{name}: {e}"))?;
+        }
+        match self.method{
+            SensitivityMethod::Morris{ trajectories, levels } if trajectories<2 || levels<2 => Err("morris needs at least 2 trajectories and 2 levels".to_string()),
+            SensitivityMethod::Sobol{ samples } if samples<2 => Err("sobol needs at least 2 samples".to_string()),
+            SensitivityMethod::Sobol{..} if 2*self.bounds.len()>21 => Err("sobol supports up to 10 parameters".to_string()),
+            _ => Ok(()),
+        }
+    }
+    pub fn scenarios(&self, registry: &ScenarioRegistry) -> Result<Vec<Scenario>,String>{
+        self.scenarios.iter().map(|name| registry.get(name).cloned().ok_or(format!("unknown scenario {name}"))).collect()
+    }
+
+    //points in the unit cube, the dimensions are the bounded parameters in alphabetical order
+    pub fn unit_points(&self) -> Vec<Vec<f64>>{
+        let k = self.bounds.len();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        match self.method{
+            SensitivityMethod::Morris{ trajectories, levels } => {
+                let delta = levels as f64/(2.*(levels as f64-1.));
+                let mut points = vec![];
+                for _ in 0..trajectories{
+                    let mut x: Vec<f64> = (0..k).map(|_| rng.gen_range(0..levels) as f64/(levels as f64-1.)).collect();
+                    points.push(x.clone());
+                    let mut order: Vec<usize> = (0..k).collect();
+                    order.shuffle(&mut rng);
+                    for i in order{
+                        x[i] = if x[i]+delta<=1.+1e-9 {x[i]+delta} else {x[i]-delta};
+                        points.push(x.clone());
+                    }
+                }
+                points
+            }
+            //rows of A, B, then A with column i from B for every i
+            SensitivityMethod::Sobol{ samples } => {
+                let rows: Vec<Vec<f64>> = (1..=samples).map(|i| sobol(i as u32, 2*k)).collect();
+                let mut points: Vec<Vec<f64>> = rows.iter().map(|r| r[..k].to_vec()).collect();
+                points.extend(rows.iter().map(|r| r[k..].to_vec()));
+                for i in 0..k{
+                    points.extend(rows.iter().map(|r|{
+                        let mut a = r[..k].to_vec();
+                        a[i] = r[k+i];
+                        a
+                    }));
+                }
+                points
+            }
+        }
+    }
+
+    pub fn points(&self, base: &Settings) -> Result<(Vec<Vec<f64>>,Vec<SweepPoint>),String>{
+        let unit = self.unit_points();
+        let points = unit.iter().map(|u|{
+            let mut values = self.parameters.clone();
+            values.extend(self.bounds.iter().zip(u).map(|((name,bounds),&u)| (name.clone(),bounds.value(u))));
+            SweepPoint::new(base, &values, &self.derived)
+        }).collect::<Result<Vec<_>,String>>()?;
+        Ok((unit,points))
+    }
+
+    //indices of every bounded parameter from the metric at every point
+    pub fn indices(&self, unit: &[Vec<f64>], outputs: &[f32]) -> Vec<(String,SensitivityIndices)>{
+        let names: Vec<&String> = self.bounds.keys().collect();
+        let k = names.len();
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed.wrapping_add(1));
+        let alpha = (1.-self.confidence)/2.;
+        match self.method{
+            SensitivityMethod::Morris{ trajectories, .. } => {
+                //elementary effects by parameter, one per trajectory
+                let mut effects = vec![vec![]; k];
+                for t in 0..trajectories{
+                    let start = t*(k+1);
+                    for step in 0..k{
+                        let (a,b) = (&unit[start+step],&unit[start+step+1]);
+                        let i = (0..k).find(|&i| a[i]!=b[i]).unwrap_or(0);
+                        effects[i].push((outputs[start+step+1]-outputs[start+step])/(b[i]-a[i]) as f32);
+                    }
+                }
+                names.iter().zip(effects).map(|(name,effects)|{
+                    let mu = mean(&effects);
+                    let absolute: Vec<f32> = effects.iter().map(|e| e.abs()).collect();
+                    let sigma = variance(&effects).sqrt();
+                    let mu_star = bootstrap(&mut rng, self.bootstrap, alpha, absolute.len(), |sample| mean(&sample.iter().map(|&j| absolute[j]).collect::<Vec<_>>()));
+                    (name.to_string(),SensitivityIndices::Morris { mu, mu_star, sigma })
+                }).collect()
+            }
+            SensitivityMethod::Sobol{ samples: n } => {
+                let a = &outputs[..n];
+                let b = &outputs[n..2*n];
+                names.iter().enumerate().map(|(i,name)|{
+                    let ab = &outputs[(2+i)*n..(3+i)*n];
+                    //Saltelli 2010 first order and Jansen total order estimators
+                    let indices = |sample: &[usize]|{
+                        let all: Vec<f32> = sample.iter().flat_map(|&j| [a[j],b[j]]).collect();
+                        let v = variance(&all).max(f32::EPSILON);
+                        let first = sample.iter().map(|&j| b[j]*(ab[j]-a[j])).sum::<f32>()/sample.len() as f32/v;
+                        let total = sample.iter().map(|&j| (a[j]-ab[j]).powi(2)).sum::<f32>()/(2.*sample.len() as f32)/v;
+                        (first,total)
+                    };
+                    let first = bootstrap(&mut rng, self.bootstrap, alpha, n, |sample| indices(sample).0);
+                    let total = bootstrap(&mut rng, self.bootstrap, alpha, n, |sample| indices(sample).1);
+                    (name.to_string(),SensitivityIndices::Sobol { first, total })
+                }).collect()
+            }
+        }
+    }
+}
+
+fn variance(values: &[f32]) -> f32{
+    let m = mean(values);
+    values.iter().map(|v| (v-m)*(v-m)).sum::<f32>()/(values.len().max(2)-1) as f32
+}
+
+//statistic of all rows with the percentile interval of the statistic over resampled rows
+fn bootstrap(rng: &mut ChaCha8Rng, resamples: usize, alpha: f32, rows: usize, statistic: impl Fn(&[usize]) -> f32) -> Estimate{
+    let value = statistic(&(0..rows).collect::<Vec<_>>());
+    let mut values: Vec<f32> = (0..resamples).map(|_|{
+        let sample: Vec<usize> = (0..rows).map(|_| rng.gen_range(0..rows)).collect();
+        statistic(&sample)
+    }).filter(|v| v.is_finite()).collect();
+    if values.is_empty(){
+        return Estimate { value, low: value, high: value };
+    }
+    values.sort_by(|a,b| a.total_cmp(b));
+    let at = |q: f32| values[((q*(values.len()-1) as f32).round() as usize).min(values.len()-1)];
+    Estimate { value, low: at(alpha), high: at(1.-alpha) }
+}
+
+//metric at every point, the mean over the replicates, and the number of points with replicates that did not converge
+pub fn evaluate(points: &[SweepPoint], scenario: &Scenario, metric: OutputMetric, options: &RunOptions, threads: Option<usize>) -> Result<(Vec<f32>,usize),String>{
+    let outputs = run_jobs(points, &scenario.name, threads, |point|{
+        let (trees,records) = simulate_replicates(&point.settings, scenario, options)?;
+        let converged = records.iter().all(|r| r.convergence.converged);
+        Ok((trees.iter().map(|tree| metric.value(tree)).sum::<f32>()/trees.len().max(1) as f32,converged))
+    })?;
+    let unconverged = outputs.iter().filter(|(_,converged)| !converged).count();
+    Ok((outputs.into_iter().map(|(value,_)| value).collect(),unconverged))
+}
+
+//analysis of every scenario, the metric at every point is written to samples.csv and the indices to indices.csv
+//in {output}/sensitivity_{name}
+pub fn run_sensitivity(config: &SensitivityConfig, scenarios: &[Scenario], base: &Settings, options: &RunOptions, threads: Option<usize>) -> Result<Vec<SensitivityResult>,String>{
+    let (unit,points) = config.points(base)?;
+    let dir = options.output.join(format!("sensitivity_{}",config.name));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+    let mut results = vec![];
+    let mut samples = vec![];
+    for scenario in scenarios{
+        let (outputs,unconverged) = evaluate(&points, scenario, config.metric, options, threads)?;
+        if unconverged>0{
+            println!("{}: {unconverged} of {} points did not converge",scenario.name,points.len());
+        }
+        for (point,output) in points.iter().zip(&outputs){
+            samples.push((scenario.name.clone(),point.values.clone(),*output));
+        }
+        for (parameter,indices) in config.indices(&unit, &outputs){
+            results.push(SensitivityResult { scenario: scenario.name.clone(), parameter, indices });
+        }
+    }
+    write_samples(&dir.join("samples.csv"), &samples)?;
+    write_indices(&dir.join("indices.csv"), &results)?;
+    let path = dir.join("config.json");
+    std::fs::write(&path, serde_json::to_string_pretty(config).map_err(|e| e.to_string())?).map_err(|e| format!("{}: {e}",path.display()))?;
+    Ok(results)
+}
+
+fn write_samples(path: &PathBuf, samples: &[(String,BTreeMap<String,f32>,f32)]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    let names: Vec<&String> = samples.first().map_or(vec![], |s| s.1.keys().collect());
+    writer.write_record(["scenario"].into_iter().chain(names.iter().map(|n| n.as_str())).chain(["metric"])).map_err(error)?;
+    for (scenario,values,output) in samples{
+        writer.write_record([scenario.clone()].into_iter().chain(names.iter().map(|n| values[*n].to_string())).chain([output.to_string()])).map_err(error)?;
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+fn write_indices(path: &PathBuf, results: &[SensitivityResult]) -> Result<(),String>{
+    let error = |e: csv::Error| format!("{}: {e}",path.display());
+    let mut writer = csv::Writer::from_path(path).map_err(error)?;
+    writer.write_record(["scenario","parameter","index","value","low","high"]).map_err(error)?;
+    for result in results{
+        let rows = match &result.indices{
+            SensitivityIndices::Morris{ mu, mu_star, sigma } => vec![
+                ("mu",Estimate { value: *mu, low: f32::NAN, high: f32::NAN }),
+                ("mu_star",*mu_star),
+                ("sigma",Estimate { value: *sigma, low: f32::NAN, high: f32::NAN }),
+            ],
+            SensitivityIndices::Sobol{ first, total } => vec![("first",*first),("total",*total)],
+        };
+        for (index,estimate) in rows{
+            let cell = |v: f32| if v.is_nan() {String::new()} else {v.to_string()};
+            writer.write_record([result.scenario.clone(),result.parameter.clone(),index.to_string(),cell(estimate.value),cell(estimate.low),cell(estimate.high)]).map_err(error)?;
+        }
+    }
+    writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn config(method: &str) -> SensitivityConfig{
+        toml::from_str(&format!("scenarios = [\"x\"]\nmetric = \"mean_auxin\"\nbootstrap = 200\n{method}\n[bounds]\na = {{ min = 0, max = 1 }}\nb = {{ min = 0, max = 1 }}\nc = {{ min = 0, max = 1 }}\n")).unwrap()
+    }
+
+    fn assert_close(estimate: &Estimate, expected: f32, tolerance: f32){
+        assert!((estimate.value-expected).abs()<tolerance,"{estimate:?}, expected {expected}");
+        assert!(estimate.low<=estimate.value && estimate.value<=estimate.high,"{estimate:?}");
+    }
+
+    #[test]
+    fn ishigami_sobol_indices(){
+        let config = config("method = \"sobol\"\nsamples = 4096");
+        let unit = config.unit_points();
+        assert_eq!(unit.len(),4096*5);
+        let outputs: Vec<f32> = unit.iter().map(|u|{
+            let x: Vec<f64> = u.iter().map(|u| (2.*u-1.)*std::f64::consts::PI).collect();
+            (x[0].sin()+7.*x[1].sin().powi(2)+0.1*x[2].powi(4)*x[0].sin()) as f32
+        }).collect();
+        //analytic indices of the ishigami function with a = 7 and b = 0.1
+        let expected = [(0.3139,0.5576),(0.4424,0.4424),(0.,0.2437)];
+        let indices = config.indices(&unit, &outputs);
+        for ((name,indices),(first,total)) in indices.iter().zip(expected){
+            match indices{
+                SensitivityIndices::Sobol { first: estimated_first, total: estimated_total } => {
+                    assert_close(estimated_first, first, 0.03);
+                    assert_close(estimated_total, total, 0.03);
+                }
+                _ => panic!("{name}: {indices:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn morris_effects_of_linear_outputs(){
+        let config = config("method = \"morris\"\ntrajectories = 20");
+        let unit = config.unit_points();
+        assert_eq!(unit.len(),20*4);
+        let outputs: Vec<f32> = unit.iter().map(|u| (2.*u[0]-3.*u[1]) as f32).collect();
+        let expected = [(2.,2.),(-3.,3.),(0.,0.)];
+        for ((name,indices),(mu,mu_star)) in config.indices(&unit, &outputs).iter().zip(expected){
+            match indices{
+                SensitivityIndices::Morris { mu: estimated_mu, mu_star: estimated_mu_star, sigma } => {
+                    assert!((estimated_mu-mu).abs()<1e-4 && sigma.abs()<1e-4,"{name}: {indices:?}");
+                    assert_close(estimated_mu_star, mu_star, 1e-4);
+                }
+                _ => panic!("{name}: {indices:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn bootstrap_intervals(){
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let values: Vec<f32> = (0..200).map(|i| (i%10) as f32).collect();
+        let estimate = bootstrap(&mut rng, 500, 0.025, values.len(), |sample| mean(&sample.iter().map(|&j| values[j]).collect::<Vec<_>>()));
+        assert_eq!(estimate.value,4.5);
+        assert!(estimate.low<4.5 && estimate.high>4.5 && estimate.high-estimate.low<1.,"{estimate:?}");
+        let constant = bootstrap(&mut rng, 0, 0.025, 5, |_| 1.);
+        assert_eq!((constant.low,constant.high),(1.,1.));
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::scenario::*;
+use crate::expr::*;
+use crate::design::*;
+
+//parameter sweeps described in TOML or JSON files, see sweeps/README.md
+//every combination of the parameter values is run for every scenario,
+//with every sample of the design when the file has one (see design.rs)
+
+//steps values from start to end
+pub fn iter_range_steps(start:f32,end:f32,steps: i32)->Vec<f32>{
+    if steps==1{
+        return vec![start];
+    }
+    (0..steps).map(|i| (i as f32*(end-start))/(steps as f32-1.)+start).collect()
+}
+//start, start+step, ... up to end, end is included when it is a whole number of steps away up to rounding
+pub fn iter_range_step_by(start:f32,end:f32,step: f32)->Vec<f32>{
+    let steps = ((end-start)/step+1e-4).floor() as i32;
+    (0..steps+1).map(|i| (i as f32* step)+start).collect()
+}
+//steps_side values on each side of the middle
+pub fn iter_range_step_around(middle:f32,steps_side:i32,step_size: f32)->Vec<f32>{
+    let start = middle - (steps_side as f32) * step_size;
+    (0..steps_side*2+1).map(|i| (i as f32* step_size)+start).collect()
+}
+//steps values from start to end evenly spaced on a log scale
+pub fn iter_range_log(start:f32,end:f32,steps: i32)->Vec<f32>{
+    iter_range_steps(start.ln(),end.ln(),steps).into_iter().map(|v| v.exp()).collect()
+}
+
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,Default,PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Scale{
+    #[default]
+    Linear,
+    Log,
+}
+
+//values of one sweep parameter: a value, a list, or a range
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(untagged)]
+pub enum ParameterValues{
+    Value(f32),
+    List(Vec<f32>),
+    //{ start, end, steps, scale = "linear" or "log" }
+    Steps{ start: f32, end: f32, steps: i32, #[serde(default)] scale: Scale },
+    //{ start, end, step }
+    StepBy{ start: f32, end: f32, step: f32 },
+    //{ centre, steps_side, step }
+    Around{ centre: f32, steps_side: i32, step: f32 },
+}
+
+impl ParameterValues{
+    pub fn values(&self) -> Result<Vec<f32>,String>{
+        let values = match self{
+            ParameterValues::Value(value) => vec![*value],
+            ParameterValues::List(values) => values.clone(),
+            ParameterValues::Steps{ start, end, steps, scale } => {
+                if *steps<1{
+                    return Err("a range needs at least one step".to_string());
+                }
+                match scale{
+                    Scale::Linear => iter_range_steps(*start, *end, *steps),
+                    Scale::Log if *start>0. && *end>0. => iter_range_log(*start, *end, *steps),
+                    Scale::Log => return Err("a log range needs positive start and end".to_string()),
+                }
+            }
+            ParameterValues::StepBy{ start, end, step } => {
+                if *step<=0. || end<start{
+                    return Err("a range needs a positive step and end not below start".to_string());
+                }
+                iter_range_step_by(*start, *end, *step)
+            }
+            ParameterValues::Around{ centre, steps_side, step } => {
+                if *steps_side<0{
+                    return Err("steps_side can not be negative".to_string());
+                }
+                iter_range_step_around(*centre, *steps_side, *step)
+            }
+        };
+        if values.is_empty(){
+            return Err("no values".to_string());
+        }
+        Ok(values)
+    }
+}
+
+//settings of one job with the values that defined it, parameters and derived parameters
+#[derive(Clone,Debug)]
+pub struct SweepPoint{
+    pub settings: Settings,
+    pub values: BTreeMap<String,f32>,
+    pub design: Option<DesignSample>,
+}
+
+impl SweepPoint{
+    //base settings with the values of Settings fields applied and the derived parameters evaluated,
+    //values that are not Settings fields are only variables of the derived expressions
+    pub fn new(base: &Settings, values: &BTreeMap<String,f32>, derived: &BTreeMap<String,Expr>) -> Result<SweepPoint,String>{
+        let mut settings = base.clone();
+        for (name,value) in values{
+            if settings.parameter(name).is_some(){
+                settings.set_parameter(name, *value)?;
+            }
+        }
+        let mut context: BTreeMap<String,f64> = PARAMETER_NAMES.iter()
+            .filter(|name| !derived.contains_key(**name))
+            .map(|name| (name.to_string(),settings.parameter(name).unwrap() as f64)).collect();
+        context.extend(values.iter().map(|(name,value)| (name.clone(),*value as f64)));
+        let mut values = values.clone();
+        let mut pending: Vec<(&String,&Expr)> = derived.iter().collect();
+        while !pending.is_empty(){
+            let (ready,waiting): (Vec<_>,Vec<_>) = pending.into_iter()
+                .partition(|(_,expr)| expr.variables().iter().all(|v| !derived.contains_key(v) || context.contains_key(v)));
+            if ready.is_empty(){
+                return Err(format!("derived parameters depend on each other: {}",waiting.iter().map(|(n,_)| n.as_str()).collect::<Vec<_>>().join(", ")));
+            }
+            for (name,expr) in ready{
+                let value = expr.eval(&mut context).map_err(|e| format!("{name}: {e}"))?;
+                context.insert(name.clone(), value);
+                values.insert(name.clone(), value as f32);
+                if settings.parameter(name).is_some(){
+                    settings.set_parameter(name, value as f32)?;
+                }
+            }
+            pending = waiting;
+        }
+        Ok(SweepPoint { settings, values, design: None })
+    }
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct SweepConfig{
+    #[serde(default)]
+    pub name: String,
+    pub scenarios: Vec<String>,
+    //replicates, master seed and precision of the batch runner, command line flags take precedence
+    #[serde(default)]
+    pub replicates: Option<u64>,
+    #[serde(default)]
+    pub master_seed: Option<u64>,
+    #[serde(default)]
+    pub precision: Option<f32>,
+    //values of Settings fields by name (see PARAMETER_NAMES), or of variables used by derived parameters
+    #[serde(default)]
+    pub parameters: BTreeMap<String,ParameterValues>,
+    //parameters computed from the others, e.g. dormant_gain = "gain_ratio * active_gain"
+    #[serde(default)]
+    pub derived: BTreeMap<String,Expr>,
+    //samples of the bounded parameters combined with every combination of the parameter values
+    #[serde(default)]
+    pub design: Option<Design>,
+}
+
+impl SweepConfig{
+    pub fn from_toml(text: &str) -> Result<SweepConfig,String>{
+        let config: SweepConfig = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.check()?;
+        Ok(config)
+    }
+    pub fn from_json(text: &str) -> Result<SweepConfig,String>{
+        let config: SweepConfig = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        config.check()?;
+        Ok(config)
+    }
+    pub fn load(path: &Path) -> Result<SweepConfig,String>{
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let config = match path.extension().and_then(|e| e.to_str()){
+            Some("json") => SweepConfig::from_json(&text),
+            _ => SweepConfig::from_toml(&text),
+        };
+        config.map_err(|e| format!("{}: {e}",path.display()))
+    }
+    fn check(&self) -> Result<(),String>{
+        if self.scenarios.is_empty(){
+            return Err("sweep has no scenarios".to_string());
+        }
+        let bounded = self.design.iter().flat_map(|design| design.bounds.keys());
+        check_names(self.parameters.keys().chain(bounded), &self.derived)?;
+        let mut first = BTreeMap::new();
+        for (name,values) in &self.parameters{
+            first.insert(name.clone(), values.values().map_err(|e| format!("{name}: {e}"))?[0]);
+        }
+        if let Some(design) = &self.design{
+            design.check().map_err(|e| format!("design: {e}"))?;
+            for (name,bounds) in &design.bounds{
+                if self.parameters.contains_key(name){
+                    return Err(format!("{name} is both a parameter and bounded by the design"));
+                }
+                first.insert(name.clone(), bounds.min);
+            }
+        }
+        SweepPoint::new(&Settings::default(), &first, &self.derived)?;
+        Ok(())
+    }
+
+    pub fn scenarios(&self, registry: &ScenarioRegistry) -> Result<Vec<Scenario>,String>{
+        self.scenarios.iter().map(|name| registry.get(name).cloned().ok_or(format!("unknown scenario {name}"))).collect()
+    }
+
+    //every combination of the parameter values on top of the base settings, the last parameter changes fastest,
+    //and for every combination all samples of the design
+    pub fn points(&self, base: &Settings) -> Result<Vec<SweepPoint>,String>{
+        let mut combinations = vec![BTreeMap::new()];
+        for (name,values) in &self.parameters{
+            let values = values.values().map_err(|e| format!("{name}: {e}"))?;
+            combinations = combinations.into_iter().flat_map(|combination: BTreeMap<String,f32>|{
+                values.iter().map(move |&value|{
+                    let mut combination = combination.clone();
+                    combination.insert(name.clone(), value);
+                    combination
+                })
+            }).collect();
+        }
+        let Some(design) = &self.design else {
+            return combinations.iter().map(|values| SweepPoint::new(base, values, &self.derived)).collect();
+        };
+        let samples = design.points();
+        let mut points = vec![];
+        for combination in &combinations{
+            for (sample,values) in &samples{
+                let mut values = values.clone();
+                values.extend(combination.clone());
+                let mut point = SweepPoint::new(base, &values, &self.derived)?;
+                point.design = Some(sample.clone());
+                points.push(point);
+            }
+        }
+        Ok(points)
+    }
+}
+
+//parameters are Settings fields or variables of the derived expressions
+pub fn check_names<'a>(parameters: impl Iterator<Item=&'a String>, derived: &BTreeMap<String,Expr>) -> Result<(),String>{
+    let used: Vec<String> = derived.values().flat_map(|expr| expr.variables()).collect();
+    for name in parameters{
+        if !PARAMETER_NAMES.contains(&name.as_str()) && !used.contains(name){
+            return Err(format!("unknown parameter {name}, not a Settings field nor used by a derived parameter"));
+        }
+        if derived.contains_key(name){
+            return Err(format!("{name} is both a parameter and a derived parameter"));
+        }
+    }
+    Ok(())
+}
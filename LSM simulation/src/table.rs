@@ -0,0 +1,270 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, FloatType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+use crate::geometry::*;
+
+//one row per compartment of whole trees, written as CSV or Apache Parquet for pandas and R
+//compartments are numbered per tree as in TreeGeometry: for every node its segments from the base, then the node compartment
+
+#[derive(Clone,Debug)]
+pub struct CompartmentRow{
+    pub tree: String,
+    pub replicate: i32,
+    pub seed: u64,
+    //index of the compartment in its tree and of the compartment it grows from, -1 for the base
+    pub compartment: i32,
+    pub parent: i32,
+    pub node: i32,
+    //-1 for the node compartment
+    pub segment: i32,
+    //node at the base of the axis the compartment belongs to
+    pub axis: i32,
+    pub order: i32,
+    pub initial_order: i32,
+    pub bud_state: String,
+    pub start: [f32;3],
+    pub end: [f32;3],
+    pub width: f32,
+    //path length from the base of the tree to the end of the compartment,
+    //and from the end of the compartment to the tip of its axis
+    pub distance_from_base: f32,
+    pub distance_to_tip: f32,
+    pub data: Data,
+}
+
+enum Values{
+    Int(Vec<i32>),
+    Unsigned(Vec<u64>),
+    Float(Vec<f32>),
+    Text(Vec<String>),
+}
+impl Values{
+    fn cell(&self, row: usize) -> String{
+        match self{
+            Values::Int(values) => values[row].to_string(),
+            Values::Unsigned(values) => values[row].to_string(),
+            Values::Float(values) => values[row].to_string(),
+            Values::Text(values) => values[row].clone(),
+        }
+    }
+    fn schema(&self, name: &str) -> String{
+        match self{
+            Values::Int(_) => format!("required int32 {name};"),
+            Values::Unsigned(_) => format!("required int64 {name} (INTEGER(64,false));"),
+            Values::Float(_) => format!("required float {name};"),
+            Values::Text(_) => format!("required binary {name} (UTF8);"),
+        }
+    }
+}
+
+#[derive(Clone,Debug,Default)]
+pub struct CompartmentTable{
+    pub rows: Vec<CompartmentRow>,
+}
+
+impl CompartmentTable{
+    pub fn new() -> CompartmentTable{
+        CompartmentTable::default()
+    }
+
+    pub fn add_tree(&mut self, name: &str, replicate: i32, tree: &Tree, params: &GeometryParams){
+        let geometry = TreeGeometry::new(tree, params);
+        //index of the first compartment of every node
+        let mut first = vec![0; tree.nodes.len()];
+        let mut count = 0;
+        for id in tree.node_ids(){
+            first[id.0] = count;
+            count += geometry.node(id).len();
+        }
+        let node_compartment = |id: NodeId| first[id.0]+geometry.node(id).len()-1;
+        let lengths: Vec<f32> = geometry.compartments().map(|c| c.length()).collect();
+
+        let mut from_base = vec![0.; count];
+        let mut axis = vec![0; tree.nodes.len()];
+        for id in tree.preorder(){
+            let node = &tree[id];
+            axis[id.0] = match node.parent{
+                Some(parent) if tree[parent].main_child==Some(id) => axis[parent.0],
+                _ => id.0,
+            };
+            let mut distance = node.parent.map_or(0., |parent| from_base[node_compartment(parent)]);
+            for i in first[id.0]..=node_compartment(id){
+                distance += lengths[i];
+                from_base[i] = distance;
+            }
+        }
+        //length of the node and all main children after it
+        let mut axis_rest = vec![0.; tree.nodes.len()];
+        for id in tree.postorder(){
+            let own: f32 = lengths[first[id.0]..=node_compartment(id)].iter().sum();
+            axis_rest[id.0] = own+tree[id].main_child.map_or(0., |child| axis_rest[child.0]);
+        }
+
+        for id in tree.node_ids(){
+            let node = &tree[id];
+            let mut to_tip = axis_rest[id.0];
+            for (k,compartment) in geometry.node(id).iter().enumerate(){
+                let index = first[id.0]+k;
+                to_tip -= lengths[index];
+                let parent = match (k,node.parent){
+                    (0,Some(parent)) => node_compartment(parent) as i32,
+                    (0,None) => -1,
+                    _ => index as i32-1,
+                };
+                let data = match compartment.compartment.segment{
+                    Some(segment) => &node.segments[segment].data,
+                    None => &node.data,
+                };
+                self.rows.push(CompartmentRow {
+                    tree: name.to_string(),
+                    replicate,
+                    seed: tree.seed,
+                    compartment: index as i32,
+                    parent,
+                    node: id.0 as i32,
+                    segment: compartment.compartment.segment.map_or(-1, |s| s as i32),
+                    axis: axis[id.0] as i32,
+                    order: node.order,
+                    initial_order: node.initial_order,
+                    bud_state: format!("{:?}",node.bud_state),
+                    start: compartment.start.into(),
+                    end: compartment.end.into(),
+                    width: compartment.width,
+                    distance_from_base: from_base[index],
+                    distance_to_tip: to_tip.max(0.),
+                    data: data.clone(),
+                });
+            }
+        }
+    }
+
+    fn columns(&self) -> Vec<(&'static str,Values)>{
+        let rows = &self.rows;
+        let int = |f: fn(&CompartmentRow) -> i32| Values::Int(rows.iter().map(f).collect());
+        let float = |f: fn(&CompartmentRow) -> f32| Values::Float(rows.iter().map(f).collect());
+        vec![
+            ("tree",Values::Text(rows.iter().map(|r| r.tree.clone()).collect())),
+            ("replicate",int(|r| r.replicate)),
+            ("seed",Values::Unsigned(rows.iter().map(|r| r.seed).collect())),
+            ("compartment",int(|r| r.compartment)),
+            ("parent",int(|r| r.parent)),
+            ("node",int(|r| r.node)),
+            ("segment",int(|r| r.segment)),
+            ("axis",int(|r| r.axis)),
+            ("order",int(|r| r.order)),
+            ("initial_order",int(|r| r.initial_order)),
+            ("bud_state",Values::Text(rows.iter().map(|r| r.bud_state.clone()).collect())),
+            ("x0",float(|r| r.start[0])),
+            ("y0",float(|r| r.start[1])),
+            ("z0",float(|r| r.start[2])),
+            ("x1",float(|r| r.end[0])),
+            ("y1",float(|r| r.end[1])),
+            ("z1",float(|r| r.end[2])),
+            ("width",float(|r| r.width)),
+            ("distance_from_base",float(|r| r.distance_from_base)),
+            ("distance_to_tip",float(|r| r.distance_to_tip)),
+            ("auxin",float(|r| r.data.auxin)),
+            ("pin",float(|r| r.data.pin)),
+            ("strigolactin",float(|r| r.data.strigolactin)),
+            ("auxin_flow",float(|r| r.data.auxin_flow)),
+            ("transport",float(|r| r.data.transport)),
+            ("external_auxin",float(|r| r.data.external_auxin)),
+            ("age",int(|r| r.data.age)),
+        ]
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<(),String>{
+        let columns = self.columns();
+        let mut writer = csv::Writer::from_path(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        writer.write_record(columns.iter().map(|(name,_)| *name)).map_err(|e| format!("{}: {e}",path.display()))?;
+        for row in 0..self.rows.len(){
+            writer.write_record(columns.iter().map(|(_,values)| values.cell(row))).map_err(|e| format!("{}: {e}",path.display()))?;
+        }
+        writer.flush().map_err(|e| format!("{}: {e}",path.display()))
+    }
+
+    pub fn write_parquet(&self, path: &Path) -> Result<(),String>{
+        let error = |e: parquet::errors::ParquetError| format!("{}: {e}",path.display());
+        let columns = self.columns();
+        let fields: Vec<String> = columns.iter().map(|(name,values)| values.schema(name)).collect();
+        let schema = Arc::new(parse_message_type(&format!("message compartments {{ {} }}",fields.join(" "))).map_err(error)?);
+        let properties = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let file = File::create(path).map_err(|e| format!("{}: {e}",path.display()))?;
+        let mut writer = SerializedFileWriter::new(file, schema, properties).map_err(error)?;
+        let mut row_group = writer.next_row_group().map_err(error)?;
+        for (_,values) in &columns{
+            let mut column = row_group.next_column().map_err(error)?.ok_or("parquet schema has fewer columns than the table")?;
+            match values{
+                Values::Int(values) => column.typed::<Int32Type>().write_batch(values, None, None),
+                Values::Unsigned(values) => column.typed::<Int64Type>().write_batch(&values.iter().map(|&v| v as i64).collect::<Vec<_>>(), None, None),
+                Values::Float(values) => column.typed::<FloatType>().write_batch(values, None, None),
+                Values::Text(values) => column.typed::<ByteArrayType>().write_batch(&values.iter().map(|v| ByteArray::from(v.as_str())).collect::<Vec<_>>(), None, None),
+            }.map_err(error)?;
+            column.close().map_err(error)?;
+        }
+        row_group.close().map_err(error)?;
+        writer.close().map_err(error)?;
+        Ok(())
+    }
+
+    //format from the extension, .parquet or .csv
+    pub fn save(&self, path: &Path) -> Result<(),String>{
+        match path.extension().and_then(|e| e.to_str()){
+            Some("parquet") => self.write_parquet(path),
+            Some("csv") => self.write_csv(path),
+            _ => Err(format!("{}: unknown table format, use .csv or .parquet",path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+
+    const COLUMNS: [&str; 27] = [
+        "tree","replicate","seed","compartment","parent","node","segment","axis","order","initial_order","bud_state",
+        "x0","y0","z0","x1","y1","z1","width","distance_from_base","distance_to_tip",
+        "auxin","pin","strigolactin","auxin_flow","transport","external_auxin","age",
+    ];
+
+    #[test]
+    fn csv_and_parquet_columns(){
+        let mut tree = Tree::with_seed(&Settings::default(), 3);
+        for _ in 0..12{
+            tree.extend_main();
+        }
+        let mut table = CompartmentTable::new();
+        table.add_tree("WT", 0, &tree, &GeometryParams::default());
+        table.add_tree("WT", 1, &tree, &GeometryParams::default());
+        let compartments = TreeGeometry::new(&tree, &GeometryParams::default()).compartments().count();
+        assert_eq!(table.rows.len(),2*compartments);
+        assert_eq!(table.rows.iter().filter(|r| r.parent==-1).count(),2);
+
+        let dir = std::env::temp_dir().join(format!("tree_d_table_{}",std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        table.save(&dir.join("compartments.csv")).unwrap();
+        table.save(&dir.join("compartments.parquet")).unwrap();
+        let csv = std::fs::read_to_string(dir.join("compartments.csv")).unwrap();
+        let parquet = SerializedFileReader::new(File::open(dir.join("compartments.parquet")).unwrap()).unwrap();
+        let unknown = table.save(&dir.join("compartments.xlsx"));
+        std::fs::remove_dir_all(&dir).ok();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next().unwrap().split(',').collect::<Vec<_>>(),COLUMNS);
+        assert_eq!(lines.count(),table.rows.len());
+        let metadata = parquet.metadata().file_metadata();
+        let names: Vec<&str> = metadata.schema_descr().columns().iter().map(|c| c.name()).collect();
+        assert_eq!(names,COLUMNS);
+        assert_eq!(metadata.num_rows(),table.rows.len() as i64);
+        assert!(unknown.is_err());
+    }
+}
@@ -0,0 +1,236 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+use serde::{Serialize, Deserialize, Deserializer};
+
+use crate::vec_tree::*;
+
+//index of a node in tree.nodes
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq,Eq,Hash,PartialOrd,Ord)]
+#[serde(transparent)]
+pub struct NodeId(pub usize);
+
+impl NodeId{
+    pub const ROOT: NodeId = NodeId(0);
+    pub fn index(self) -> usize{
+        self.0
+    }
+}
+//trees saved before NodeId store node indices as i32 with -1 for no node, both forms are read
+pub(crate) fn legacy_node_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NodeId>,D::Error>{
+    Ok(Option::<i64>::deserialize(deserializer)?.and_then(|i| usize::try_from(i).ok()).map(NodeId))
+}
+pub(crate) fn legacy_node_ids<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<NodeId>,D::Error>{
+    Ok(Vec::<i64>::deserialize(deserializer)?.into_iter().filter_map(|i| usize::try_from(i).ok()).map(NodeId).collect())
+}
+
+impl fmt::Display for NodeId{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f,"{}",self.0)
+    }
+}
+
+impl Index<NodeId> for Tree{
+    type Output = Node;
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
+impl IndexMut<NodeId> for Tree{
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id.0]
+    }
+}
+
+impl Node{
+    //main child first, then secondary
+    pub fn children(&self) -> impl Iterator<Item=NodeId>{
+        self.main_child.into_iter().chain(self.secondary_child)
+    }
+    pub fn is_leaf(&self) -> bool{
+        self.main_child.is_none() && self.secondary_child.is_none()
+    }
+}
+
+//depth first traversal, a node is visited before its main child and the main child subtree before the secondary one
+pub struct Preorder<'a>{
+    tree: &'a Tree,
+    stack: Vec<NodeId>,
+}
+impl<'a> Iterator for Preorder<'a>{
+    type Item = NodeId;
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.stack.pop()?;
+        let node = &self.tree[id];
+        self.stack.extend(node.secondary_child);
+        self.stack.extend(node.main_child);
+        Some(id)
+    }
+}
+
+//depth first traversal, a node is visited after all of its descendants
+pub struct Postorder<'a>{
+    tree: &'a Tree,
+    stack: Vec<(NodeId,bool)>,
+}
+impl<'a> Iterator for Postorder<'a>{
+    type Item = NodeId;
+    fn next(&mut self) -> Option<NodeId> {
+        loop{
+            let (id,expanded) = self.stack.pop()?;
+            if expanded{
+                return Some(id);
+            }
+            self.stack.push((id,true));
+            let node = &self.tree[id];
+            self.stack.extend(node.secondary_child.map(|c| (c,false)));
+            self.stack.extend(node.main_child.map(|c| (c,false)));
+        }
+    }
+}
+
+//follows one link from every node, used for paths to the root and axes
+pub struct Chain<'a>{
+    tree: &'a Tree,
+    next: Option<NodeId>,
+    link: fn(&Node) -> Option<NodeId>,
+}
+impl<'a> Iterator for Chain<'a>{
+    type Item = NodeId;
+    fn next(&mut self) -> Option<NodeId> {
+        let id = self.next?;
+        self.next = (self.link)(&self.tree[id]);
+        Some(id)
+    }
+}
+
+impl Tree{
+    pub fn root(&self) -> NodeId{
+        NodeId::ROOT
+    }
+    pub fn node_ids(&self) -> impl Iterator<Item=NodeId>{
+        (0..self.nodes.len()).map(NodeId)
+    }
+    pub fn preorder(&self) -> Preorder<'_>{
+        self.subtree(NodeId::ROOT)
+    }
+    pub fn postorder(&self) -> Postorder<'_>{
+        Postorder { tree: self, stack: vec![(NodeId::ROOT,false)] }
+    }
+    //the node and all of its descendants in preorder
+    pub fn subtree(&self, id: NodeId) -> Preorder<'_>{
+        Preorder { tree: self, stack: vec![id] }
+    }
+    //the node followed by its ancestors up to the root
+    pub fn path_to_root(&self, id: NodeId) -> Chain<'_>{
+        Chain { tree: self, next: Some(id), link: |node| node.parent }
+    }
+    //the node followed by the chain of main children, the axis ends with its tip
+    pub fn axis(&self, id: NodeId) -> Chain<'_>{
+        Chain { tree: self, next: Some(id), link: |node| node.main_child }
+    }
+    //other children of the parent
+    pub fn siblings(&self, id: NodeId) -> impl Iterator<Item=NodeId> + '_{
+        self[id].parent.into_iter().flat_map(move |parent| self[parent].children()).filter(move |&c| c!=id)
+    }
+    pub fn leaves(&self) -> impl Iterator<Item=NodeId> + '_{
+        self.preorder().filter(move |&id| self[id].is_leaf())
+    }
+    //first node of the axis the node belongs to, the root or a secondary child
+    pub fn axis_base(&self, id: NodeId) -> NodeId{
+        let mut base = id;
+        while let Some(parent) = self[base].parent{
+            if self[parent].main_child!=Some(base){
+                break;
+            }
+            base = parent;
+        }
+        base
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests{
+    use super::*;
+
+    //tree from the (main child, secondary child, bud state) of every node,
+    //parents, orders and tips are derived from the child links
+    pub(crate) fn hand_built(links: &[(Option<usize>,Option<usize>,BudState)]) -> Tree{
+        let settings = Settings::default();
+        let mut tree = Tree::with_seed(&settings, 0);
+        tree.nodes = links.iter().enumerate().map(|(i,(main,secondary,bud_state))|{
+            let mut node = Node::new(NodeId(i), None, 0, settings.segments_amount, bud_state.clone(), settings.clone());
+            node.main_child = main.map(NodeId);
+            node.secondary_child = secondary.map(NodeId);
+            node
+        }).collect();
+        tree.tip_indices = tree.node_ids().filter(|&id| tree[id].bud_state==BudState::ActiveBud).collect();
+        tree.repair();
+        for id in tree.preorder().collect::<Vec<_>>(){
+            if let Some(parent) = tree[id].parent{
+                let order = tree[parent].order + (tree[parent].secondary_child==Some(id)) as i32;
+                tree[id].order = order;
+                tree[id].initial_order = order;
+            }
+        }
+        assert!(tree.repair().is_empty());
+        tree
+    }
+
+    //   3   5 6
+    //   |   |/
+    //   1---4
+    //   |
+    //   0---2
+    pub(crate) fn small() -> Tree{
+        hand_built(&[
+            (Some(1),Some(2),BudState::BranchingSegment),
+            (Some(3),Some(4),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::ActiveBud),
+            (Some(5),Some(6),BudState::BranchingSegment),
+            (None,None,BudState::ActiveBud),
+            (None,None,BudState::DormantBud),
+        ])
+    }
+
+    fn ids(indices: &[usize]) -> Vec<NodeId>{
+        indices.iter().copied().map(NodeId).collect()
+    }
+
+    #[test]
+    fn traversal_order(){
+        let tree = small();
+        assert_eq!(tree.preorder().collect::<Vec<_>>(),ids(&[0,1,3,4,5,6,2]));
+        assert_eq!(tree.postorder().collect::<Vec<_>>(),ids(&[3,5,6,4,1,2,0]));
+        assert_eq!(tree.subtree(NodeId(4)).collect::<Vec<_>>(),ids(&[4,5,6]));
+        assert_eq!(tree.leaves().collect::<Vec<_>>(),ids(&[3,5,6,2]));
+    }
+
+    #[test]
+    fn chains(){
+        let tree = small();
+        assert_eq!(tree.axis(tree.root()).collect::<Vec<_>>(),ids(&[0,1,3]));
+        assert_eq!(tree.axis(NodeId(4)).collect::<Vec<_>>(),ids(&[4,5]));
+        assert_eq!(tree.path_to_root(NodeId(5)).collect::<Vec<_>>(),ids(&[5,4,1,0]));
+        assert_eq!(tree.path_to_root(tree.root()).collect::<Vec<_>>(),ids(&[0]));
+    }
+
+    #[test]
+    fn neighbours(){
+        let tree = small();
+        assert_eq!(tree.siblings(NodeId(3)).collect::<Vec<_>>(),ids(&[4]));
+        assert_eq!(tree.siblings(NodeId(6)).collect::<Vec<_>>(),ids(&[5]));
+        assert_eq!(tree.siblings(tree.root()).count(),0);
+        let bases: Vec<NodeId> = tree.node_ids().map(|id| tree.axis_base(id)).collect();
+        assert_eq!(bases,ids(&[0,0,2,0,4,4,6]));
+    }
+
+    #[test]
+    fn legacy_indices(){
+        let links: Vec<Option<NodeId>> = ["3","-1","null"].iter()
+            .map(|text| legacy_node_id(&mut serde_json::Deserializer::from_str(text)).unwrap()).collect();
+        assert_eq!(links,vec![Some(NodeId(3)),None,None]);
+        let tips = legacy_node_ids(&mut serde_json::Deserializer::from_str("[4,-1,0]")).unwrap();
+        assert_eq!(tips,vec![NodeId(4),NodeId(0)]);
+    }
+}
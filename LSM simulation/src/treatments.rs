@@ -0,0 +1,111 @@
+use serde::{Serialize, Deserialize};
+
+use crate::vec_tree::*;
+use crate::topology::*;
+
+//exogenous treatments applied to a tree, scheduled in simulated time (tree.time)
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub enum TreatmentKind{
+    //constant auxin production added to the node compartment, e.g. IAA paste on a decapitated stump
+    AuxinSource{ node: NodeId, rate: f32 },
+    //PAT inhibitor (NPA), strength 1 blocks PIN mediated transport completely
+    PinInhibition{ compartments: Vec<Compartment>, strength: f32 },
+    //removes transport out of the compartment
+    Girdling{ compartment: Compartment },
+}
+
+#[derive(Serialize, Deserialize,Clone,Debug)]
+pub struct Treatment{
+    pub kind: TreatmentKind,
+    pub start: f32,
+    //None keeps the treatment active until the end of the simulation
+    pub end: Option<f32>,
+}
+
+impl Treatment{
+    pub fn new(kind: TreatmentKind, start: f32, end: Option<f32>) -> Treatment{
+        Treatment { kind, start, end }
+    }
+    pub fn is_active(&self, time: f32) -> bool{
+        time>=self.start && self.end.map_or(true, |end| time<end)
+    }
+
+    pub fn auxin_source(node: NodeId, rate: f32, start: f32, end: Option<f32>) -> Treatment{
+        Treatment::new(TreatmentKind::AuxinSource { node, rate }, start, end)
+    }
+    //IAA applied in place of the removed apex, None if the tree was not decapitated
+    pub fn iaa_on_stump(tree: &Tree, rate: f32, start: f32, end: Option<f32>) -> Option<Treatment>{
+        tree.decapitated_tip_index.map(|stump| Treatment::auxin_source(stump, rate, start, end))
+    }
+    pub fn npa(compartments: Vec<Compartment>, strength: f32, start: f32, end: Option<f32>) -> Treatment{
+        Treatment::new(TreatmentKind::PinInhibition { compartments, strength }, start, end)
+    }
+    //NPA ring of given length applied below the node
+    pub fn npa_below(tree: &Tree, node: NodeId, length: usize, strength: f32, start: f32, end: Option<f32>) -> Treatment{
+        Treatment::npa(tree.compartments_below(node, length), strength, start, end)
+    }
+    pub fn girdling(compartment: Compartment, start: f32) -> Treatment{
+        Treatment::new(TreatmentKind::Girdling { compartment }, start, None)
+    }
+}
+
+impl std::fmt::Display for Treatment{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind{
+            TreatmentKind::AuxinSource { node, rate } => write!(f,"auxin source {rate} at node {node}")?,
+            TreatmentKind::PinInhibition { compartments, strength } => write!(f,"PIN inhibition {strength} on {} compartments",compartments.len())?,
+            TreatmentKind::Girdling { compartment } => write!(f,"girdling at {compartment:?}")?,
+        }
+        match self.end{
+            Some(end) => write!(f," from {} to {}",self.start,end),
+            None => write!(f," from {}",self.start),
+        }
+    }
+}
+
+//place on the tree given without node indices, resolved after the tree has grown
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Site{
+    //node left after decapitate_main
+    Stump,
+    MainTip,
+    //n-th node of the main stem counted from the base
+    StemNode(usize),
+}
+impl Site{
+    pub fn resolve(&self, tree: &Tree) -> Option<NodeId>{
+        match self{
+            Site::Stump => tree.decapitated_tip_index,
+            Site::MainTip => Some(tree.get_oringal_tip_index()),
+            Site::StemNode(n) => tree.axis(tree.root()).nth(*n),
+        }
+    }
+}
+
+//treatment as written in scenario files
+#[derive(Serialize, Deserialize,Clone,Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TreatmentSpec{
+    Iaa{ site: Site, rate: f32, #[serde(default)] start: f32, end: Option<f32> },
+    Npa{ site: Site, length: usize, strength: f32, #[serde(default)] start: f32, end: Option<f32> },
+    //girdling of the compartment `below` steps under the site
+    Girdling{ site: Site, #[serde(default)] below: usize, #[serde(default)] start: f32 },
+}
+impl TreatmentSpec{
+    pub fn resolve(&self, tree: &Tree) -> Option<Treatment>{
+        match self{
+            TreatmentSpec::Iaa { site, rate, start, end } => {
+                site.resolve(tree).map(|node| Treatment::auxin_source(node, *rate, *start, *end))
+            }
+            TreatmentSpec::Npa { site, length, strength, start, end } => {
+                site.resolve(tree).map(|node| Treatment::npa_below(tree, node, *length, *strength, *start, *end))
+            }
+            TreatmentSpec::Girdling { site, below, start } => {
+                let node = site.resolve(tree)?;
+                let compartment = *tree.compartments_below(node, below+1).get(*below)?;
+                Some(Treatment::girdling(compartment, *start))
+            }
+        }
+    }
+}
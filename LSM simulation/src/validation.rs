@@ -0,0 +1,313 @@
+use std::fmt;
+use std::collections::BTreeMap;
+
+use crate::vec_tree::*;
+use crate::topology::*;
+
+//inconsistency found in a tree, see Tree::validate
+#[derive(Clone,Debug,PartialEq)]
+pub enum Violation{
+    //node stored at position has a different index
+    IndexMismatch{ position: usize, index: NodeId },
+    //link pointing outside of tree.nodes
+    DanglingLink{ node: NodeId, link: NodeId },
+    //parent does not have the node as a child
+    ParentMismatch{ node: NodeId, parent: NodeId },
+    //child has a different parent
+    ChildMismatch{ node: NodeId, child: NodeId },
+    //node other than the root without a parent, or the root with one
+    Unrooted{ node: NodeId },
+    Unreachable{ node: NodeId },
+    OrderIndexMissing{ node: NodeId, order: i32 },
+    OrderIndexDuplicate{ node: NodeId, order: usize },
+    //node listed under an order different from node.order
+    OrderIndexMisplaced{ node: NodeId, listed: usize, order: i32 },
+    //tip used by extend_main is not an active bud
+    StaleTip{ tip: NodeId },
+    StaleDecapitatedTip{ node: NodeId },
+    TooManySegments{ node: NodeId, segments: usize, segments_amount: i32 },
+    BranchingWithoutMainChild{ node: NodeId },
+    BudWithMainChild{ node: NodeId },
+}
+
+impl fmt::Display for Violation{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            Violation::IndexMismatch { position, index } => write!(f,"node at position {position} has index {index}"),
+            Violation::DanglingLink { node, link } => write!(f,"node {node} links to missing node {link}"),
+            Violation::ParentMismatch { node, parent } => write!(f,"node {node} is not a child of its parent {parent}"),
+            Violation::ChildMismatch { node, child } => write!(f,"child {child} of node {node} has a different parent"),
+            Violation::Unrooted { node } => write!(f,"node {node} has a wrong parent link for its position"),
+            Violation::Unreachable { node } => write!(f,"node {node} is not reachable from the root"),
+            Violation::OrderIndexMissing { node, order } => write!(f,"node {node} of order {order} is missing in the order index"),
+            Violation::OrderIndexDuplicate { node, order } => write!(f,"node {node} is listed more than once under order {order}"),
+            Violation::OrderIndexMisplaced { node, listed, order } => write!(f,"node {node} of order {order} is listed under order {listed}"),
+            Violation::StaleTip { tip } => write!(f,"tip {tip} is not an active bud"),
+            Violation::StaleDecapitatedTip { node } => write!(f,"decapitated tip {node} is not decapitated"),
+            Violation::TooManySegments { node, segments, segments_amount } => write!(f,"node {node} has {segments} segments, internodes have at most {} segments",segments_amount-1),
+            Violation::BranchingWithoutMainChild { node } => write!(f,"branching segment {node} has no main child"),
+            Violation::BudWithMainChild { node } => write!(f,"bud {node} has a main child"),
+        }
+    }
+}
+
+impl Tree{
+    //checks links, derived indices and bud states, empty result means the tree is consistent
+    pub fn validate(&self) -> Vec<Violation>{
+        let mut violations = vec![];
+        let size = self.get_size();
+        let in_range = |id: NodeId| id.0<size;
+
+        for (position,node) in self.nodes.iter().enumerate(){
+            let id = NodeId(position);
+            if node.index!=id{
+                violations.push(Violation::IndexMismatch { position, index: node.index });
+            }
+            match node.parent{
+                None if position!=0 => violations.push(Violation::Unrooted { node: id }),
+                Some(_) if position==0 => violations.push(Violation::Unrooted { node: id }),
+                Some(parent) if !in_range(parent) => violations.push(Violation::DanglingLink { node: id, link: parent }),
+                Some(parent) => {
+                    if !self[parent].children().any(|c| c==id){
+                        violations.push(Violation::ParentMismatch { node: id, parent });
+                    }
+                }
+                None => {}
+            }
+            for child in node.children(){
+                if !in_range(child){
+                    violations.push(Violation::DanglingLink { node: id, link: child });
+                }
+                else if self[child].parent!=Some(id){
+                    violations.push(Violation::ChildMismatch { node: id, child });
+                }
+            }
+            let segments_amount = self.settings.segments_amount;
+            if node.segments.len()>(segments_amount-1).max(0) as usize{
+                violations.push(Violation::TooManySegments { node: id, segments: node.segments.len(), segments_amount });
+            }
+            match node.bud_state{
+                BudState::BranchingSegment if node.main_child.is_none() => violations.push(Violation::BranchingWithoutMainChild { node: id }),
+                BudState::ActiveBud | BudState::DormantBud if node.main_child.is_some() => violations.push(Violation::BudWithMainChild { node: id }),
+                _ => {}
+            }
+        }
+
+        //traversal that does not trust the links
+        let mut visited = vec![false; size];
+        let mut stack = vec![];
+        if size>0{
+            stack.push(NodeId::ROOT);
+        }
+        while let Some(id) = stack.pop(){
+            if visited[id.0]{
+                continue;
+            }
+            visited[id.0]=true;
+            stack.extend(self[id].children().filter(|&c| in_range(c) && self[c].parent==Some(id)));
+        }
+        for (position,seen) in visited.iter().enumerate(){
+            if !seen{
+                violations.push(Violation::Unreachable { node: NodeId(position) });
+            }
+        }
+
+        let mut listed: BTreeMap<NodeId,usize> = BTreeMap::new();
+        for (order,nodes) in self.orders_indexed.iter().enumerate(){
+            for &id in nodes{
+                if !in_range(id){
+                    continue;
+                }
+                let count = listed.entry(id).or_default();
+                *count+=1;
+                if *count==2{
+                    violations.push(Violation::OrderIndexDuplicate { node: id, order });
+                }
+                if self[id].order!=order as i32{
+                    violations.push(Violation::OrderIndexMisplaced { node: id, listed: order, order: self[id].order });
+                }
+            }
+        }
+        for node in &self.nodes{
+            if node.index.0<size && !listed.contains_key(&node.index){
+                violations.push(Violation::OrderIndexMissing { node: node.index, order: node.order });
+            }
+        }
+
+        for &tip in &self.tip_indices{
+            if !in_range(tip) || self[tip].bud_state!=BudState::ActiveBud{
+                violations.push(Violation::StaleTip { tip });
+            }
+        }
+        if let Some(node) = self.decapitated_tip_index{
+            if !in_range(node) || self[node].bud_state!=BudState::DecapitatedSegment{
+                violations.push(Violation::StaleDecapitatedTip { node });
+            }
+        }
+        violations
+    }
+
+    //rebuilds the derived indices (node indices, parent links, order index, tips) from the child links,
+    //returns the violations left after the repair
+    pub fn repair(&mut self) -> Vec<Violation>{
+        let size = self.get_size();
+        for (position,node) in self.nodes.iter_mut().enumerate(){
+            node.index=NodeId(position);
+            node.main_child=node.main_child.filter(|c| c.0<size);
+            node.secondary_child=node.secondary_child.filter(|c| c.0<size);
+        }
+        if size>0{
+            self.nodes[0].parent=None;
+        }
+        for position in 0..size{
+            let children: Vec<NodeId> = self.nodes[position].children().collect();
+            for child in children{
+                self[child].parent=Some(NodeId(position));
+            }
+        }
+
+        let max_order = self.nodes.iter().map(|n| n.order.max(0) as usize).max().unwrap_or(0);
+        let mut orders_indexed = vec![vec![]; max_order+2];
+        for node in &self.nodes{
+            orders_indexed[node.order.max(0) as usize].push(node.index);
+        }
+        self.orders_indexed=orders_indexed;
+
+        let mut tips: Vec<NodeId> = vec![];
+        for tip in self.tip_indices.clone(){
+            if tip.0>=size{
+                continue;
+            }
+            //bounded, the main child links may form a cycle
+            let Some(tip) = self.axis(tip).take(size).last() else{ continue };
+            if self[tip].bud_state==BudState::ActiveBud && !tips.contains(&tip){
+                tips.push(tip);
+            }
+        }
+        self.tip_indices=tips;
+        self.decapitated_tip_index=self.decapitated_tip_index.filter(|&i| i.0<size && self[i].bud_state==BudState::DecapitatedSegment);
+        self.validate()
+    }
+
+    //panics in debug builds when a structural operation left the tree inconsistent
+    pub(crate) fn debug_validate(&self, operation: &str){
+        if cfg!(debug_assertions){
+            let violations = self.validate();
+            assert!(violations.is_empty(),"tree inconsistent after {operation}: {}",
+                violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("; "));
+        }
+    }
+
+    //tree saved with serde_json, derived indices are rebuilt when they are inconsistent
+    pub fn from_json(text: &str) -> Result<Tree,String>{
+        let tree: Tree = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        tree.repaired()
+    }
+
+    //deserialized tree with derived indices rebuilt when they are inconsistent
+    pub fn repaired(mut self) -> Result<Tree,String>{
+        if !self.validate().is_empty(){
+            let violations = self.repair();
+            if !violations.is_empty(){
+                return Err(violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join("\n"));
+            }
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //main stem with a few growing laterals
+    fn grown() -> Tree{
+        let mut tree = Tree::with_seed(&Settings::default(), 4);
+        for _ in 0..30{
+            tree.extend_main();
+        }
+        for _ in 0..3{
+            tree.activate_random_with_order(1);
+        }
+        for _ in 0..12{
+            tree.extend_random_with_order(1);
+        }
+        assert!(tree.validate().is_empty());
+        tree
+    }
+
+    #[test]
+    fn consistent_tree_is_unchanged(){
+        let mut tree = grown();
+        let tips = tree.tip_indices.clone();
+        assert!(tree.repair().is_empty());
+        assert_eq!(tree.tip_indices,tips);
+        tree.debug_validate("test");
+    }
+
+    #[test]
+    fn dangling_parent(){
+        let mut tree = grown();
+        let node = tree.axis(tree.root()).nth(3).unwrap();
+        let parent = tree[node].parent;
+        tree[node].parent = Some(NodeId(999));
+        assert!(tree.validate().contains(&Violation::DanglingLink { node, link: NodeId(999) }));
+        assert!(tree.repair().is_empty());
+        assert_eq!(tree[node].parent,parent);
+    }
+
+    #[test]
+    fn cycle_is_not_repaired(){
+        let mut tree = grown();
+        let stem: Vec<NodeId> = tree.axis(tree.root()).collect();
+        let (tip,node) = (*stem.last().unwrap(),stem[2]);
+        tree[tip].main_child = Some(node);
+        let violations = tree.validate();
+        assert!(violations.contains(&Violation::ChildMismatch { node: tip, child: node }),"{violations:?}");
+        assert!(violations.contains(&Violation::BudWithMainChild { node: tip }),"{violations:?}");
+        //the parent links follow the child links, which leaves the cycle unreachable from the root
+        let left = tree.repair();
+        assert!(left.contains(&Violation::Unreachable { node }),"{left:?}");
+        assert!(tree.clone().repaired().is_err());
+    }
+
+    #[test]
+    fn stale_order_index(){
+        let mut tree = grown();
+        let node = tree.axis(tree.root()).nth(2).unwrap();
+        tree.orders_indexed[1].push(node);
+        tree.orders_indexed[0].push(node);
+        let violations = tree.validate();
+        assert!(violations.contains(&Violation::OrderIndexMisplaced { node, listed: 1, order: 0 }),"{violations:?}");
+        assert!(violations.contains(&Violation::OrderIndexDuplicate { node, order: 0 }),"{violations:?}");
+        tree.orders_indexed[0].retain(|&n| n!=node);
+        tree.orders_indexed[1].retain(|&n| n!=node);
+        assert_eq!(tree.validate(),[Violation::OrderIndexMissing { node, order: 0 }]);
+        assert!(tree.repair().is_empty());
+        assert_eq!(tree.orders_indexed[0].iter().filter(|&&n| n==node).count(),1);
+    }
+
+    #[test]
+    fn stale_tips(){
+        let mut tree = grown();
+        let tips = tree.tip_indices.clone();
+        let dormant = tree.node_ids().find(|&id| tree[id].bud_state==BudState::DormantBud).unwrap();
+        tree.tip_indices.push(dormant);
+        tree.decapitated_tip_index = Some(NodeId::ROOT);
+        assert_eq!(tree.validate(),[Violation::StaleTip { tip: dormant },Violation::StaleDecapitatedTip { node: NodeId::ROOT }]);
+        assert!(tree.repair().is_empty());
+        assert_eq!(tree.tip_indices,tips);
+        assert_eq!(tree.decapitated_tip_index,None);
+    }
+
+    #[test]
+    #[should_panic(expected = "tree inconsistent after test")]
+    fn debug_validate_panics(){
+        if !cfg!(debug_assertions){
+            panic!("tree inconsistent after test");
+        }
+        let mut tree = grown();
+        tree.decapitated_tip_index = Some(NodeId::ROOT);
+        tree.debug_validate("test");
+    }
+}
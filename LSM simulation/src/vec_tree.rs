@@ -4,7 +4,11 @@ use std::vec;
 use std::fmt;
 use three_d::*;
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 use crate::model_functions::*;
+use crate::treatments::*;
+use crate::topology::*;
+use crate::geometry::frame;
 use rand::seq::IteratorRandom;
 
 use serde::{Serialize, Deserialize};
@@ -47,6 +51,14 @@ pub enum NodeExtensionResult{
     Extended,
 }
 use std::sync::Mutex;
+//outcome of Tree::steady_state, difference is the summed difference of the last block of iterations
+#[derive(Serialize, Deserialize,Clone,Copy,Debug)]
+pub struct Convergence{
+    pub iterations: usize,
+    pub difference: f32,
+    pub converged: bool,
+}
+
 #[derive(Serialize, Deserialize,Clone,Debug)]
 pub struct Settings{
     pub segments_amount: i32,
@@ -74,11 +86,62 @@ pub static SETTINGS: Mutex<Settings> = Mutex::new(Settings{
     pin_production: (1.0,0.06),
     dt: 0.01,
 });
+pub const PARAMETER_NAMES: [&str; 12] = [
+    "segments_amount",
+    "init_auxin",
+    "init_strigolactin",
+    "init_pin",
+    "dormant_gain",
+    "segment_gain",
+    "active_gain",
+    "decay",
+    "pin_decay",
+    "pin_production_1",
+    "pin_production_2",
+    "dt",
+];
 impl Settings{
     pub fn global_copy()->Settings{
         let x = SETTINGS.lock().unwrap().clone();
         return x
     }
+    //access to fields by name, used by scenario files and sweeps
+    pub fn parameter(&self, name: &str) -> Option<f32>{
+        let value = match name{
+            "segments_amount" => self.segments_amount as f32,
+            "init_auxin" => self.init_auxin,
+            "init_strigolactin" => self.init_strigolactin,
+            "init_pin" => self.init_pin,
+            "dormant_gain" => self.dormant_gain,
+            "segment_gain" => self.segment_gain,
+            "active_gain" => self.active_gain,
+            "decay" => self.decay,
+            "pin_decay" => self.pin_decay,
+            "pin_production_1" => self.pin_production.0,
+            "pin_production_2" => self.pin_production.1,
+            "dt" => self.dt,
+            _ => return None,
+        };
+        Some(value)
+    }
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> Result<(),String>{
+        match name{
+            "segments_amount" => self.segments_amount = value.round() as i32,
+            "init_auxin" => self.init_auxin = value,
+            "init_strigolactin" => self.init_strigolactin = value,
+            "init_pin" => self.init_pin = value,
+            "dormant_gain" => self.dormant_gain = value,
+            "segment_gain" => self.segment_gain = value,
+            "active_gain" => self.active_gain = value,
+            "decay" => self.decay = value,
+            "pin_decay" => self.pin_decay = value,
+            "pin_production_1" => self.pin_production.0 = value,
+            "pin_production_2" => self.pin_production.1 = value,
+            "dt" => self.dt = value,
+            _ => return Err(format!("unknown parameter {name}")),
+        }
+        Ok(())
+    }
 }
 
 impl Default for Settings {
@@ -113,7 +176,16 @@ pub struct Data{
     pub auxin: f32,
     pub strigolactin: f32,
     pub pin: f32,
-    pub auxin_flow:f32
+    pub auxin_flow:f32,
+    //fraction of PIN activity left after treatments (NPA, girdling), 1 when untreated
+    #[serde(default="full_transport")]
+    pub transport: f32,
+    //auxin applied by treatments
+    #[serde(default)]
+    pub external_auxin: f32,
+}
+fn full_transport() -> f32{
+    1.
 }
 impl Data {
     pub fn new(order: i32,init_auxin: f32, init_pin: f32) -> Data{
@@ -121,7 +193,7 @@ impl Data {
         //let mut auxin = rand::random();
         let mut strigolactin= 0.;
         let mut pin= init_pin;
-        Data { order,age:0,auxin, strigolactin, pin,auxin_flow:0. }
+        Data { order,age:0,auxin, strigolactin, pin,auxin_flow:0.,transport:1.,external_auxin:0. }
     }
 }
 impl Data{
@@ -132,13 +204,22 @@ impl Data{
         self.pin=(self.pin+dP).max(0.);
     }
 }
+//measured shape of a compartment, e.g. a cylinder of a QSM reconstruction
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+pub struct Cylinder{
+    pub start: [f32;3],
+    pub end: [f32;3],
+    pub radius: f32,
+}
 #[derive(Serialize, Deserialize,Clone,Debug)]
 pub struct Segment{
     pub data: Data,
+    #[serde(default)]
+    pub cylinder: Option<Cylinder>,
 }
 impl Segment{
     pub fn new(order:i32,init_auxin: f32, init_pin: f32) -> Segment{
-        Segment { data: Data::new(order,init_auxin, init_pin) }
+        Segment { data: Data::new(order,init_auxin, init_pin), cylinder: None }
     }
 }
 
@@ -152,21 +233,27 @@ pub enum BudState{
 #[derive(Serialize, Deserialize,Clone)]
 pub struct Node {
     pub bud_state: BudState,
-    pub index: i32,
-    pub parent: i32,
+    pub index: NodeId,
+    #[serde(deserialize_with = "legacy_node_id")]
+    pub parent: Option<NodeId>,
     pub order: i32,
     pub initial_order: i32,
-    pub main_child: i32,
-    pub secondary_child: i32,
+    #[serde(deserialize_with = "legacy_node_id")]
+    pub main_child: Option<NodeId>,
+    #[serde(deserialize_with = "legacy_node_id")]
+    pub secondary_child: Option<NodeId>,
     pub data: Data,
     #[serde(with = "Matrix4Def")]
     pub transformation: Mat4,
     pub segments: Vec<Segment>,
     pub segments_amount: i32,
-    pub settings: Settings
+    pub settings: Settings,
+    //measured shape of the node compartment
+    #[serde(default)]
+    pub cylinder: Option<Cylinder>,
 }
 impl Node{
-    pub fn new(index:i32, parent: i32,order: i32,segments_amount:i32,bud_state: BudState,settings: Settings) -> Node{
+    pub fn new(index:NodeId, parent: Option<NodeId>,order: i32,segments_amount:i32,bud_state: BudState,settings: Settings) -> Node{
         let initial_order = order;
         Node{
             bud_state,
@@ -174,24 +261,15 @@ impl Node{
             parent,
             order,
             initial_order,
-            main_child:-1,
-            secondary_child:-1,
+            main_child:None,
+            secondary_child:None,
             data: Data::new(order,settings.init_auxin, settings.init_pin),
             transformation: Mat4::identity(),
             segments: vec![],
             segments_amount,
-            settings
-        }
-    }
-    fn get_children(&self) -> Vec<usize>{
-        let mut result: Vec<usize> = vec![];
-        if self.main_child>0{
-            result.push(self.main_child as usize);
-        }
-        if self.secondary_child>0{
-            result.push(self.secondary_child as usize);
+            settings,
+            cylinder: None,
         }
-        return result;
     }
     pub fn get_out_data(&self) -> Data{
         match self.segments.len(){
@@ -213,7 +291,7 @@ impl Node{
             for i in 0..(self.segments.len()-1){
                 let data = &old_node.segments[i].data;
                 let data_other = &old_node.segments[i+1].data;
-                self.segments[i].data.auxin_update(dt*(outflow(&data)+inflow(&data_other)+decay(&data,&self.settings)+segment_production(&data, &self.settings)));
+                self.segments[i].data.auxin_update(dt*(outflow(&data)+inflow(&data_other)+decay(&data,&self.settings)+segment_production(&data, &self.settings)+external_production(&data)));
                 self.segments[i].data.pin_update(dt*(pin_production(&data,&self.settings)+pin_decay(&data,&self.settings)));
                 self.segments[i].data.auxin_flow=-outflow(&data);
                 
@@ -221,7 +299,7 @@ impl Node{
             let i = self.segments.len()-1;
             let data = &old_node.segments[i].data;
             let data_other = &old_node.data;
-            self.segments[i].data.auxin_update(dt*(outflow(&data)+inflow(&data_other)+decay(&data,&self.settings)+segment_production(&data, &self.settings)));
+            self.segments[i].data.auxin_update(dt*(outflow(&data)+inflow(&data_other)+decay(&data,&self.settings)+segment_production(&data, &self.settings)+external_production(&data)));
             self.segments[i].data.pin_update(dt*(pin_production(&data,&self.settings)+pin_decay(&data,&self.settings)));
             self.segments[i].data.auxin_flow=-outflow(&data);
         }
@@ -245,7 +323,7 @@ impl Node{
 
         };
         self.segment_flow(old_node);
-        self.data.auxin_update(dt*(outflow(&old_node.data)+production(&old_node.data,gain)+decay(&old_node.data,&self.settings)));
+        self.data.auxin_update(dt*(outflow(&old_node.data)+production(&old_node.data,gain)+decay(&old_node.data,&self.settings)+external_production(&old_node.data)));
         self.data.pin_update(dt*(pin_production(&old_node.data,&self.settings)+pin_decay(&old_node.data,&self.settings)));
         self.data.auxin_flow=-outflow(&old_node.data);
         Ok(())
@@ -266,7 +344,7 @@ impl Node{
             BudState::BranchingSegment => {}
         };
         self.segment_flow(&old_node);
-        self.data.auxin_update(dt*(outflow(&old_node.data)+inflow(&main_child.get_out_data())+inflow(&secondary_child.get_out_data())+decay(&old_node.data,&self.settings)+segment_production(&old_node.data, &self.settings)));
+        self.data.auxin_update(dt*(outflow(&old_node.data)+inflow(&main_child.get_out_data())+inflow(&secondary_child.get_out_data())+decay(&old_node.data,&self.settings)+segment_production(&old_node.data, &self.settings)+external_production(&old_node.data)));
         self.data.pin_update(dt*(pin_production(&old_node.data,&self.settings)+pin_decay(&old_node.data,&self.settings)));
         self.data.auxin_flow=-outflow(&old_node.data);
         Ok(())
@@ -281,7 +359,7 @@ impl Node{
 }
 impl std::fmt::Debug for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut r = write!(f," index {}\n parent {}\n main child {} \n secondary child {}\n type {:?}\n segnents\n",self.index,self.parent,self.main_child,self.secondary_child,self.bud_state);
+        let mut r = write!(f," index {}\n parent {:?}\n main child {:?} \n secondary child {:?}\n type {:?}\n segnents\n",self.index,self.parent,self.main_child,self.secondary_child,self.bud_state);
         for segment in &self.segments{
             r = write!(f,"{:#?}",segment.data);
         }
@@ -300,29 +378,58 @@ pub struct TreeRenderData{
 #[derive(Serialize, Deserialize,Clone)]
 pub struct Tree{
     //pub tip_index: i32,
-    pub tip_indices: Vec<i32>,
-    pub decapitated_tip_index: Option<i32>,
+    #[serde(deserialize_with = "legacy_node_ids")]
+    pub tip_indices: Vec<NodeId>,
+    #[serde(deserialize_with = "legacy_node_id")]
+    pub decapitated_tip_index: Option<NodeId>,
     pub segments_amount: i32,
     #[serde(with = "Matrix4Def")]
     pub transformation: Mat4,
     pub settings: Settings,
     pub nodes : Vec<Node>,
-    orders_indexed: Vec<Vec<usize>>,
-    
+    pub(crate) orders_indexed: Vec<Vec<NodeId>>,
+    //simulated time, advanced by settings.dt on every update
+    #[serde(default)]
+    pub time: f32,
+    #[serde(default)]
+    pub treatments: Vec<Treatment>,
+    //seed of the random choices made while growing, the generator itself is not saved
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(skip, default="unseeded_rng")]
+    rng: ChaCha8Rng,
+    //shape comes from the compartment cylinders instead of update_transformations, see qsm.rs
+    #[serde(default)]
+    pub measured: bool,
+}
+fn unseeded_rng() -> ChaCha8Rng{
+    ChaCha8Rng::seed_from_u64(0)
+}
+//single compartment of the tree, segment None means the node itself
+#[derive(Serialize, Deserialize,Clone,Copy,Debug,PartialEq)]
+pub struct Compartment{
+    pub node: NodeId,
+    pub segment: Option<usize>,
 }
 impl Tree{
-    pub fn new(settings: &Settings)-> Tree{
+    //tree with seed drawn from entropy, use with_seed for reproducible trees
+    pub fn with_seed(settings: &Settings, seed: u64)-> Tree{
         let segments_amount= settings.segments_amount;
-        let mut main =Node::new(0, -1, 0,segments_amount,BudState::ActiveBud,settings.clone());
-        main.secondary_child=1;
+        let mut main =Node::new(NodeId(0), None, 0,segments_amount,BudState::ActiveBud,settings.clone());
+        main.secondary_child=Some(NodeId(1));
         Tree{
-            tip_indices:vec![0],
+            tip_indices:vec![NodeId(0)],
             decapitated_tip_index:None,
             segments_amount,
             transformation: Mat4::identity(),
             settings:settings.clone(),
-            nodes: vec![main,Node::new(1, 0, 1,segments_amount,BudState::DormantBud,settings.clone()),],
-            orders_indexed: vec![vec![0],vec![1],vec![]]
+            nodes: vec![main,Node::new(NodeId(1), Some(NodeId(0)), 1,segments_amount,BudState::DormantBud,settings.clone()),],
+            orders_indexed: vec![vec![NodeId(0)],vec![NodeId(1)],vec![]],
+            time: 0.,
+            treatments: vec![],
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            measured: false,
         }
     }
     pub fn new_settings(&mut self,settings:Settings){
@@ -330,78 +437,97 @@ impl Tree{
         for node in &mut self.nodes{
             node.settings=settings.clone();
         }
+        self.debug_validate("new_settings");
     }
 
-    pub fn extend_node(&mut self,node_index:usize) -> NodeExtensionResult{
-        match self.nodes[node_index].bud_state {
+    pub fn extend_node(&mut self,node_index:NodeId) -> NodeExtensionResult{
+        match self[node_index].bud_state {
             BudState::ActiveBud =>{
                 let mut segments_amount = self.settings.segments_amount;
-                self.nodes[node_index].add_segment();
-                if self.nodes[node_index].segments.len()==segments_amount as usize-1{
-                    self.nodes[node_index].bud_state=BudState::BranchingSegment;
+                self[node_index].add_segment();
+                let result = if self[node_index].segments.len()==segments_amount as usize-1{
+                    self[node_index].bud_state=BudState::BranchingSegment;
                     self.add_main_node(node_index);
                     NodeExtensionResult::ExtensionFinished
                 }
-                else{NodeExtensionResult::Extended}
+                else{NodeExtensionResult::Extended};
+                self.debug_validate("extend_node");
+                result
             }
             _ =>{
                 NodeExtensionResult::FullBefore
             }
         }
     }
-    fn get_tip_index(&self) -> (i32,usize){
+    pub fn rng(&mut self) -> &mut ChaCha8Rng{
+        &mut self.rng
+    }
+    //uniform number from [0,1) drawn from the tree generator
+    pub fn random(&mut self) -> f32{
+        self.rng.gen()
+    }
+    //None when no tip is growing, e.g. for imported trees whose main tip is decapitated or dormant
+    fn get_tip_index(&mut self) -> Option<(NodeId,usize)>{
+        if self.tip_indices.is_empty(){
+            return None
+        }
         if self.tip_indices.len()==1{
-            return (self.tip_indices[0],0)
+            return Some((self.tip_indices[0],0))
         }
-        let index = rand::thread_rng().gen_range(0..self.tip_indices.len());
-        return (self.tip_indices[index],index)
+        let index = self.rng.gen_range(0..self.tip_indices.len());
+        return Some((self.tip_indices[index],index))
     }
-    pub fn get_oringal_tip_index(&self) -> i32{
+    pub fn get_oringal_tip_index(&self) -> NodeId{
         if self.tip_indices.len()==1{
             return self.tip_indices[0]
         }
-        else{
-            return self.decapitated_tip_index.unwrap();
-        }
+        //the main axis ends at the original tip when the tree was not decapitated by decapitate_main
+        self.decapitated_tip_index.unwrap_or_else(|| self.find_main_tip(self.root()))
     }
 
-    fn cache_index(&mut self, index: usize,order:usize){
+    fn cache_index(&mut self, index: NodeId,order:usize){
         while order>=self.orders_indexed.len(){
             self.orders_indexed.push(vec![]);
         }
         self.orders_indexed[order].push(index);
     }
     pub fn extend_main(&mut self){
-        self.extend_node(self.get_tip_index().0 as usize);
+        let Some((tip_index,_)) = self.get_tip_index() else{ return };
+        self.extend_node(tip_index);
     }
-    pub fn find_main_tip(&self, node_index: i32)-> i32{
-        let mut tip = node_index;
-        while self.nodes[tip as usize].bud_state==BudState::BranchingSegment{
-            tip = self.nodes[tip as usize].main_child;
-        }
-        return  tip;
+    //tip of the axis the node belongs to
+    pub fn find_main_tip(&self, node_index: NodeId)-> NodeId{
+        self.axis(node_index).last().unwrap()
     }
-    fn recursive_decapitation(&mut self, index:i32){
-        self.nodes[index as usize].decapitate();
-        if self.nodes[index as usize].main_child>0{
-            self.recursive_decapitation(self.nodes[index as usize].main_child);
-        }
-        if self.nodes[index as usize].secondary_child>0{
-            self.recursive_decapitation(self.nodes[index as usize].secondary_child);
+    fn parent_of(&self, index: NodeId) -> NodeId{
+        self[index].parent.expect("root node has no parent")
+    }
+    fn secondary_child_of(&self, index: NodeId) -> NodeId{
+        self[index].secondary_child.expect("node without secondary child")
+    }
+    fn recursive_decapitation(&mut self, index:NodeId){
+        let subtree: Vec<NodeId> = self.subtree(index).collect();
+        for i in subtree{
+            self[i].decapitate();
         }
-
     }
-    pub fn decapitate_main(&mut self){
-        let (tip_intex,index_of_tip_index) = self.get_tip_index();
-        self.nodes[tip_intex as usize].decapitate();
+    //fails without changing the tree when the tip has no branches on its two last nodes
+    pub fn decapitate_main(&mut self) -> Result<(),String>{
+        let (tip_intex,index_of_tip_index) = self.get_tip_index().ok_or("main decapitation needs a growing tip")?;
+        let branched = |node: Option<NodeId>| node.is_some_and(|node| self[node].secondary_child.is_some());
+        let parent = self[tip_intex].parent;
+        if !branched(parent) || !branched(parent.and_then(|parent| self[parent].parent)){
+            return Err("main decapitation needs branches on the two nodes below the tip".to_string());
+        }
+        self[tip_intex].decapitate();
         self.decapitated_tip_index = Some(tip_intex);
-        let parent_node = self.nodes[tip_intex as usize].parent as usize;
-        let first_tip = self.find_main_tip(self.nodes[parent_node].secondary_child);
-        let grand_parent_node = self.nodes[parent_node].parent as usize;
-        let second_tip = self.find_main_tip(self.nodes[grand_parent_node].secondary_child);
+        let parent_node = self.parent_of(tip_intex);
+        let first_tip = self.find_main_tip(self.secondary_child_of(parent_node));
+        let grand_parent_node = self.parent_of(parent_node);
+        let second_tip = self.find_main_tip(self.secondary_child_of(grand_parent_node));
 
-        self.activate(first_tip as usize);
-        self.activate(second_tip as usize);
+        self.activate(first_tip);
+        self.activate(second_tip);
         //TODO reorder nodes
         //TODO turn geting tip_index to function
         self.tip_indices.remove(index_of_tip_index);
@@ -409,48 +535,61 @@ impl Tree{
         self.tip_indices.push(second_tip);
         self.decrease_order(first_tip);
         self.decrease_order(second_tip);
+        self.debug_validate("decapitate_main");
+        Ok(())
     }
-    pub fn decapitate_lowest_branches(&mut self, number: usize,skip: usize,decapitated_segments:i32){
-        let mut node_index = 0;
+    pub fn decapitate_lowest_branches(&mut self, number: usize,skip: usize,decapitated_segments:i32) -> Result<(),String>{
+        let mut node_index = NodeId::ROOT;
         for _ in 0..(skip){
-            if self.nodes[node_index].main_child>=0{
-                node_index = (self.nodes[node_index].main_child as usize);
+            if let Some(main_child) = self[node_index].main_child{
+                node_index = main_child;
             }
         }
-        for _ in 0..(number){
-            let mut decapitation = self.find_main_tip(self.nodes[node_index].secondary_child);
+        for branch in 0..(number){
+            let Some(secondary_child) = self[node_index].secondary_child else{
+                return Err(format!("main stem node {} has no branch to decapitate",skip+branch));
+            };
+            let mut decapitation = self.find_main_tip(secondary_child);
             let mut i =0;
 
             
-            while (i<decapitated_segments) && self.nodes[self.nodes[decapitation as usize].parent as usize].order!=0 && (self.nodes[self.nodes[self.nodes[decapitation as usize].parent as usize].parent as usize].order!=0){
-                decapitation=self.nodes[decapitation as usize].parent;
+            while (i<decapitated_segments) && self[self.parent_of(decapitation)].order!=0 && (self[self.parent_of(self.parent_of(decapitation))].order!=0){
+                decapitation=self.parent_of(decapitation);
                 i+=1;
             }   
 
-            if (self.nodes[self.nodes[decapitation as usize].parent as usize].order==0){
+            if (self[self.parent_of(decapitation)].order==0){
                 continue;
             }
             self.recursive_decapitation(decapitation);
-            let new_branch = self.nodes[self.nodes[decapitation as usize].parent as usize].secondary_child;
+            let new_branch = self.secondary_child_of(self.parent_of(decapitation));
             let new_branch_tip = self.find_main_tip(new_branch);
-            self.activate(new_branch_tip as usize);
+            self.activate(new_branch_tip);
             self.decrease_order(new_branch);
 
 
 
 
 
-            node_index= (self.nodes[node_index].main_child as usize);
+            //the main stem may end before number branches are decapitated
+            if let Some(main_child) = self[node_index].main_child{
+                node_index = main_child;
+            }
+            else{
+                break;
+            }
         }
+        self.debug_validate("decapitate_lowest_branches");
+        Ok(())
     }
 
     pub fn decapitate_random_order_1(&mut self, number: usize) -> Result<(),()>{
         let mut new_tips = vec![];
         let order = 1;
         let mut iterations = 0;
-        if order>self.orders_indexed.len(){ return Err(());}
+        if order>=self.orders_indexed.len(){ return Err(());}
         let free = self.orders_indexed[order].iter().filter(|&x|
-            match self.nodes[*x].bud_state{
+            match self.nodes[x.0].bud_state{
                 BudState::ActiveBud => {true},
                 _ => {false},
             });
@@ -460,33 +599,30 @@ impl Tree{
         while new_tips.len()<number{
             iterations+=1;
             if iterations>100{
-                break;
+                return Err(());
             }
             let free = self.orders_indexed[order].iter().filter(|&x|
-                match self.nodes[*x].bud_state{
+                match self.nodes[x.0].bud_state{
                     BudState::ActiveBud => {true},
                     _ => {false},
                 });
-            let mut rng = rand::thread_rng();
-            let _index=free.choose(&mut rng);
+            let _index=free.choose(&mut self.rng).copied();
             match _index{
                 None =>{
                     return Err(());
                 }
                 Some(index)=>{
-                    let parent_node = self.nodes[*index].parent as usize;
-                    let first_tip = self.nodes[parent_node].secondary_child;
-                    let grand_parent_node = self.nodes[parent_node].parent as usize;
-                    let second_tip = self.nodes[grand_parent_node].secondary_child;
+                    let Some(grand_parent_node) = self[index].parent.and_then(|parent| self[parent].parent) else{ continue };
+                    let Some(second_tip) = self[grand_parent_node].secondary_child else{ continue };
 
-                    if (self.nodes[grand_parent_node].order==0){
+                    if (self[grand_parent_node].order==0){
                         continue;
                     }
-                    self.recursive_decapitation(*index as i32);
+                    self.recursive_decapitation(index);
                     //self.nodes[*index].decapitate();
                     //self.nodes[first_tip as usize].decapitate();
             
-                    self.activate(second_tip as usize);
+                    self.activate(second_tip);
                     //TODO reorder nodes
                     //TODO turn geting tip_index to function
                     new_tips.push(second_tip);
@@ -496,71 +632,61 @@ impl Tree{
         for i in new_tips{
             self.decrease_order(i);
         }
+        self.debug_validate("decapitate_random_order_1");
         Ok(())
     }
 
-    fn decrease_order(&mut self, _index:i32){
-        let mut index = _index;
-        let order = self.nodes[index as usize].order;
-        let mut parent = self.nodes[index as usize].parent;
-        while parent>0 && self.nodes[parent as usize].order==order{
-            index = parent;
-            parent = self.nodes[index as usize].parent;
-        }
-        //let node = &self.nodes[index as usize];
-        for x in &mut self.nodes[index as usize].segments{
-            x.data.order-=1;
-        }
-        //println!("decrasing order of {:?}",index);
-        //println!("{:?}",self.nodes[index as usize].order);
-        //println!("{:?}",self.orders_indexed[self.nodes[index as usize].order as usize]);
-        self.orders_indexed[self.nodes[index as usize].order as usize].retain(|&x| x != index as usize);
-
-        //println!("{:?}",self.orders_indexed[self.nodes[index as usize].order as usize]);
-
-        self.nodes[index as usize].order-=1;
-        self.nodes[index as usize].data.order-=1;
-
-        self.orders_indexed[(self.nodes[index as usize].order) as usize].push(index as usize);
-
-        if self.nodes[index as usize].main_child>0{
-            self.decrease_order(self.nodes[index as usize].main_child);
-        }
-        if self.nodes[index as usize].secondary_child>0{
-            self.decrease_order(self.nodes[index as usize].secondary_child);
+    //decreases order of the whole axis containing the node and of everything growing from it
+    fn decrease_order(&mut self, _index:NodeId){
+        let order = self[_index].order;
+        let index = self.path_to_root(_index)
+            .take_while(|&x| x!=NodeId::ROOT && self[x].order==order)
+            .last()
+            .unwrap_or(_index);
+        let subtree: Vec<NodeId> = self.subtree(index).collect();
+        for index in subtree{
+            for x in &mut self[index].segments{
+                x.data.order-=1;
+            }
+            let order = self[index].order;
+            self.orders_indexed[order as usize].retain(|&x| x != index);
+            self[index].order-=1;
+            self[index].data.order-=1;
+            self.orders_indexed[order as usize-1].push(index);
         }
     }
-    fn add_main_node(&mut self, node_index:usize) ->Result<(),&'static str>{
+    fn add_main_node(&mut self, node_index:NodeId) ->Result<(),&'static str>{
         let mut curr_index = node_index;
-        match self.nodes[curr_index].bud_state {
+        match self[curr_index].bud_state {
             BudState::BranchingSegment => {
-                if self.nodes[curr_index].main_child!=-1{
+                if self[curr_index].main_child.is_some(){
                     return Err("already added")
                 }
-                let new_index = self.nodes.len() as i32;
-                self.nodes.push(Node::new(new_index, curr_index as i32, self.nodes[curr_index].order,self.segments_amount,BudState::ActiveBud,self.settings.clone()));
-                self.nodes.push(Node::new(new_index+1, new_index as i32, self.nodes[curr_index].order+1,self.segments_amount,BudState::DormantBud,self.settings.clone()));
-                self.nodes[new_index as usize].secondary_child = new_index+1;
-                self.nodes[curr_index].main_child=new_index;
-                if let Some(index) = self.tip_indices.iter().position(|&x| x == curr_index as i32) {
+                let new_index = NodeId(self.nodes.len());
+                let bud_index = NodeId(self.nodes.len()+1);
+                self.nodes.push(Node::new(new_index, Some(curr_index), self[curr_index].order,self.segments_amount,BudState::ActiveBud,self.settings.clone()));
+                self.nodes.push(Node::new(bud_index, Some(new_index), self[curr_index].order+1,self.segments_amount,BudState::DormantBud,self.settings.clone()));
+                self[new_index].secondary_child = Some(bud_index);
+                self[curr_index].main_child=Some(new_index);
+                if let Some(index) = self.tip_indices.iter().position(|&x| x == curr_index) {
                     self.tip_indices[index]=new_index;
                 } 
-                self.cache_index(new_index as usize,self.nodes[curr_index].order as usize);
-                self.cache_index((new_index+1) as usize,(self.nodes[curr_index].order+1) as usize);
+                self.cache_index(new_index,self[curr_index].order as usize);
+                self.cache_index(bud_index,(self[curr_index].order+1) as usize);
                 return Ok(());
             }
             _ =>{return Err("still growing")}
         }
 
     }
-    fn activate(&mut self, node_index:usize) -> Result<(),()>{
-        match self.nodes[node_index].bud_state{
+    pub(crate) fn activate(&mut self, node_index:NodeId) -> Result<(),()>{
+        match self[node_index].bud_state{
             BudState::DormantBud => {
-                let new_index = self.nodes.len() as i32;
-                self.nodes[node_index].bud_state=BudState::ActiveBud;
-                self.nodes.push(Node::new(new_index, node_index as i32, self.nodes[node_index].order+1,self.segments_amount,BudState::DormantBud,self.settings.clone()));
-                self.nodes[node_index].secondary_child=new_index;
-                self.cache_index(new_index as usize,self.nodes[node_index].order as usize+1);
+                let new_index = NodeId(self.nodes.len());
+                self[node_index].bud_state=BudState::ActiveBud;
+                self.nodes.push(Node::new(new_index, Some(node_index), self[node_index].order+1,self.segments_amount,BudState::DormantBud,self.settings.clone()));
+                self[node_index].secondary_child=Some(new_index);
+                self.cache_index(new_index,self[node_index].order as usize+1);
                 Ok(())
             }
             _ => {Err(())}
@@ -568,30 +694,30 @@ impl Tree{
 
     }
 
-    pub fn activate_secondary(&mut self, node_index:usize) -> Result<(),()>{
-        let secondary_index = self.nodes[node_index].secondary_child;
-        match self.nodes[node_index].bud_state{
-            BudState::DormantBud => {Err(())},
-            _ => {
-                self.activate(secondary_index as usize)
+    pub fn activate_secondary(&mut self, node_index:NodeId) -> Result<(),()>{
+        match (&self[node_index].bud_state,self[node_index].secondary_child){
+            (BudState::DormantBud,_) | (_,None) => {Err(())},
+            (_,Some(secondary_index)) => {
+                let result = self.activate(secondary_index);
+                self.debug_validate("activate_secondary");
+                result
             },
         }
     }
     pub fn extend_random_with_order(&mut self, order: usize) -> Result<(),()>{
         if order<self.orders_indexed.len(){
             let free = self.orders_indexed[order].iter().filter(|&x|
-                match self.nodes[*x].bud_state{
+                match self.nodes[x.0].bud_state{
                     BudState::ActiveBud => {true},
                     _ => {false},
                 });
-            let mut rng = rand::thread_rng();
-            let _index=free.choose(&mut rng);
+            let _index=free.choose(&mut self.rng).copied();
             match _index{
                 None =>{
                     Err(())
                 }
                 Some(index)=>{
-                    self.extend_node(*index);
+                    self.extend_node(index);
                     Ok(())
                 }
             }      
@@ -604,28 +730,28 @@ impl Tree{
                 BudState::ActiveBud => {true},
                 _ => {false},
             });
-        let mut rng = rand::thread_rng();
-        let _node=free.choose(&mut rng);
+        let _node=free.choose(&mut self.rng).map(|node| node.index);
         match _node{
             None =>{}
-            Some(node)=>{
-                self.extend_node(node.index as usize);
+            Some(index)=>{
+                self.extend_node(index);
             }
         };  
     }
     pub fn activate_random_with_order(&mut self, order: usize) -> Result<(),()>{
-        if order-1<self.orders_indexed.len(){
+        if order<self.orders_indexed.len(){
             let free= self.orders_indexed[order].iter().filter(
-                |&x|match self.nodes[*x].bud_state{
+                |&x|match self.nodes[x.0].bud_state{
                     BudState::DormantBud => {true},
                     _ => {false},
                 });
-                let mut rng = rand::thread_rng();
-                let _index=free.choose(&mut rng);
+                let _index=free.choose(&mut self.rng).copied();
                 match _index{
                     None => {return Err(());}
                     Some(index)=>{
-                        return self.activate(*index as usize)
+                        let result = self.activate(index);
+                        self.debug_validate("activate_random_with_order");
+                        return result
                     }
                 };           
         }
@@ -637,12 +763,13 @@ impl Tree{
                 BudState::DormantBud => {true},
                 _ => {false},
             });
-        let mut rng = rand::thread_rng();
-        let _node=free.choose(&mut rng);
+        let _node=free.choose(&mut self.rng).map(|node| node.index);
         match _node{
             None =>{return Err(());}
-            Some(node)=>{
-                return self.activate(node.index as usize);
+            Some(index)=>{
+                let result = self.activate(index);
+                self.debug_validate("branch_random");
+                return result;
             }
         };  
     }
@@ -650,35 +777,46 @@ impl Tree{
         self.nodes.len()
     }
     pub fn update_transformations(&mut self, divergence_angle: f32,branching_angle: f32,segment_length: f32,initial_width: f32,order_width_influence: f32){
+        let transformations = self.node_transformations(divergence_angle, branching_angle, segment_length, initial_width, order_width_influence);
+        for (node,transformation) in self.nodes.iter_mut().zip(transformations){
+            node.transformation=transformation;
+        }
+    }
+    //transformation of every node relative to the tree, node.transformation is left unchanged
+    pub fn node_transformations(&self, divergence_angle: f32,branching_angle: f32,segment_length: f32,initial_width: f32,order_width_influence: f32) -> Vec<Mat4>{
+        if self.measured{
+            return self.nodes.iter().map(|node| measured_transformation(node)).collect();
+        }
+        let mut transformations = vec![Mat4::identity(); self.get_size()];
         let mut distance = segment_length*self.segments_amount as f32;
-        self.nodes[0].transformation=Mat4::identity();
         for i in 0..self.get_size(){
             
-            let base_transformation = self.nodes[i].transformation;
+            let base_transformation = transformations[i];
             let main_child = self.nodes[i].main_child;
             let secondary_child = self.nodes[i].secondary_child;
             let translation=Mat4::from_translation(Vector3 { x: 0., y: distance, z: 0. });
             
-            if main_child!=-1{
-                self.nodes[main_child as usize].transformation=
+            if let Some(main_child) = main_child{
+                transformations[main_child.0]=
                 base_transformation*translation;
             }
-            if secondary_child!=-1{
+            if let Some(secondary_child) = secondary_child{
                 let s = initial_width/((self.nodes[i].initial_order as f32)*order_width_influence+1.)*0.8;
                 let mut distance= segment_length*(self.nodes[i].segments.len()) as f32;
-                if main_child!=-1{distance+=segment_length;}
+                if main_child.is_some(){distance+=segment_length;}
                 let translation=Mat4::from_translation(Vector3 { x: 0., y: distance, z: 0. });
-                self.nodes[secondary_child as usize].transformation=
+                transformations[secondary_child.0]=
                 //Mat4::from_angle_z(radians(angle))*Mat4::from_angle_y(radians(divergence_angle*rng.gen_range(0..30) as f32))*base_transformation;
-                base_transformation*translation*Mat4::from_angle_y(radians(divergence_angle*self.nodes[secondary_child as usize].index as f32))*
+                base_transformation*translation*Mat4::from_angle_y(radians(divergence_angle*self[secondary_child].index.0 as f32))*
                 Mat4::from_translation(vec3(-s,0.,0.))*
                 Mat4::from_angle_z(radians(branching_angle));
             }
         }
         for i in 0..self.get_size(){
             let s = initial_width/((self.nodes[i].initial_order as f32)*order_width_influence+1.);
-            self.nodes[i].transformation=self.nodes[i].transformation*Mat4::from_nonuniform_scale(s,1.,s);
+            transformations[i]=transformations[i]*Mat4::from_nonuniform_scale(s,1.,s);
         }
+        transformations
     }
     pub fn update_tree_ref(tree:&Tree, new_tree: &mut Tree){
         let old_tree = tree;
@@ -687,7 +825,7 @@ impl Tree{
             match node.bud_state{
                 BudState::DormantBud => {
                     node.gain_flow(&old_tree.nodes[i]);
-                    if old_tree.nodes[node.parent as usize].activate_child(){
+                    if node.parent.map_or(false, |parent| old_tree[parent].activate_child()){
                         node.bud_state=BudState::ActiveBud;
                     }
                 },
@@ -698,7 +836,68 @@ impl Tree{
                     node.gain_flow(&old_tree.nodes[i]);
                 },
                 BudState::BranchingSegment =>{
-                    node.move_flow(&old_tree.nodes[i],&old_tree.nodes[node.main_child as usize],&old_tree.nodes[node.secondary_child as usize]);
+                    let main_child = &old_tree[node.main_child.expect("branching segment without main child")];
+                    let secondary_child = &old_tree[node.secondary_child.expect("branching segment without secondary child")];
+                    node.move_flow(&old_tree.nodes[i],main_child,secondary_child);
+                }
+            }
+        }
+        new_tree.time=old_tree.time+old_tree.settings.dt;
+        new_tree.apply_treatments();
+    }
+    pub fn data_mut(&mut self, compartment: Compartment) -> &mut Data{
+        let node = &mut self[compartment.node];
+        match compartment.segment{
+            None => &mut node.data,
+            Some(segment) => &mut node.segments[segment].data,
+        }
+    }
+    //compartments on the path from the node towards the base, in the same order as main_stem_values
+    pub fn compartments_below(&self, node_index: NodeId, amount: usize) -> Vec<Compartment>{
+        let mut result = vec![];
+        for index in self.path_to_root(node_index){
+            if result.len()>=amount{
+                break;
+            }
+            result.push(Compartment{node: index, segment: None});
+            for segment in (0..self[index].segments.len()).rev(){
+                result.push(Compartment{node: index, segment: Some(segment)});
+            }
+        }
+        result.truncate(amount);
+        result
+    }
+    pub fn add_treatment(&mut self, treatment: Treatment){
+        self.treatments.push(treatment);
+        self.apply_treatments();
+    }
+    //sets transport and external auxin of every compartment according to treatments active at current time
+    pub fn apply_treatments(&mut self){
+        if self.treatments.is_empty(){
+            return;
+        }
+        for node in &mut self.nodes{
+            node.data.transport=1.;
+            node.data.external_auxin=0.;
+            for segment in &mut node.segments{
+                segment.data.transport=1.;
+                segment.data.external_auxin=0.;
+            }
+        }
+        let time = self.time;
+        let treatments = self.treatments.clone();
+        for treatment in treatments.iter().filter(|t| t.is_active(time)){
+            match &treatment.kind{
+                TreatmentKind::AuxinSource { node, rate } => {
+                    self.data_mut(Compartment{node: *node, segment: None}).external_auxin+=rate;
+                }
+                TreatmentKind::PinInhibition { compartments, strength } => {
+                    for compartment in compartments{
+                        self.data_mut(*compartment).transport*=1.-strength.clamp(0., 1.);
+                    }
+                }
+                TreatmentKind::Girdling { compartment } => {
+                    self.data_mut(*compartment).transport=0.;
                 }
             }
         }
@@ -721,11 +920,17 @@ impl Tree{
 
     
     pub fn calculate_static_distribution(_tree: &Tree,precision: f32) -> Tree{
+        Tree::steady_state(_tree,precision,None).0
+    }
+
+    //transport iterations in blocks of 100 until the summed difference of a block is below precision,
+    //or until max_iterations when given
+    pub fn steady_state(_tree: &Tree,precision: f32,max_iterations: Option<usize>) -> (Tree,Convergence){
         let mut difference =100.;
         let mut i = 0;
         let mut old_tree = (*_tree).clone();
-        let mut new_tree = (*_tree).clone();
-        while difference>precision{
+        let mut new_tree;
+        while difference>precision && max_iterations.map_or(true, |max| i<max){
             difference=0.;
             for _ in 0..(100){
                 new_tree=Tree::update_tree_copy(&old_tree);
@@ -734,35 +939,50 @@ impl Tree{
                 i+=1;
             }
         }
-        old_tree
+        (old_tree,Convergence { iterations: i, difference, converged: difference<=precision })
     }
 
     pub fn main_stem_values(&self) -> Vec<(f32,f32)>{
         let mut result =vec![];
 
-        let mut index = self.get_oringal_tip_index();
-        while index !=-1{
-            result.push((self.nodes[index as usize].data.auxin,self.nodes[index as usize].data.pin));
-            for segment in self.nodes[index as usize].segments.iter().rev(){
+        for index in self.path_to_root(self.get_oringal_tip_index()){
+            result.push((self[index].data.auxin,self[index].data.pin));
+            for segment in self[index].segments.iter().rev(){
                 result.push((segment.data.auxin,segment.data.pin));
 
             }
-            index=self.nodes[index as usize].parent;
         }
         result
     }
-    pub fn recalculate_initial_order(&mut self){
-        self.recalculate_initial_order_recursive(0,0);
+    //internode of every value of main_stem_values, counted from the tip with the compartments of the tip node in the first internode
+    pub fn main_stem_internodes(&self) -> Vec<(i32,f32,f32)>{
+        let segments_amount = self.settings.segments_amount.max(1);
+        let tip_segments = self[self.get_oringal_tip_index()].segments.len() as i32;
+        self.main_stem_values().into_iter().enumerate().map(|(i,(auxin,pin))|{
+            ((i as i32+segments_amount-tip_segments-1)/segments_amount+1,auxin,pin)
+        }).collect()
     }
-
-
-    pub fn recalculate_initial_order_recursive(&mut self,index: i32,order:i32){
-        self.nodes[index as usize].initial_order=order;
-        if self.nodes[index as usize].main_child>0{
-            self.recalculate_initial_order_recursive(self.nodes[index as usize].main_child,order);
+    //initial order is the number of secondary links between the node and the root, it is not changed by decapitation
+    pub fn recalculate_initial_order(&mut self){
+        let nodes: Vec<NodeId> = self.preorder().collect();
+        for index in nodes{
+            self[index].initial_order = match self[index].parent{
+                None => 0,
+                Some(parent) if self[parent].main_child==Some(index) => self[parent].initial_order,
+                Some(parent) => self[parent].initial_order+1,
+            };
         }
-        if self.nodes[index as usize].secondary_child>0{
-            self.recalculate_initial_order_recursive(self.nodes[index as usize].secondary_child,order+1);
+    }
+}
+//frame at the base of the first measured compartment of the node with y along the compartment
+fn measured_transformation(node: &Node) -> Mat4{
+    let cylinder = node.segments.first().and_then(|s| s.cylinder).or(node.cylinder);
+    match cylinder{
+        None => Mat4::identity(),
+        Some(cylinder) => {
+            let start = Vec3::from(cylinder.start);
+            let direction = Vec3::from(cylinder.end)-start;
+            Mat4::from_translation(start)*frame(direction)
         }
     }
 }
@@ -771,237 +991,3 @@ impl std::fmt::Debug for Tree {
         write!(f,"{:#?}", self.nodes)
     }
 }
-
-
-
-pub fn random_growth(tree: &mut Tree, prob:f32){
-    if rand::random::<f32>()>prob{
-        tree.branch_random();
-        tree.extend_random();
-    }
-    else{
-        tree.extend_random();
-    }
-
-}
-
-pub fn wild_type_week_11(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=6;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..10{
-        tree.extend_main();
-    }
-    for i in 0..25{
-        tree.activate_random_with_order(1);
-        let do_it :f32 =random();
-        if do_it<0.45 {tree.activate_random_with_order(1);}
-        for _ in 0..3*i{
-        tree.extend_random_with_order(1);
-        }
-        // if i==10{
-        //     settings.segments_amount+=1;
-        //     tree.new_settings(settings.clone())
-        // }
-        for _ in 0..5{
-        tree.extend_main();
-        }
-    }
-
-    tree
-}
-pub fn wild_decapitated_week_11(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=6;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..10{
-        tree.extend_main();
-    }
-    for i in 0..25{
-        if i == 8{
-            tree.decapitate_main();
-            settings.segments_amount+=1;
-            tree.new_settings(settings.clone())
-            //tree.decapitate_random_order_1(5);
-            //return tree;
-        }
-        if i==20{
-            tree.decapitate_lowest_branches(5, 1, 3);
-        }
-        tree.activate_random_with_order(1);
-        let do_it :f32 =random();
-        if do_it<0.45 {tree.activate_random_with_order(1);}
-
-        //let order_extension = if i<8 {3*(i)+12} else {3*i-8};
-        let order_extension = if i<20 {(3*i)} else {(2.5*i as f32).floor() as i32};
-        //let mul=3;
-        for _ in 0..order_extension{
-        tree.extend_random_with_order(1);
-        }
-        // if i==10{
-        //     settings.segments_amount+=1;
-        //     tree.new_settings(settings.clone())
-        // }
-        for _ in 0..5{
-        tree.extend_main();
-        }
-    }
-    tree.recalculate_initial_order();
-
-    tree
-}
-pub fn wild_type_week_11_2(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=5;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..(settings.segments_amount)*2{
-        tree.extend_main();
-    }
-    for i in 0..20{
-        tree.activate_random_with_order(1);
-        for _ in 0..(((settings.segments_amount as f32)/2.)*i as f32).floor() as i32{
-        tree.extend_random_with_order(1);
-        }
-        for _ in 0..(settings.segments_amount-1){
-        tree.extend_main();
-        }
-    }
-
-    tree
-}
-pub fn rnai60(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=4;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..(settings.segments_amount-1)*2{
-        tree.extend_main();
-    }
-    for i in 0..25{
-        tree.activate_random_with_order(1);
-        if i>4{
-            let do_it :f32 =random();
-            if do_it<0.5 {tree.activate_random_with_order(2);}
-            for _ in 0..(0.5*i as f32).ceil() as i32{
-                tree.extend_random_with_order(2);
-            }
-        }
-        // if i==10{
-        //     settings.segments_amount+=1;
-        //     tree.new_settings(settings.clone())
-        // }
-        let times = if random::<f32>()<0.6{ 3 } else{2};
-        for _ in 0..times*i{
-        tree.extend_random_with_order(1);
-        }
-        for _ in 0..(settings.segments_amount-1){
-        tree.extend_main();
-        }
-    }
-    tree
-}
-pub fn rnai60_decapitated(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=4;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..(settings.segments_amount-1)*2{
-        tree.extend_main();
-    }
-    for i in 0..25{
-        tree.activate_random_with_order(1);
-        if i>4{
-            let do_it :f32 =random();
-            if do_it<0.5 {tree.activate_random_with_order(2);}
-            for _ in 0..(0.5*i as f32).ceil() as i32{
-                tree.extend_random_with_order(2);
-            }
-        }
-        if i == 8{
-            tree.decapitate_main();
-            //settings.segments_amount+=1;
-            //tree.new_settings(settings.clone())
-            //tree.decapitate_random_order_1(5);
-            //return tree;
-        }
-        if i==20{
-            tree.decapitate_lowest_branches(5, 1, 3);
-        }
-        // if i==10{
-        //     settings.segments_amount+=1;
-        //     tree.new_settings(settings.clone())
-        // }
-        let times = if random::<f32>()<0.6{ 3 } else{2};
-        for _ in 0..times*i{
-        tree.extend_random_with_order(1);
-        }
-        for _ in 0..(settings.segments_amount-1){
-        tree.extend_main();
-        }
-    }
-    tree.recalculate_initial_order();
-    tree
-}
-pub fn kanttarelli_week_11(_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=4;
-    let mut tree = Tree::new(&settings);
-    for _ in 0..4{
-        tree.extend_main();
-    }
-    for i in 0..20{
-        tree.activate_random_with_order(1);
-        if i>4{
-            for _ in 0..1*i{
-                tree.extend_random_with_order(2);
-            }
-        }
-        for _ in 0..2*i{
-        tree.extend_random_with_order(1);
-        }
-        for _ in 0..2{
-        tree.extend_main();
-        }
-    }
-    tree
-}
-pub fn random_tree(nodes:i32,initial_size:i32,prob:f32,settings: &Settings) -> Tree{
-    let mut tree = Tree::new(&settings);
-    for  _ in 0..initial_size{    tree.extend_main();}
-    //tree.branch_random();
-    //print!("{:#?}",tree);
-    for _ in 0..nodes{
-        random_growth(&mut tree, prob);
-    }
-    for _ in 0..nodes/2{
-        tree.extend_random();
-    }
-    let branching_angle = PI*0.2;
-    let divergence_angle = PI*(137./180.);
-    let segment_length = 2.;
-
-    //tree.update_transformations(divergence_angle,branching_angle,segment_length);
-    tree
-}
-pub fn pole(size:i32,_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=size+2;
-    random_tree(0, size, 0.,&settings)
-}
-pub fn pole_internode_size(size:i32,internode_size:i32,_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=internode_size;
-    random_tree(0, size, 0.,&settings)
-}
-pub fn pole_internode_size_active(size:i32,internode_size:i32,_settings: &Settings) -> Tree{
-    let mut settings = _settings.clone();
-    settings.segments_amount=internode_size;
-    let mut tree=random_tree(0, size, 0.,&settings);
-    for i in 1..tree.get_size(){
-        if let BudState::DormantBud = tree.nodes[i].bud_state {
-        match tree.nodes[tree.nodes[i].parent as usize].bud_state{
-            BudState::ActiveBud => {},
-            _=> {tree.activate(i);}
-            }
-        }
-    }
-    tree
-}
@@ -0,0 +1,220 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use crate::vec_tree::*;
+use crate::geometry::*;
+use crate::mesh::*;
+
+//VTK PolyData (.vtp) files for ParaView, one line or tube per compartment
+//values of the compartment are written both as point and cell data, a .pvd collection makes a time series
+
+#[derive(Clone,Copy,Debug,PartialEq)]
+pub enum VtkStyle{
+    //two points per compartment with a radius array for the Tube filter
+    Lines,
+    //the tube mesh of mesh.rs
+    Tubes,
+}
+
+enum Field{
+    Float(fn(&Tree,Compartment,&Data) -> f32),
+    Int(fn(&Tree,Compartment,&Data) -> i32),
+}
+
+fn bud_state_code(state: &BudState) -> i32{
+    match state{
+        BudState::DormantBud => 0,
+        BudState::ActiveBud => 1,
+        BudState::BranchingSegment => 2,
+        BudState::DecapitatedSegment => 3,
+    }
+}
+
+//bud_state: 0 dormant, 1 active, 2 branching, 3 decapitated; segment is -1 for the node compartment
+const FIELDS: [(&str,Field); 12] = [
+    ("auxin",Field::Float(|_,_,d| d.auxin)),
+    ("pin",Field::Float(|_,_,d| d.pin)),
+    ("auxin_flow",Field::Float(|_,_,d| d.auxin_flow)),
+    ("strigolactin",Field::Float(|_,_,d| d.strigolactin)),
+    ("transport",Field::Float(|_,_,d| d.transport)),
+    ("external_auxin",Field::Float(|_,_,d| d.external_auxin)),
+    ("order",Field::Int(|t,c,_| t[c.node].order)),
+    ("initial_order",Field::Int(|t,c,_| t[c.node].initial_order)),
+    ("bud_state",Field::Int(|t,c,_| bud_state_code(&t[c.node].bud_state))),
+    ("age",Field::Int(|_,_,d| d.age)),
+    ("node",Field::Int(|_,c,_| c.node.0 as i32)),
+    ("segment",Field::Int(|_,c,_| c.segment.map_or(-1, |s| s as i32))),
+];
+
+fn data_of(tree: &Tree, compartment: Compartment) -> &Data{
+    match compartment.segment{
+        Some(segment) => &tree[compartment.node].segments[segment].data,
+        None => &tree[compartment.node].data,
+    }
+}
+
+fn data_array(text: &mut String, name: &str, kind: &str, components: usize, values: impl Iterator<Item=String>){
+    let _ = write!(text, "        <DataArray type=\"{kind}\" Name=\"{name}\" NumberOfComponents=\"{components}\" format=\"ascii\">\n          ");
+    let values: Vec<String> = values.collect();
+    *text += &values.join(" ");
+    *text += "\n        </DataArray>\n";
+}
+
+fn field_arrays(text: &mut String, tree: &Tree, compartments: &[Compartment]){
+    for (name,field) in &FIELDS{
+        match field{
+            Field::Float(value) => data_array(text, name, "Float32", 1, compartments.iter().map(|&c| value(tree,c,data_of(tree,c)).to_string())),
+            Field::Int(value) => data_array(text, name, "Int32", 1, compartments.iter().map(|&c| value(tree,c,data_of(tree,c)).to_string())),
+        }
+    }
+}
+
+pub fn write_vtp(tree: &Tree, params: &MeshParams, style: VtkStyle) -> String{
+    //points with their compartments and cells as (compartment, point indices)
+    let mut points: Vec<[f32;3]> = vec![];
+    let mut point_compartments: Vec<Compartment> = vec![];
+    let mut radii: Vec<f32> = vec![];
+    let mut cells: Vec<(Compartment,Vec<u32>)> = vec![];
+    match style{
+        VtkStyle::Lines => {
+            let geometry = TreeGeometry::new(tree, &params.geometry);
+            for compartment in geometry.compartments().filter(|c| params.includes(MeshPart::of(tree, c.compartment))){
+                let first = points.len() as u32;
+                let (start,end): ([f32;3],[f32;3]) = (compartment.start.into(),compartment.end.into());
+                points.extend([start,end]);
+                point_compartments.extend([compartment.compartment; 2]);
+                radii.extend([compartment.width/2.; 2]);
+                cells.push((compartment.compartment,vec![first,first+1]));
+            }
+        }
+        VtkStyle::Tubes => {
+            let mesh = TreeMesh::new(tree, params);
+            points = mesh.positions;
+            point_compartments = mesh.compartments;
+            cells = mesh.triangles.iter().map(|t| (point_compartments[t[0] as usize],t.to_vec())).collect();
+        }
+    }
+    let (lines,polys) = match style{
+        VtkStyle::Lines => (cells.len(),0),
+        VtkStyle::Tubes => (0,cells.len()),
+    };
+    let cell_compartments: Vec<Compartment> = cells.iter().map(|(c,_)| *c).collect();
+
+    let mut text = String::from("<?xml version=\"1.0\"?>\n<VTKFile type=\"PolyData\" version=\"0.1\" byte_order=\"LittleEndian\">\n  <PolyData>\n");
+    let _ = writeln!(text, "    <Piece NumberOfPoints=\"{}\" NumberOfVerts=\"0\" NumberOfLines=\"{lines}\" NumberOfStrips=\"0\" NumberOfPolys=\"{polys}\">",points.len());
+    text += "      <PointData Scalars=\"auxin\">\n";
+    field_arrays(&mut text, tree, &point_compartments);
+    if style==VtkStyle::Lines{
+        data_array(&mut text, "radius", "Float32", 1, radii.iter().map(|r| r.to_string()));
+    }
+    text += "      </PointData>\n      <CellData Scalars=\"auxin\">\n";
+    field_arrays(&mut text, tree, &cell_compartments);
+    text += "      </CellData>\n      <Points>\n";
+    data_array(&mut text, "Points", "Float32", 3, points.iter().map(|p| format!("{} {} {}",p[0],p[1],p[2])));
+    text += "      </Points>\n";
+    let tag = if style==VtkStyle::Lines {"Lines"} else {"Polys"};
+    let _ = writeln!(text, "      <{tag}>");
+    data_array(&mut text, "connectivity", "Int32", 1, cells.iter().flat_map(|(_,c)| c.iter().map(|i| i.to_string())));
+    let offsets = cells.iter().scan(0,|offset,(_,c)| {*offset+=c.len(); Some(offset.to_string())});
+    data_array(&mut text, "offsets", "Int32", 1, offsets);
+    let _ = writeln!(text, "      </{tag}>");
+    text += "    </Piece>\n  </PolyData>\n</VTKFile>\n";
+    text
+}
+
+pub fn save_vtp(path: &Path, tree: &Tree, params: &MeshParams, style: VtkStyle) -> Result<(),String>{
+    std::fs::write(path, write_vtp(tree, params, style)).map_err(|e| format!("{}: {e}",path.display()))
+}
+
+//ParaView collection of .vtp files with their times
+#[derive(Clone,Debug,Default)]
+pub struct PvdCollection{
+    datasets: Vec<(f64,String)>,
+}
+impl PvdCollection{
+    pub fn new() -> PvdCollection{
+        PvdCollection::default()
+    }
+    //file is relative to the .pvd file
+    pub fn add(&mut self, time: f64, file: &str){
+        self.datasets.push((time,file.to_string()));
+    }
+    pub fn write(&self) -> String{
+        let mut text = String::from("<?xml version=\"1.0\"?>\n<VTKFile type=\"Collection\" version=\"0.1\" byte_order=\"LittleEndian\">\n  <Collection>\n");
+        for (time,file) in &self.datasets{
+            let _ = writeln!(text, "    <DataSet timestep=\"{time}\" group=\"\" part=\"0\" file=\"{file}\"/>");
+        }
+        text += "  </Collection>\n</VTKFile>\n";
+        text
+    }
+    pub fn save(&self, path: &Path) -> Result<(),String>{
+        std::fs::write(path, self.write()).map_err(|e| format!("{}: {e}",path.display()))
+    }
+}
+
+//transport iterations from the tree written every `every` iterations as {name}_{iteration}.vtp with {name}.pvd in dir,
+//the timestep of a file is the simulated time of its tree, returns the tree after the last iteration
+pub fn save_vtk_series(dir: &Path, name: &str, tree: &Tree, iterations: usize, every: usize, params: &MeshParams, style: VtkStyle) -> Result<Tree,String>{
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {e}",dir.display()))?;
+    let mut collection = PvdCollection::new();
+    let mut tree = tree.clone();
+    let frame = |tree: &Tree, iteration: usize, collection: &mut PvdCollection| -> Result<(),String>{
+        let file = format!("{name}_{iteration:06}.vtp");
+        let path: PathBuf = dir.join(&file);
+        save_vtp(&path, tree, params, style)?;
+        collection.add(tree.time as f64, &file);
+        Ok(())
+    };
+    frame(&tree, 0, &mut collection)?;
+    for iteration in 1..=iterations{
+        tree = Tree::update_tree_copy(&tree);
+        if iteration%every.max(1)==0 || iteration==iterations{
+            frame(&tree, iteration, &mut collection)?;
+        }
+    }
+    collection.save(&dir.join(format!("{name}.pvd")))?;
+    Ok(tree)
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    #[test]
+    fn series_timesteps_are_tree_times(){
+        let mut settings = Settings::default();
+        settings.dt = 0.25;
+        let mut tree = Tree::with_seed(&settings, 1);
+        for _ in 0..6{
+            tree.extend_main();
+        }
+        tree.time = 2.;
+        let dir = std::env::temp_dir().join(format!("tree_d_vtk_{}",std::process::id()));
+        let last = save_vtk_series(&dir, "run", &tree, 5, 2, &MeshParams::default(), VtkStyle::Lines).unwrap();
+        let pvd = std::fs::read_to_string(dir.join("run.pvd")).unwrap();
+        let frames: Vec<bool> = ["run_000000.vtp","run_000002.vtp","run_000004.vtp","run_000005.vtp"].iter().map(|f| dir.join(f).exists()).collect();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(frames,vec![true; 4]);
+        assert_eq!(last.time,3.25);
+        let timesteps: Vec<&str> = pvd.lines().filter_map(|l| l.split("timestep=\"").nth(1)).map(|l| l.split('"').next().unwrap()).collect();
+        assert_eq!(timesteps,vec!["2","2.5","3","3.25"]);
+    }
+
+    #[test]
+    fn line_and_tube_counts(){
+        let mut tree = Tree::with_seed(&Settings::default(), 1);
+        for _ in 0..12{
+            tree.extend_main();
+        }
+        let params = MeshParams::default();
+        //bud compartments are hidden by default
+        let compartments = TreeGeometry::new(&tree, &params.geometry).compartments().filter(|c| params.includes(MeshPart::of(&tree, c.compartment))).count();
+        assert!(compartments>0);
+        let lines = write_vtp(&tree, &params, VtkStyle::Lines);
+        assert!(lines.contains(&format!("NumberOfPoints=\"{}\" NumberOfVerts=\"0\" NumberOfLines=\"{compartments}\"",2*compartments)),"{lines}");
+        let mesh = TreeMesh::new(&tree, &params);
+        let tubes = write_vtp(&tree, &params, VtkStyle::Tubes);
+        assert!(tubes.contains(&format!("NumberOfPoints=\"{}\"",mesh.positions.len())));
+        assert!(tubes.contains(&format!("NumberOfPolys=\"{}\"",mesh.triangles.len())));
+    }
+}